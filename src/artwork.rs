@@ -0,0 +1,183 @@
+use crate::card_size::CardSize;
+use crate::deck::{DehydratedCard, PickedCard};
+use crate::mapping::CardMap;
+use crate::pdf_calc::calculate_dpi;
+use printpdf::image_crate::image_dimensions;
+use std::fs::read_dir;
+use std::path::Path;
+
+/// File extensions picked up from an artwork directory
+const ARTWORK_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+/// Stem suffix marking an image in an artwork directory as the back face of a card
+const BACK_SUFFIX: &str = "_back";
+/// Card shape, width over height
+const CARD_ASPECT: f32 = 63.0 / 88.0;
+/// Below this an override is reported as likely to print blurry
+const MINIMUM_DPI: f32 = 200.0;
+
+/// Local images used in place of the Scryfall ones, with fronts and backs kept apart
+#[derive(Debug, Clone, Default)]
+pub struct Artwork {
+    fronts: CardMap,
+    backs: CardMap,
+}
+
+impl Artwork {
+    pub fn new() -> Artwork {
+        Artwork::default()
+    }
+
+    /// Add every image in a directory. Files are named after the card, as `Lightning Bolt.png`,
+    /// or after the printing, as `m21_176.jpg`. A `_back` suffix, as in `m21_176_back.jpg`, makes
+    /// the image the back of the card.
+    pub fn with_directory(mut self, directory: &Path) -> Result<Self, String> {
+        let entries = read_dir(directory)
+            .map_err(|e| format!("Could not read artwork directory {:?}: {}", directory, e))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|e| format!("Could not read artwork directory {:?}: {}", directory, e))?
+                .path();
+            let extension = path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            if !ARTWORK_EXTENSIONS.contains(&extension.as_str()) {
+                continue;
+            }
+            let stem = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let (card, map) = match stem.strip_suffix(BACK_SUFFIX) {
+                Some(card) => (card.to_string(), &mut self.backs),
+                None => (stem, &mut self.fronts),
+            };
+            match printing_of(&card) {
+                Some((set, number)) => map.insert_printing(set, number, path.clone()),
+                None => map.insert_name(&card, path.clone()),
+            }
+        }
+        Ok(self)
+    }
+
+    /// Add the entries of a front or back mapping file, replacing any from the directory
+    pub fn with_mapping(mut self, mapping: &Path, back: bool) -> Result<Self, String> {
+        let map = CardMap::load(mapping)?;
+        match back {
+            true => self.backs.extend(map),
+            false => self.fronts.extend(map),
+        }
+        Ok(self)
+    }
+
+    pub fn front(&self, card: &PickedCard) -> Option<&Path> {
+        self.fronts.get(card)
+    }
+
+    pub fn back(&self, card: &PickedCard) -> Option<&Path> {
+        self.backs.get(card)
+    }
+
+    /// True if a card from a deck file has a front of its own, so it can be printed even when
+    /// Scryfall has never heard of it
+    pub fn has_front(&self, card: &DehydratedCard) -> bool {
+        let printing = card
+            .set_code
+            .as_deref()
+            .zip(card.collector_number.as_deref());
+        self.fronts.lookup(&card.name, printing).is_some()
+    }
+
+    /// Check every image can be read. Images that are not card shaped or too small to print
+    /// sharply are reported, as they will be stretched or blurry at 63x88mm.
+    pub fn validate(&self) -> Result<(), String> {
+        for file in self.fronts.files().chain(self.backs.files()) {
+            let (width, height) = image_dimensions(file)
+                .map_err(|e| format!("Could not read artwork {:?}: {}", file, e))?;
+            let aspect = width as f32 / height as f32;
+            if (aspect - CARD_ASPECT).abs() > 0.02 {
+                println!(
+                    "Warning: artwork {:?} is {}x{}, which is not card shaped",
+                    file, width, height
+                );
+            }
+            let dpi = calculate_dpi(width as usize, height as usize, &CardSize::STANDARD);
+            if dpi < MINIMUM_DPI {
+                println!(
+                    "Warning: artwork {:?} is only {:.0} DPI at card size",
+                    file, dpi
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The set code and collector number of a file stem such as `m21_176` or `war_97★`, or None if
+/// the stem is a card name that happens to have an underscore in it
+fn printing_of(stem: &str) -> Option<(&str, &str)> {
+    let (set, number) = stem.split_once('_')?;
+    let set_code = (2..=6).contains(&set.len()) && set.chars().all(|c| c.is_ascii_alphanumeric());
+    let collector_number = number.starts_with(|c: char| c.is_ascii_digit())
+        && number
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '★' || c == '-');
+    (set_code && collector_number).then_some((set, number))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::artwork::Artwork;
+    use crate::deck::{DehydratedCard, PickedCard};
+    use std::fs::{create_dir_all, remove_dir_all, File};
+    use std::path::Path;
+
+    #[test]
+    pub fn test_artwork_directory() {
+        let directory = Path::new("Test_Temp_Artwork");
+        create_dir_all(directory).unwrap();
+        for file in [
+            "Lightning Bolt.png",
+            "m21_176.jpg",
+            "isd_51_back.jpg",
+            "Kaya_Ghost Assassin.png",
+            "notes.txt",
+        ] {
+            File::create(directory.join(file)).unwrap();
+        }
+        let artwork = Artwork::new().with_directory(directory).unwrap();
+        remove_dir_all(directory).unwrap();
+
+        let card = |name: &str, set_code: &str, collector_number: &str| PickedCard {
+            quantity: 1,
+            set_code: set_code.to_string(),
+            collector_number: collector_number.to_string(),
+            name: name.to_string(),
+            double_sided: false,
+            ..Default::default()
+        };
+        let bolt = card("Lightning Bolt", "m10", "146");
+        assert_eq!(
+            artwork.front(&bolt),
+            Some(directory.join("Lightning Bolt.png").as_path())
+        );
+        assert_eq!(artwork.back(&bolt), None);
+        let dreadmaw = card("Colossal Dreadmaw", "m21", "176");
+        assert!(artwork.front(&dreadmaw).is_some());
+        let delver = card("Delver of Secrets // Insectile Aberration", "isd", "51");
+        assert_eq!(artwork.front(&delver), None);
+        assert!(artwork.back(&delver).is_some());
+        let kaya = card("Kaya_Ghost Assassin", "cn2", "75");
+        assert!(artwork.front(&kaya).is_some());
+
+        let custom = DehydratedCard {
+            quantity: 1,
+            set_code: None,
+            collector_number: None,
+            name: "Lightning Bolt".to_string(),
+            flip_name: None,
+            double_sided: None,
+        };
+        assert!(artwork.has_front(&custom));
+    }
+}
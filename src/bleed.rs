@@ -0,0 +1,111 @@
+use printpdf::image_crate::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+/// How the bleed around a card image is filled in
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum BleedFill {
+    /// Repeat the outermost pixels of the image
+    #[default]
+    Edge,
+    /// Use the average colour of the card's border
+    Border,
+}
+
+/// Grow an image by `bleed_px` on every side, filling the new area so that a cut slightly off
+/// the trim line still lands on card coloured pixels.
+pub fn add_bleed(image: &DynamicImage, bleed_px: u32, fill: BleedFill) -> DynamicImage {
+    if bleed_px == 0 {
+        return image.clone();
+    }
+    let source = image.to_rgb8();
+    let (width, height) = source.dimensions();
+    let mut output = RgbImage::new(width + bleed_px * 2, height + bleed_px * 2);
+    match fill {
+        BleedFill::Edge => {
+            for (x, y, pixel) in output.enumerate_pixels_mut() {
+                let source_x = x.saturating_sub(bleed_px).min(width - 1);
+                let source_y = y.saturating_sub(bleed_px).min(height - 1);
+                *pixel = *source.get_pixel(source_x, source_y);
+            }
+        }
+        BleedFill::Border => {
+            let colour = border_colour(image);
+            for pixel in output.pixels_mut() {
+                *pixel = colour;
+            }
+            for (x, y, pixel) in source.enumerate_pixels() {
+                output.put_pixel(x + bleed_px, y + bleed_px, *pixel);
+            }
+        }
+    }
+    DynamicImage::ImageRgb8(output)
+}
+
+/// Average colour of a thin strip just inside each edge of the card. The strips stay away from
+/// the corners, which are rounded and usually white or transparent in scans.
+pub fn border_colour(image: &DynamicImage) -> Rgb<u8> {
+    let (width, height) = image.dimensions();
+    let inset_x = (width / 50).max(1).min(width - 1);
+    let inset_y = (height / 50).max(1).min(height - 1);
+    let mut total = [0u64; 3];
+    let mut count = 0u64;
+    let mut sample = |x: u32, y: u32| {
+        let pixel = image.get_pixel(x, y);
+        for (channel, sum) in total.iter_mut().enumerate() {
+            *sum += pixel[channel] as u64;
+        }
+        count += 1;
+    };
+    for x in (width / 10)..(width - width / 10) {
+        sample(x, inset_y);
+        sample(x, height - 1 - inset_y);
+    }
+    for y in (height / 10)..(height - height / 10) {
+        sample(inset_x, y);
+        sample(width - 1 - inset_x, y);
+    }
+    let count = count.max(1);
+    Rgb([
+        (total[0] / count) as u8,
+        (total[1] / count) as u8,
+        (total[2] / count) as u8,
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bleed::{add_bleed, BleedFill};
+    use printpdf::image_crate::{DynamicImage, Rgb, RgbImage};
+
+    /// A black bordered white card, 20x20 pixels with a 4 pixel border and a red pixel on the left
+    /// edge at 0,10
+    fn bordered_card() -> DynamicImage {
+        let mut image = RgbImage::from_pixel(20, 20, Rgb([0, 0, 0]));
+        for x in 4..16 {
+            for y in 4..16 {
+                image.put_pixel(x, y, Rgb([255, 255, 255]));
+            }
+        }
+        image.put_pixel(0, 10, Rgb([255, 0, 0]));
+        DynamicImage::ImageRgb8(image)
+    }
+
+    #[test]
+    pub fn test_edge_bleed() {
+        let image = add_bleed(&bordered_card(), 3, BleedFill::Edge).to_rgb8();
+        assert_eq!(image.dimensions(), (26, 26));
+        // The red edge pixel is smeared out into the bleed
+        assert_eq!(image.get_pixel(0, 13), &Rgb([255, 0, 0]));
+        assert_eq!(image.get_pixel(3, 13), &Rgb([255, 0, 0]));
+        assert_eq!(image.get_pixel(0, 0), &Rgb([0, 0, 0]));
+        assert_eq!(image.get_pixel(13, 13), &Rgb([255, 255, 255]));
+    }
+
+    #[test]
+    pub fn test_border_bleed() {
+        let image = add_bleed(&bordered_card(), 3, BleedFill::Border).to_rgb8();
+        assert_eq!(image.dimensions(), (26, 26));
+        assert_eq!(image.get_pixel(0, 13), &Rgb([0, 0, 0]));
+        assert_eq!(image.get_pixel(3, 13), &Rgb([255, 0, 0]));
+        assert_eq!(image.get_pixel(13, 13), &Rgb([255, 255, 255]));
+    }
+}
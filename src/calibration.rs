@@ -0,0 +1,138 @@
+use crate::layout::{Layout, Rect};
+use crate::pdf_writer::{Font, Layer, PdfWriter};
+use printpdf::utils::calculate_points_for_circle;
+use printpdf::{BuiltinFont, Line, Mm, PdfConformance, PdfMetadata, Point};
+use std::fs;
+use std::path::Path;
+
+/// Distance of the corner targets from the edges of the page
+const TARGET_INSET: Mm = Mm(25.0);
+/// How far the rulers reach either side of the centre of the page, in whole mm
+const RULER_REACH: i32 = 20;
+
+/// Write a two page calibration sheet. Printed double sided and held up to a light, the cross
+/// printed in the centre of the back shows on the rulers of the front how far the printer shifts
+/// the back page, in the units `--back-offset-x` and `--back-offset-y` take.
+pub fn write_calibration_sheet(layout: &Layout, path: &Path) -> Result<(), String> {
+    let metadata = PdfMetadata::new("Duplex calibration", 1, false, PdfConformance::default());
+    let mut doc = PdfWriter::new(metadata, layout.page_width(), layout.page_height());
+    let font = doc.add_builtin_font(BuiltinFont::Helvetica);
+    let front = doc.layer(0);
+    let back = doc.layer(1);
+
+    let width = layout.page_width();
+    let height = layout.page_height();
+    let centre = (width / 2.0, height / 2.0);
+    let corners = [
+        (TARGET_INSET, TARGET_INSET),
+        (width - TARGET_INSET, TARGET_INSET),
+        (TARGET_INSET, height - TARGET_INSET),
+        (width - TARGET_INSET, height - TARGET_INSET),
+    ];
+
+    for corner in corners {
+        draw_target(&front, corner, Mm(4.0));
+        draw_target(&back, back_point(layout, corner), Mm(6.0));
+    }
+    draw_rulers(layout, &front, &font, centre);
+    draw_cross(&back, back_point(layout, centre), Mm(10.0));
+
+    let duplex = layout.duplex();
+    let text = [
+        "Duplex calibration: print this sheet double sided and hold it up to a light.".to_string(),
+        "Read where the centre cross on the back lands on the rulers, and add the".to_string(),
+        "values to --back-offset-x and --back-offset-y. Keep them with --save-printer.".to_string(),
+        format!(
+            "Current settings: flip {}, rotate back {}, offset x {}mm, offset y {}mm",
+            duplex
+                .flip
+                .map_or("left to right".to_string(), |flip| format!("{:?}", flip)),
+            duplex.rotate_back,
+            duplex.back_offset_x,
+            duplex.back_offset_y
+        ),
+    ];
+    for (line, text) in text.iter().enumerate() {
+        front.use_text(
+            text.as_str(),
+            8.0,
+            TARGET_INSET + Mm(12.0),
+            height - Mm(10.0) - Mm(4.0) * line as f32,
+            &font,
+        );
+    }
+    let label = back_point(layout, (TARGET_INSET + Mm(12.0), height - Mm(10.0)));
+    back.use_text("Back", 8.0, label.0, label.1, &font);
+
+    fs::write(path, doc.save_to_bytes()?)
+        .map_err(|e| format!("Could not write calibration sheet {:?}: {}", path, e))
+}
+
+/// Where a point on the front page lands on the back page
+fn back_point(layout: &Layout, point: (Mm, Mm)) -> (Mm, Mm) {
+    let back = layout.back_of(&Rect::new(point.0, point.1, Mm(0.0), Mm(0.0)));
+    (back.x, back.y)
+}
+
+fn line(layer: &Layer, from: (Mm, Mm), to: (Mm, Mm)) {
+    layer.add_line(Line {
+        points: vec![
+            (Point::new(from.0, from.1), false),
+            (Point::new(to.0, to.1), false),
+        ],
+        is_closed: false,
+    });
+}
+
+fn draw_cross(layer: &Layer, (x, y): (Mm, Mm), size: Mm) {
+    line(layer, (x - size, y), (x + size, y));
+    line(layer, (x, y - size), (x, y + size));
+}
+
+/// A registration target: a circle with a cross through it
+fn draw_target(layer: &Layer, (x, y): (Mm, Mm), radius: Mm) {
+    layer.add_line(Line {
+        points: calculate_points_for_circle(radius, x, y),
+        is_closed: true,
+    });
+    draw_cross(layer, (x, y), radius + Mm(3.0));
+}
+
+/// Rulers running right and down from the centre of the page, one tick per mm
+fn draw_rulers(layout: &Layout, layer: &Layer, font: &Font, (x, y): (Mm, Mm)) {
+    // A shift of the back page shows up mirrored on the front, so the labels are flipped to read
+    // as the offset that corrects it
+    let portrait = layout.page_height().0 >= layout.page_width().0;
+    let duplex = layout.duplex();
+    let mirrored_x = duplex.mirrors_horizontally(portrait) != duplex.rotate_back;
+    let (sign_x, sign_y) = match mirrored_x {
+        true => (1, -1),
+        false => (-1, 1),
+    };
+    for mm in -RULER_REACH..=RULER_REACH {
+        let length = match mm {
+            mm if mm % 10 == 0 => Mm(6.0),
+            mm if mm % 5 == 0 => Mm(4.0),
+            _ => Mm(2.0),
+        };
+        let offset = Mm(mm as f32);
+        line(layer, (x + offset, y), (x + offset, y - length));
+        line(layer, (x, y + offset), (x - length, y + offset));
+        if mm % 5 == 0 && mm != 0 {
+            layer.use_text(
+                format!("{}", mm * sign_x),
+                5.0,
+                x + offset - Mm(1.0),
+                y - Mm(9.0),
+                font,
+            );
+            layer.use_text(
+                format!("{}", mm * sign_y),
+                5.0,
+                x - Mm(12.0),
+                y + offset - Mm(0.6),
+                font,
+            );
+        }
+    }
+}
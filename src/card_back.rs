@@ -0,0 +1,125 @@
+use crate::deck::PickedCard;
+use crate::mapping::CardMap;
+use std::path::{Path, PathBuf};
+
+/// Backs printed behind single faced cards in place of the repository's default back. The most
+/// specific one wins: card, then section, then deck.
+#[derive(Debug, Clone, Default)]
+pub struct CardBacks {
+    /// Backs for each deck, by deck file name with or without its extension
+    decks: Vec<(String, PathBuf)>,
+    sections: Vec<(String, PathBuf)>,
+    cards: CardMap,
+}
+
+impl CardBacks {
+    pub fn new() -> CardBacks {
+        CardBacks::default()
+    }
+
+    pub fn with_decks(mut self, decks: Vec<(String, PathBuf)>) -> Self {
+        self.decks = decks;
+        self
+    }
+
+    pub fn with_sections(mut self, sections: Vec<(String, PathBuf)>) -> Self {
+        self.sections = sections;
+        self
+    }
+
+    pub fn with_cards(mut self, cards: CardMap) -> Self {
+        self.cards = cards;
+        self
+    }
+
+    /// The back for a card in a section, or None to use the repository's default back
+    pub fn back_for(&self, section: &str, card: &PickedCard) -> Option<&Path> {
+        self.cards
+            .get(card)
+            .or_else(|| {
+                self.sections
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(section))
+                    .map(|(_, back)| back.as_path())
+            })
+            .or_else(|| {
+                self.decks
+                    .iter()
+                    .find(|(deck, _)| {
+                        deck == &card.deck
+                            || Path::new(deck)
+                                .file_stem()
+                                .is_some_and(|s| *s == *card.deck)
+                    })
+                    .map(|(_, back)| back.as_path())
+            })
+    }
+
+    /// Check every back exists up front rather than failing half way through a deck
+    pub fn validate(&self) -> Result<(), String> {
+        let backs = self
+            .decks
+            .iter()
+            .chain(&self.sections)
+            .map(|(_, b)| b.as_path());
+        for back in backs.chain(self.cards.files()) {
+            if !back.is_file() {
+                return Err(format!("Card back {:?} does not exist", back));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a `<name>=<file>` override from the command line
+pub fn parse_override(s: &str) -> Result<(String, PathBuf), String> {
+    let (name, file) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected <name>=<file>, got '{}'", s))?;
+    Ok((name.trim().to_string(), PathBuf::from(file.trim())))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::card_back::{parse_override, CardBacks};
+    use crate::deck::PickedCard;
+    use crate::mapping::CardMap;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    pub fn test_back_precedence() {
+        let card = |name: &str| PickedCard {
+            quantity: 1,
+            set_code: "m21".to_string(),
+            collector_number: "1".to_string(),
+            name: name.to_string(),
+            double_sided: false,
+            deck: "cube".to_string(),
+            ..Default::default()
+        };
+        let cards = CardMap::parse("Ponder = ponder.png", Path::new("")).unwrap();
+        let backs = CardBacks::new()
+            .with_decks(vec![("cube.dck".to_string(), PathBuf::from("cube.jpg"))])
+            .with_sections(vec![parse_override("sideboard=sb.png").unwrap()])
+            .with_cards(cards);
+        let back = |section: &str, name: &str| backs.back_for(section, &card(name)).unwrap();
+        assert_eq!(back("Mainboard", "Opt"), Path::new("cube.jpg"));
+        assert_eq!(back("Sideboard", "Opt"), Path::new("sb.png"));
+        assert_eq!(back("Sideboard", "Ponder"), Path::new("ponder.png"));
+        assert_eq!(CardBacks::new().back_for("Mainboard", &card("Opt")), None);
+        let other_deck = PickedCard {
+            deck: "burn".to_string(),
+            ..card("Opt")
+        };
+        assert_eq!(backs.back_for("Mainboard", &other_deck), None);
+        assert!(parse_override("sideboard").is_err());
+        assert!(backs.validate().is_err());
+        let only_card = CardBacks::new().with_cards(
+            CardMap::parse("Ponder = src/card_back_normal.jpg", Path::new("")).unwrap(),
+        );
+        assert!(only_card.validate().is_ok());
+        let missing_card = CardBacks::new()
+            .with_cards(CardMap::parse("Ponder = ponder.png", Path::new("")).unwrap());
+        assert!(missing_card.validate().is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::deck::PickedCard;
+use crate::mapping::CardMap;
+use crate::pdf_calc::{parse_dimensions, CARD_HEIGHT, CARD_WIDTH};
+use printpdf::Mm;
+use std::str::FromStr;
+
+/// Scryfall layout of planes and phenomena, which are printed sideways
+const PLANAR_LAYOUT: &str = "planar";
+
+/// Size of a card, as the card is read. Cards read sideways, such as planes, are wider than
+/// they are tall.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CardSize {
+    pub width: Mm,
+    pub height: Mm,
+}
+
+impl CardSize {
+    pub const STANDARD: CardSize = CardSize {
+        width: CARD_WIDTH,
+        height: CARD_HEIGHT,
+    };
+    /// Mini cards, as used for quick drafts
+    pub const MINI: CardSize = CardSize {
+        width: Mm(41.0),
+        height: Mm(63.0),
+    };
+    /// Board game cards in the European size
+    pub const EURO: CardSize = CardSize {
+        width: Mm(59.0),
+        height: Mm(92.0),
+    };
+    /// Tarot cards
+    pub const TAROT: CardSize = CardSize {
+        width: Mm(70.0),
+        height: Mm(120.0),
+    };
+    /// Cards in the small Japanese size, as used by Yu-Gi-Oh!
+    pub const JAPANESE: CardSize = CardSize {
+        width: Mm(59.0),
+        height: Mm(86.0),
+    };
+    /// Planes, phenomena, schemes and oversized commanders
+    pub const OVERSIZED: CardSize = CardSize {
+        width: Mm(88.9),
+        height: Mm(127.0),
+    };
+
+    pub fn is_landscape(&self) -> bool {
+        self.width.0 > self.height.0
+    }
+
+    /// The same size turned on its side, so it is wider than it is tall
+    pub fn landscape(&self) -> CardSize {
+        match self.is_landscape() {
+            true => *self,
+            false => CardSize {
+                width: self.height,
+                height: self.width,
+            },
+        }
+    }
+}
+
+impl Default for CardSize {
+    fn default() -> Self {
+        CardSize::STANDARD
+    }
+}
+
+impl FromStr for CardSize {
+    type Err = String;
+
+    /// Accepts a preset name (standard, mini, euro, tarot, japanese, oversized) or a custom size
+    /// such as `70x120mm` or `2.5x3.5in`. Custom sizes are always taken upright.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "standard" => return Ok(CardSize::STANDARD),
+            "mini" => return Ok(CardSize::MINI),
+            "euro" => return Ok(CardSize::EURO),
+            "tarot" => return Ok(CardSize::TAROT),
+            "japanese" => return Ok(CardSize::JAPANESE),
+            "oversized" => return Ok(CardSize::OVERSIZED),
+            _ => {}
+        }
+        let (width, height) =
+            parse_dimensions(s).map_err(|e| format!("Unknown card size '{}': {}", s, e))?;
+        Ok(CardSize {
+            width: Mm(width.0.min(height.0)),
+            height: Mm(width.0.max(height.0)),
+        })
+    }
+}
+
+/// Sizes to print cards at. The most specific one wins: card, then section, then the size
+/// Scryfall gives the printing, then the default. Planes and phenomena are turned sideways.
+#[derive(Debug, Clone, Default)]
+pub struct CardSizes {
+    default: CardSize,
+    sections: Vec<(String, CardSize)>,
+    cards: CardMap<CardSize>,
+}
+
+impl CardSizes {
+    pub fn new() -> CardSizes {
+        CardSizes::default()
+    }
+
+    pub fn with_default(mut self, size: CardSize) -> Self {
+        self.default = size;
+        self
+    }
+
+    pub fn with_sections(mut self, sections: Vec<(String, CardSize)>) -> Self {
+        self.sections = sections;
+        self
+    }
+
+    pub fn with_cards(mut self, cards: CardMap<CardSize>) -> Self {
+        self.cards = cards;
+        self
+    }
+
+    /// The size of a card in a section, as the card is read
+    pub fn size_for(&self, section: &str, card: &PickedCard) -> CardSize {
+        let size = self
+            .cards
+            .get_value(card)
+            .or_else(|| {
+                self.sections
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(section))
+                    .map(|(_, size)| size)
+            })
+            .copied()
+            .unwrap_or(match card.oversized {
+                true => CardSize::OVERSIZED,
+                false => self.default,
+            });
+        match card.layout == PLANAR_LAYOUT {
+            true => size.landscape(),
+            false => size,
+        }
+    }
+}
+
+/// Parse a `<section>=<size>` override from the command line
+pub fn parse_size_override(s: &str) -> Result<(String, CardSize), String> {
+    let (name, size) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected <section>=<size>, got '{}'", s))?;
+    Ok((name.trim().to_string(), size.parse()?))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::card_size::{parse_size_override, CardSize, CardSizes};
+    use crate::deck::PickedCard;
+    use crate::mapping::CardMap;
+    use printpdf::Mm;
+
+    #[test]
+    pub fn test_parse_card_size() {
+        assert_eq!("Mini".parse::<CardSize>(), Ok(CardSize::MINI));
+        assert_eq!("tarot".parse::<CardSize>(), Ok(CardSize::TAROT));
+        assert_eq!("59x92mm".parse::<CardSize>(), Ok(CardSize::EURO));
+        assert_eq!(
+            "3.5x2.5in".parse::<CardSize>().unwrap(),
+            CardSize {
+                width: Mm(63.5),
+                height: Mm(88.9)
+            }
+        );
+        assert!("huge".parse::<CardSize>().is_err());
+        assert!(parse_size_override("Commander").is_err());
+    }
+
+    #[test]
+    pub fn test_size_precedence() {
+        let card = |name: &str, number: &str, layout: &str, oversized: bool| PickedCard {
+            quantity: 1,
+            set_code: "opca".to_string(),
+            collector_number: number.to_string(),
+            name: name.to_string(),
+            layout: layout.to_string(),
+            oversized,
+            ..Default::default()
+        };
+        let cards = CardMap::parse_with("[OPCA:1] = standard", str::parse).unwrap();
+        let sizes = CardSizes::new()
+            .with_default(CardSize::MINI)
+            .with_sections(vec![parse_size_override("commander=oversized").unwrap()])
+            .with_cards(cards);
+        let size = |section: &str, card: &PickedCard| sizes.size_for(section, card);
+        let opt = card("Opt", "5", "normal", false);
+        assert_eq!(size("Mainboard", &opt), CardSize::MINI);
+        assert_eq!(size("Commander", &opt), CardSize::OVERSIZED);
+        let plane = card("Tazeem", "1", "planar", true);
+        assert_eq!(size("Planes", &plane), CardSize::STANDARD.landscape());
+        let plane = card("Tazeem", "2", "planar", true);
+        assert_eq!(size("Planes", &plane), CardSize::OVERSIZED.landscape());
+        assert!(size("Planes", &plane).is_landscape());
+    }
+}
@@ -0,0 +1,251 @@
+use crate::deck::PickedDeck;
+use crate::font_metrics::text_width;
+use crate::layout::{Layout, Rect};
+use crate::pdf_writer::Layer;
+use crate::text_proxy::{winansi, Fonts, BOLD_FONT, TEXT_FONT};
+use printpdf::{BuiltinFont, Line, Mm, Point};
+
+/// Distance from the edge of the page to the checklist
+pub const MARGIN: Mm = Mm(15.0);
+/// Space taken by the title at the top of each page
+const TITLE_HEIGHT: Mm = Mm(12.0);
+const TITLE_SIZE: f32 = 16.0;
+/// Distance from one row to the next
+const ROW_HEIGHT: Mm = Mm(4.5);
+const ROW_SIZE: f32 = 9.0;
+/// Side of the tick box at the start of each card row
+const BOX_SIZE: Mm = Mm(2.8);
+
+/// One row of the checklist
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChecklistLine {
+    /// A section name and how many cards it has, or the total at the end
+    Heading(String),
+    Card {
+        quantity: u32,
+        name: String,
+        set_code: String,
+        collector_number: String,
+        deck: String,
+    },
+}
+
+/// A list of every card to print, by section, to tick off the cards as they are cut out
+#[derive(Debug, Clone)]
+pub struct Checklist {
+    title: String,
+    lines: Vec<ChecklistLine>,
+    /// Whether the deck of each card is listed, for print runs of several decks
+    show_decks: bool,
+}
+
+impl Checklist {
+    pub fn new(title: &str, deck: &PickedDeck) -> Checklist {
+        let mut lines = vec![];
+        let mut total = 0;
+        for (section, cards) in &deck.cards {
+            let count: u32 = cards.iter().map(|card| card.quantity).sum();
+            total += count;
+            lines.push(ChecklistLine::Heading(format!("{} ({})", section, count)));
+            for card in cards {
+                lines.push(ChecklistLine::Card {
+                    quantity: card.quantity,
+                    name: card.name.clone(),
+                    set_code: card.set_code.to_uppercase(),
+                    collector_number: card.collector_number.clone(),
+                    deck: card.deck.clone(),
+                });
+            }
+        }
+        lines.push(ChecklistLine::Heading(format!("Total: {} cards", total)));
+        Checklist {
+            title: title.to_string(),
+            lines,
+            show_decks: deck.counts_by_deck().len() > 1,
+        }
+    }
+
+    /// Number of rows that fit below the title on a page
+    fn rows_per_page(layout: &Layout) -> usize {
+        let height = layout.page_height() - MARGIN * 2.0 - TITLE_HEIGHT;
+        ((height.0 / ROW_HEIGHT.0).floor() as usize).max(1)
+    }
+
+    /// Number of pages the checklist takes up
+    pub fn page_count(&self, layout: &Layout) -> usize {
+        self.lines.len().div_ceil(Checklist::rows_per_page(layout))
+    }
+
+    /// The title and rows of each page
+    pub fn pages(&self, layout: &Layout) -> Vec<(String, &[ChecklistLine])> {
+        let chunks = self.lines.chunks(Checklist::rows_per_page(layout));
+        chunks
+            .enumerate()
+            .map(|(page, lines)| match page {
+                0 => (self.title.clone(), lines),
+                _ => (format!("{} (continued)", self.title), lines),
+            })
+            .collect()
+    }
+
+    /// Baseline of the title on each page
+    pub fn title_y(layout: &Layout) -> Mm {
+        layout.page_height() - MARGIN - Mm(6.0)
+    }
+
+    /// Baseline of a row on its page
+    pub fn row_y(layout: &Layout, row: usize) -> Mm {
+        let top = layout.page_height() - MARGIN;
+        top - TITLE_HEIGHT - ROW_HEIGHT * (row as f32 + 1.0) + Mm(1.0)
+    }
+
+    /// Draw the checklist onto its pages, which must number at least `page_count`
+    pub fn draw(&self, pages: &[Layer], fonts: &Fonts, layout: &Layout) {
+        let width = layout.page_width() - MARGIN * 2.0;
+        // Columns from the left margin: tick box, quantity, name, set, number, then deck
+        let quantity_x = MARGIN + Mm(5.0);
+        let name_x = MARGIN + Mm(13.0);
+        let set_x = match self.show_decks {
+            true => MARGIN + width - Mm(70.0),
+            false => MARGIN + width - Mm(30.0),
+        };
+        let number_x = set_x + Mm(12.0);
+        let deck_x = number_x + Mm(18.0);
+        let deck_width = MARGIN + width - deck_x;
+
+        for (layer, (title, lines)) in pages.iter().zip(self.pages(layout)) {
+            let title = truncate(&winansi(&title), BOLD_FONT, TITLE_SIZE, width);
+            let title_y = Checklist::title_y(layout);
+            layer.use_text(title, TITLE_SIZE, MARGIN, title_y, &fonts.bold);
+            layer.set_outline_thickness(0.5);
+            for (row, line) in lines.iter().enumerate() {
+                let y = Checklist::row_y(layout, row);
+                match line {
+                    ChecklistLine::Heading(heading) => {
+                        layer.use_text(winansi(heading), ROW_SIZE + 1.0, MARGIN, y, &fonts.bold);
+                    }
+                    ChecklistLine::Card {
+                        quantity,
+                        name,
+                        set_code,
+                        collector_number,
+                        deck,
+                    } => {
+                        draw_box(layer, &tick_box(y));
+                        let text = |value: &str, x: Mm| {
+                            layer.use_text(winansi(value), ROW_SIZE, x, y, &fonts.regular);
+                        };
+                        text(&quantity.to_string(), quantity_x);
+                        text(
+                            &truncate(name, TEXT_FONT, ROW_SIZE, set_x - name_x - Mm(2.0)),
+                            name_x,
+                        );
+                        text(set_code, set_x);
+                        text(collector_number, number_x);
+                        if self.show_decks {
+                            text(&truncate(deck, TEXT_FONT, ROW_SIZE, deck_width), deck_x);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The tick box of a card row with its baseline at `y`
+pub fn tick_box(y: Mm) -> Rect {
+    Rect::new(MARGIN, y, BOX_SIZE, BOX_SIZE)
+}
+
+fn draw_box(layer: &Layer, rect: &Rect) {
+    layer.add_line(Line {
+        points: vec![
+            (Point::new(rect.x, rect.y), false),
+            (Point::new(rect.right(), rect.y), false),
+            (Point::new(rect.right(), rect.top()), false),
+            (Point::new(rect.x, rect.top()), false),
+        ],
+        is_closed: true,
+    });
+}
+
+/// Cut text short with an ellipsis so it fits in `width`
+fn truncate(text: &str, font: BuiltinFont, size: f32, width: Mm) -> String {
+    if text_width(text, font, size).0 <= width.0 {
+        return text.to_string();
+    }
+    let mut chars: Vec<char> = text.chars().collect();
+    while !chars.is_empty() {
+        chars.pop();
+        let shortened = format!("{}...", chars.iter().collect::<String>().trim_end());
+        if text_width(&shortened, font, size).0 <= width.0 {
+            return shortened;
+        }
+    }
+    String::new()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::checklist::{truncate, Checklist, ChecklistLine};
+    use crate::deck::{PickedCard, PickedDeck, MAINBOARD, SIDEBOARD};
+    use crate::layout::Layout;
+    use crate::pdf_calc::{Orientation, PageSpec, PaperSize};
+    use crate::text_proxy::TEXT_FONT;
+    use printpdf::Mm;
+    use std::collections::BTreeMap;
+
+    #[test]
+    pub fn test_checklist_lines() {
+        let mut cards = BTreeMap::new();
+        cards.insert(
+            MAINBOARD.to_string(),
+            vec![PickedCard::new_for_test("Colossal Dreadmaw", 4)],
+        );
+        cards.insert(
+            SIDEBOARD.to_string(),
+            vec![PickedCard::new_for_test("Llanowar Elves", 2)],
+        );
+        let checklist = Checklist::new("stompy", &PickedDeck { cards });
+        assert!(!checklist.show_decks);
+        assert_eq!(
+            checklist.lines,
+            vec![
+                ChecklistLine::Heading("Mainboard (4)".to_string()),
+                ChecklistLine::Card {
+                    quantity: 4,
+                    name: "Colossal Dreadmaw".to_string(),
+                    set_code: "M21".to_string(),
+                    collector_number: "176".to_string(),
+                    deck: "stompy".to_string(),
+                },
+                ChecklistLine::Heading("Sideboard (2)".to_string()),
+                ChecklistLine::Card {
+                    quantity: 2,
+                    name: "Llanowar Elves".to_string(),
+                    set_code: "M21".to_string(),
+                    collector_number: "176".to_string(),
+                    deck: "stompy".to_string(),
+                },
+                ChecklistLine::Heading("Total: 6 cards".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_page_count() {
+        let layout = Layout::new(&PageSpec::new(PaperSize::A4, Orientation::Portrait));
+        let mut cards = BTreeMap::new();
+        let many = (0..100)
+            .map(|i| PickedCard::new_for_test(&format!("Card {}", i), 1))
+            .collect();
+        cards.insert(MAINBOARD.to_string(), many);
+        let checklist = Checklist::new("cube", &PickedDeck { cards });
+        // 102 rows at 56 a page
+        assert_eq!(checklist.page_count(&layout), 2);
+        assert_eq!(
+            truncate("Colossal Dreadmaw", TEXT_FONT, 9.0, Mm(20.0)),
+            "Colossal Dr..."
+        );
+    }
+}
@@ -0,0 +1,308 @@
+use crate::deck::{process_input, PickedCard, PickedDeck};
+use std::collections::HashMap;
+use std::fs::{read_to_string, File};
+use std::path::Path;
+
+/// Column headers recognised in a collection CSV, in lower case
+const QUANTITY_HEADERS: [&str; 3] = ["count", "quantity", "qty"];
+const NAME_HEADERS: [&str; 3] = ["name", "card name", "card"];
+const SET_HEADERS: [&str; 4] = ["set", "set code", "edition", "edition code"];
+const NUMBER_HEADERS: [&str; 4] = [
+    "collector number",
+    "collector_number",
+    "card number",
+    "number",
+];
+
+/// How an owned card is matched against a card in the deck
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum CollectionMatch {
+    /// Any printing of a card counts as owning it
+    #[default]
+    Name,
+    /// Only the exact set and collector number count. Owned cards with no printing are ignored.
+    Printing,
+}
+
+/// Cards already owned, read from a deck file or a CSV export of a collection
+#[derive(Debug, Clone, Default)]
+pub struct Collection {
+    by_name: HashMap<String, u32>,
+    by_printing: HashMap<(String, String), u32>,
+}
+
+impl Collection {
+    /// Read a `.csv` collection export, or any deck file the deck reader understands
+    pub fn load(path: &Path) -> Result<Collection, String> {
+        let is_csv = path
+            .extension()
+            .is_some_and(|e| e.to_string_lossy().eq_ignore_ascii_case("csv"));
+        if is_csv {
+            let text = read_to_string(path)
+                .map_err(|e| format!("Could not read collection {:?}: {}", path, e))?;
+            return Collection::parse_csv(&text).map_err(|e| format!("{:?}: {}", path, e));
+        }
+        let file =
+            File::open(path).map_err(|e| format!("Could not read collection {:?}: {}", path, e))?;
+        let deck = process_input(file).map_err(|e| format!("{:?}: {}", path, e))?;
+        let mut collection = Collection::default();
+        for card in deck.cards.into_values().flatten() {
+            let printing = card
+                .set_code
+                .as_deref()
+                .zip(card.collector_number.as_deref());
+            collection.add(&card.name, printing, card.quantity);
+        }
+        Ok(collection)
+    }
+
+    /// Parse a CSV with a header row naming at least a name column. Quantity, set and collector
+    /// number columns are used if there are any.
+    pub fn parse_csv(text: &str) -> Result<Collection, String> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header: Vec<String> = split_csv_line(lines.next().unwrap_or_default())
+            .into_iter()
+            .map(|h| h.trim().to_lowercase())
+            .collect();
+        let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+        let name_column = column(&NAME_HEADERS).ok_or("Collection CSV has no name column")?;
+        let quantity_column = column(&QUANTITY_HEADERS);
+        let set_column = column(&SET_HEADERS);
+        let number_column = column(&NUMBER_HEADERS);
+
+        let mut collection = Collection::default();
+        for (row, line) in lines.enumerate() {
+            let fields = split_csv_line(line);
+            let field = |column: Option<usize>| {
+                column
+                    .and_then(|c| fields.get(c))
+                    .map(|f| f.trim())
+                    .filter(|f| !f.is_empty())
+            };
+            let Some(name) = field(Some(name_column)) else {
+                continue;
+            };
+            let quantity = match field(quantity_column) {
+                Some(quantity) => quantity.parse::<u32>().map_err(|e| {
+                    format!("row {}: invalid quantity '{}': {}", row + 2, quantity, e)
+                })?,
+                None => 1,
+            };
+            let printing = field(set_column).zip(field(number_column));
+            collection.add(name, printing, quantity);
+        }
+        Ok(collection)
+    }
+
+    fn add(&mut self, name: &str, printing: Option<(&str, &str)>, quantity: u32) {
+        *self.by_name.entry(name.to_lowercase()).or_default() += quantity;
+        if let Some((set, number)) = printing {
+            let printing = (set.to_lowercase(), number.to_lowercase());
+            *self.by_printing.entry(printing).or_default() += quantity;
+        }
+    }
+
+    /// Take up to `wanted` owned copies of a card out of the collection, returning how many were
+    /// taken. Double faced cards also match on the name of their front face.
+    fn take(&mut self, card: &PickedCard, wanted: u32, matching: CollectionMatch) -> u32 {
+        let owned = match matching {
+            CollectionMatch::Name => {
+                let name = card.name.to_lowercase();
+                let front_name = name
+                    .split("//")
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string();
+                match self.by_name.contains_key(&name) {
+                    true => self.by_name.get_mut(&name),
+                    false => self.by_name.get_mut(&front_name),
+                }
+            }
+            CollectionMatch::Printing => {
+                let printing = (
+                    card.set_code.to_lowercase(),
+                    card.collector_number.to_lowercase(),
+                );
+                self.by_printing.get_mut(&printing)
+            }
+        };
+        match owned {
+            Some(owned) => {
+                let taken = wanted.min(*owned);
+                *owned -= taken;
+                taken
+            }
+            None => 0,
+        }
+    }
+}
+
+/// Split one line of a CSV, allowing quoted fields with commas and doubled quotes in them
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// The cards left to print and the owned ones left out, as (card, quantity)
+#[derive(Debug, Default, PartialEq)]
+pub struct Exclusions {
+    pub printed: Vec<(String, u32)>,
+    pub skipped: Vec<(String, u32)>,
+}
+
+impl Exclusions {
+    pub fn print(&self) {
+        let total = |cards: &[(String, u32)]| cards.iter().map(|(_, q)| q).sum::<u32>();
+        println!("Skipping {} owned cards:", total(&self.skipped));
+        for (card, quantity) in &self.skipped {
+            println!("  {} {}", quantity, card);
+        }
+        println!(
+            "Printing {} cards not in the collection:",
+            total(&self.printed)
+        );
+        for (card, quantity) in &self.printed {
+            println!("  {} {}", quantity, card);
+        }
+    }
+}
+
+/// Take the owned cards out of the deck, section by section in deck order, dropping cards with
+/// no copies left to print
+pub fn exclude_collection(
+    deck: &mut PickedDeck,
+    mut collection: Collection,
+    matching: CollectionMatch,
+) -> Exclusions {
+    let mut exclusions = Exclusions::default();
+    for cards in deck.cards.values_mut() {
+        for card in cards.iter_mut() {
+            let label = format!(
+                "{} [{}:{}]",
+                card.name,
+                card.set_code.to_uppercase(),
+                card.collector_number
+            );
+            let owned = collection.take(card, card.quantity, matching);
+            if owned > 0 {
+                exclusions.skipped.push((label.clone(), owned));
+            }
+            card.quantity -= owned;
+            if card.quantity > 0 {
+                exclusions.printed.push((label, card.quantity));
+            }
+        }
+        cards.retain(|card| card.quantity > 0);
+    }
+    exclusions
+}
+
+#[cfg(test)]
+mod test {
+    use crate::collection::{exclude_collection, split_csv_line, Collection, CollectionMatch};
+    use crate::deck::{PickedCard, PickedDeck, MAINBOARD, SIDEBOARD};
+    use std::collections::BTreeMap;
+    use std::fs::write;
+    use std::path::Path;
+
+    fn deck() -> PickedDeck {
+        let card = |name, quantity, set_code: &str, collector_number: &str| PickedCard {
+            set_code: set_code.to_string(),
+            collector_number: collector_number.to_string(),
+            ..PickedCard::new_for_test(name, quantity)
+        };
+        let mut cards = BTreeMap::new();
+        cards.insert(
+            MAINBOARD.to_string(),
+            vec![
+                card("Lightning Bolt", 4, "m10", "146"),
+                card("Delver of Secrets // Insectile Aberration", 2, "isd", "51"),
+            ],
+        );
+        cards.insert(
+            SIDEBOARD.to_string(),
+            vec![card("Lightning Bolt", 2, "m10", "146")],
+        );
+        PickedDeck { cards }
+    }
+
+    #[test]
+    pub fn test_split_csv_line() {
+        assert_eq!(
+            split_csv_line(r#"2,"Borrowing 100,000 Arrows",ptk,"1""0""#),
+            vec!["2", "Borrowing 100,000 Arrows", "ptk", "1\"0"]
+        );
+    }
+
+    #[test]
+    pub fn test_exclude_by_name() {
+        let collection = Collection::parse_csv(
+            "Count,Name,Edition,Collector Number\n5,Lightning Bolt,lea,161\n1,Delver of Secrets,,\n",
+        )
+        .unwrap();
+        let mut deck = deck();
+        let exclusions = exclude_collection(&mut deck, collection, CollectionMatch::Name);
+        // The mainboard takes the owned copies first
+        assert!(deck.cards[MAINBOARD]
+            .iter()
+            .all(|card| card.name != "Lightning Bolt"));
+        assert_eq!(deck.cards[MAINBOARD][0].quantity, 1);
+        assert_eq!(deck.cards[SIDEBOARD][0].quantity, 1);
+        assert_eq!(
+            exclusions.skipped,
+            vec![
+                ("Lightning Bolt [M10:146]".to_string(), 4),
+                (
+                    "Delver of Secrets // Insectile Aberration [ISD:51]".to_string(),
+                    1
+                ),
+                ("Lightning Bolt [M10:146]".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_exclude_by_printing() {
+        let collection = Collection::parse_csv(
+            "Name,Set,Collector Number\nLightning Bolt,LEA,161\nLightning Bolt,M10,146\n",
+        )
+        .unwrap();
+        let mut deck = deck();
+        let exclusions = exclude_collection(&mut deck, collection, CollectionMatch::Printing);
+        assert_eq!(deck.cards[MAINBOARD][0].quantity, 3);
+        assert_eq!(deck.cards[SIDEBOARD][0].quantity, 2);
+        assert_eq!(exclusions.skipped.len(), 1);
+        assert!(Collection::parse_csv("Count,Set\n1,lea\n").is_err());
+    }
+
+    #[test]
+    pub fn test_load_mtgo_list() {
+        let path = Path::new("Test_Temp_Collection.txt");
+        write(path, "4 Lightning Bolt\n\n1 Lightning Bolt\n").unwrap();
+        let collection = Collection::load(path);
+        std::fs::remove_file(path).unwrap();
+        let mut deck = deck();
+        let exclusions = exclude_collection(&mut deck, collection.unwrap(), CollectionMatch::Name);
+        assert!(deck.cards[MAINBOARD]
+            .iter()
+            .all(|card| card.name != "Lightning Bolt"));
+        assert_eq!(deck.cards[SIDEBOARD][0].quantity, 1);
+        assert_eq!(exclusions.skipped.len(), 2);
+    }
+}
@@ -0,0 +1,267 @@
+use crate::layout::{Layout, Rect};
+use crate::pdf_writer::Layer;
+use crate::processing::CORNER_RADIUS;
+use printpdf::path::{PaintMode, WindingOrder};
+use printpdf::{Color, Mm, Point, Polygon, Rgb};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs::write;
+use std::path::{Path, PathBuf};
+
+/// Space kept clear between a registration mark and any card
+const CLEARANCE: Mm = Mm(2.0);
+/// Distance from the edge of the page to the Silhouette marks
+const SILHOUETTE_INSET: Mm = Mm(10.0);
+const SILHOUETTE_SQUARE: Mm = Mm(5.0);
+const SILHOUETTE_LENGTH: Mm = Mm(20.0);
+const SILHOUETTE_THICKNESS: Mm = Mm(0.5);
+/// Largest area a Cricut cuts after printing, 6.75x9.25in
+const CRICUT_AREA: (Mm, Mm) = (Mm(171.45), Mm(234.95));
+/// Smallest distance from the edge of the page to the Cricut frame
+const CRICUT_MIN_INSET: Mm = Mm(6.35);
+const CRICUT_SQUARE: Mm = Mm(6.35);
+const CRICUT_THICKNESS: Mm = Mm(0.75);
+
+/// A cutting machine that cuts the cards out of a printed page, finding the cards by the
+/// registration marks printed around them
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum Cutter {
+    /// Cricut print then cut: a frame around the 6.75x9.25in cutting area, with a square in its
+    /// top left corner
+    Cricut,
+    /// Silhouette type 1 marks 10mm in from the edges: a square at the top left and corner lines
+    /// at the top right and bottom left
+    Silhouette,
+}
+
+impl Cutter {
+    /// The Cricut cutting area, centred on the page
+    fn cricut_area(width: Mm, height: Mm) -> Rect {
+        let (mut area_width, mut area_height) = CRICUT_AREA;
+        if width.0 > height.0 {
+            (area_width, area_height) = (area_height, area_width);
+        }
+        let area_width = Mm(area_width.0.min((width - CRICUT_MIN_INSET * 2.0).0));
+        let area_height = Mm(area_height.0.min((height - CRICUT_MIN_INSET * 2.0).0));
+        Rect::new(
+            (width - area_width) / 2.0,
+            (height - area_height) / 2.0,
+            area_width,
+            area_height,
+        )
+    }
+
+    /// The registration marks on a page of the given size, as filled rectangles
+    pub fn marks(&self, width: Mm, height: Mm) -> Vec<Rect> {
+        match self {
+            Cutter::Cricut => {
+                let area = Cutter::cricut_area(width, height);
+                let frame = area.expand(CRICUT_THICKNESS);
+                let t = CRICUT_THICKNESS;
+                vec![
+                    Rect::new(frame.x, frame.y, frame.width, t),
+                    Rect::new(frame.x, frame.top() - t, frame.width, t),
+                    Rect::new(frame.x, frame.y, t, frame.height),
+                    Rect::new(frame.right() - t, frame.y, t, frame.height),
+                    Rect::new(
+                        area.x,
+                        area.top() - CRICUT_SQUARE,
+                        CRICUT_SQUARE,
+                        CRICUT_SQUARE,
+                    ),
+                ]
+            }
+            Cutter::Silhouette => {
+                let (inset, length, t) =
+                    (SILHOUETTE_INSET, SILHOUETTE_LENGTH, SILHOUETTE_THICKNESS);
+                let (right, top) = (width - inset, height - inset);
+                vec![
+                    Rect::new(
+                        inset,
+                        top - SILHOUETTE_SQUARE,
+                        SILHOUETTE_SQUARE,
+                        SILHOUETTE_SQUARE,
+                    ),
+                    Rect::new(right - length, top - t, length, t),
+                    Rect::new(right - t, top - length, t, length),
+                    Rect::new(inset, inset, length, t),
+                    Rect::new(inset, inset, t, length),
+                ]
+            }
+        }
+    }
+
+    /// Smallest margin that keeps every card inside the marks
+    pub fn margin(&self, width: Mm, height: Mm) -> Mm {
+        match self {
+            Cutter::Cricut => {
+                let area = Cutter::cricut_area(width, height);
+                Mm(area.x.0.max(area.y.0)) + CLEARANCE
+            }
+            Cutter::Silhouette => SILHOUETTE_INSET + CLEARANCE,
+        }
+    }
+
+    /// Areas inside the margin that no card may cover, so the machine can see the marks
+    pub fn keep_out(&self, width: Mm, height: Mm) -> Vec<Rect> {
+        let marks = self.marks(width, height);
+        let zones: Vec<Rect> = match self {
+            // The frame is outside the margin, only the square reaches in
+            Cutter::Cricut => marks[4..].to_vec(),
+            Cutter::Silhouette => {
+                let (inset, length) = (SILHOUETTE_INSET, SILHOUETTE_LENGTH);
+                vec![
+                    marks[0],
+                    Rect::new(
+                        width - inset - length,
+                        height - inset - length,
+                        length,
+                        length,
+                    ),
+                    Rect::new(inset, inset, length, length),
+                ]
+            }
+        };
+        zones.iter().map(|zone| zone.expand(CLEARANCE)).collect()
+    }
+
+    /// The marks with the space kept clear around them, which cut guides stay out of
+    pub fn clear_zones(&self, width: Mm, height: Mm) -> Vec<Rect> {
+        let marks = self.marks(width, height);
+        marks.iter().map(|mark| mark.expand(CLEARANCE)).collect()
+    }
+
+    /// Draw the registration marks in black on the front of a sheet
+    pub fn draw_marks(&self, layer: &Layer, layout: &Layout) {
+        layer.save_graphics_state();
+        layer.set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+        for mark in self.marks(layout.page_width(), layout.page_height()) {
+            let corners = [
+                (mark.x, mark.y),
+                (mark.right(), mark.y),
+                (mark.right(), mark.top()),
+                (mark.x, mark.top()),
+            ];
+            layer.add_polygon(Polygon {
+                rings: vec![corners
+                    .iter()
+                    .map(|(x, y)| (Point::new(*x, *y), false))
+                    .collect()],
+                mode: PaintMode::Fill,
+                winding_order: WindingOrder::NonZero,
+            });
+        }
+        layer.restore_graphics_state();
+    }
+}
+
+/// An SVG path around a card with rounded corners, in mm from the top left of the page
+fn card_outline(card: &Rect, page_height: Mm) -> String {
+    let radius = card.width.0.min(card.height.0) * CORNER_RADIUS;
+    let (left, right) = (card.x.0, card.right().0);
+    // SVG counts down from the top of the page, pdf counts up from the bottom
+    let (top, bottom) = ((page_height - card.top()).0, (page_height - card.y).0);
+    let mut path = String::new();
+    let arc = |path: &mut String, x: f32, y: f32| {
+        let _ = write!(path, " A {r:.3} {r:.3} 0 0 1 {x:.3} {y:.3}", r = radius);
+    };
+    let _ = write!(
+        path,
+        "M {:.3} {:.3} H {:.3}",
+        left + radius,
+        top,
+        right - radius
+    );
+    arc(&mut path, right, top + radius);
+    let _ = write!(path, " V {:.3}", bottom - radius);
+    arc(&mut path, right - radius, bottom);
+    let _ = write!(path, " H {:.3}", left + radius);
+    arc(&mut path, left, bottom - radius);
+    let _ = write!(path, " V {:.3}", top + radius);
+    arc(&mut path, left + radius, top);
+    path.push_str(" Z");
+    path
+}
+
+/// An SVG the size of the page with the outline of every card on it
+fn cut_svg(cards: &[Rect], layout: &Layout) -> String {
+    let (width, height) = (layout.page_width().0, layout.page_height().0);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}mm\" height=\"{h}mm\" \
+         viewBox=\"0 0 {w} {h}\">\n",
+        w = width,
+        h = height
+    );
+    for card in cards {
+        let _ = writeln!(
+            svg,
+            "  <path d=\"{}\" fill=\"none\" stroke=\"#ff0000\" stroke-width=\"0.1\"/>",
+            card_outline(card, layout.page_height())
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// `deck.pdf` becomes `deck_cut_3.svg` for its third page
+fn cut_file_path(path: &Path, page: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}_cut_{}.svg", stem, page + 1))
+}
+
+/// Write an SVG cut file for each page of fronts in the PDF at `pdf_path`, lining up with the
+/// cards on it, keyed by page index
+pub fn write_cut_files(
+    pdf_path: &Path,
+    layout: &Layout,
+    pages: &BTreeMap<usize, Vec<Rect>>,
+) -> Result<(), String> {
+    for (&page, cards) in pages {
+        let path = cut_file_path(pdf_path, page);
+        write(&path, cut_svg(cards, layout))
+            .map_err(|e| format!("Could not write cut file {:?}: {}", path, e))?;
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cutter::{card_outline, Cutter};
+    use crate::layout::{Layout, Rect};
+    use crate::packing::Packing;
+    use crate::pdf_calc::PageSpec;
+    use printpdf::Mm;
+
+    #[test]
+    pub fn test_cards_avoid_marks() {
+        let page = PageSpec::default();
+        for cutter in [Cutter::Cricut, Cutter::Silhouette] {
+            let (width, height) = (page.width(), page.height());
+            let layouts = [Layout::new(&page)
+                .with_margin(cutter.margin(width, height))
+                .with_keep_out(cutter.keep_out(width, height))];
+            layouts[0].validate().unwrap();
+            let packing = Packing::new(&layouts, &[0; 12]);
+            for slot in &packing.slots {
+                for mark in cutter.marks(width, height) {
+                    assert!(!slot.front.overlaps(&mark), "{:?} {:?}", cutter, slot);
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_card_outline() {
+        let card = Rect::new(Mm(10.0), Mm(20.0), Mm(63.0), Mm(88.0));
+        assert_eq!(
+            card_outline(&card, Mm(297.0)),
+            "M 13.000 189.000 H 70.000 A 3.000 3.000 0 0 1 73.000 192.000 V 274.000 \
+             A 3.000 3.000 0 0 1 70.000 277.000 H 13.000 A 3.000 3.000 0 0 1 10.000 274.000 \
+             V 192.000 A 3.000 3.000 0 0 1 13.000 189.000 Z"
+        );
+    }
+}
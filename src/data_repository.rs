@@ -1,11 +1,22 @@
 use crate::deck;
 use crate::deck::{DehydratedCard, HydratedCard};
-use crate::scryfall_client::{ScryfallClient, ScryfallSearchResultEntryImageUris};
+use crate::scryfall_client::{
+    ScryfallClient, ScryfallSearchResultEntry, ScryfallSearchResultEntryImageUris, SearchCardError,
+};
 use serde::{Deserialize, Serialize};
 use sled::{Db, IVec};
 use std::collections::BTreeMap;
 use std::path::Path;
 
+/// Version of the layout of `CardInfo` in the cache, which starts every cache key. Bump it when
+/// `CardInfo` changes, so entries written by an older version are fetched again instead of read
+/// as the new layout.
+const CACHE_VERSION: u32 = 2;
+
+/// Prefix of the cache keys of tokens, emblems and meld results, which are followed by the
+/// Scryfall id of the printing
+const TOKEN_KEY_PREFIX: &str = "token:";
+
 #[derive(Clone)]
 pub struct DataRepository {
     db: Db,
@@ -14,24 +25,32 @@ pub struct DataRepository {
 
 impl DataRepository {
     pub fn new(path: &Path) -> Result<DataRepository, ()> {
-        let db = sled::open(path).map_err(|e| ())?;
+        let db = sled::open(path).map_err(|_| ())?;
         let client = ScryfallClient::new();
         Ok(DataRepository { db, client })
     }
 
     pub fn delete(&self, card: &str) {
-        self.db.remove(card.clone()).expect("Failed to remove card");
+        self.db
+            .remove(cache_key(card))
+            .expect("Failed to remove card");
     }
 
     pub fn scan_range(&self, prefix: &str) -> BTreeMap<String, CardInfo> {
         let mut results = BTreeMap::new();
-        let scan = self.db.scan_prefix(prefix.to_string());
+        let version_prefix = cache_key("");
+        let scan = self.db.scan_prefix(cache_key(prefix));
         for res in scan {
             match res {
                 Ok((key_ivec, val_ivec)) => {
-                    let key = String::from_utf8_lossy(key_ivec.as_ref()).to_string();
-                    let val: CardInfo = CardInfo::from(val_ivec);
-                    results.insert(key, val);
+                    let key = String::from_utf8_lossy(key_ivec.as_ref());
+                    let key = key
+                        .strip_prefix(&version_prefix)
+                        .unwrap_or(&key)
+                        .to_string();
+                    if let Ok(val) = CardInfo::try_from(val_ivec) {
+                        results.insert(key, val);
+                    }
                 }
                 Err(e) => {
                     panic!("Scan error: {}", e);
@@ -42,109 +61,216 @@ impl DataRepository {
     }
 
     pub fn get(&self, card: DehydratedCard) -> Result<HydratedCard, ()> {
-        let res = self.db.get(card.name.clone()).map_err(|e| ())?;
-        let card_info: Option<CardInfo> = res.map(|ivec| ivec.into());
-        let card_info = match card_info {
-            None => {
-                println!("Cache miss for '{}', requesting scryfall data", card.name);
-                let vars = match self.client.get_card_variants(&card.name) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        eprintln!("error: {}", e.error);
-                        eprintln!("url: {}", e.url);
-                        eprintln!("cause: {}", e.cause);
-                        eprintln!("response: {}", e.response);
-                        panic!()
-                    }
-                };
-                println!("Card variants: {:?}", vars);
-                let first = vars
-                    .first()
-                    .clone()
-                    .expect("The search results did not have a first card")
-                    .clone();
-                let double_sided = first
-                    .card_faces
-                    .iter()
-                    .flat_map(|card_faces_vec| card_faces_vec.iter())
-                    .map(|card_face| card_face.image_uris.is_some())
-                    .reduce(|a, b| a | b)
-                    .unwrap_or(false);
-                let variants = vars
-                    .into_iter()
-                    .filter(|var| var.valid())
-                    .map(|var| CardVariant {
-                        set: var.set,
-                        collector_number: var.collector_number,
-                        lang: var.lang,
-                        img_url: var.image_uris,
-                    })
-                    .map(|mut var| {
-                        match var.set == "plist" {
-                            true => {
-                                let mut split_res = var.collector_number.split("-");
-                                let set = split_res.next().unwrap();
-                                let collector_number = split_res.next().unwrap();
-                                assert!(split_res.next().is_none());
-                                var.set = set.to_string();
-                                var.collector_number = collector_number.to_string();
-                                var
-                            }
-                            false => {
-                                var
-                            }
-                        }
-                    })
-                    .collect();
-                let entry = CardInfo {
-                    name: first.name,
-                    double_sided,
-                    variants,
-                };
-                self.db
-                    .insert(card.name.clone(), entry.clone())
-                    .expect("It should have been possible to insert into db");
-                entry
-            }
-            Some(card_info) => {
-                println!("Cache hit for '{}'", card.name);
-                card_info
+        let card_info = self.card_info(&card.name, || self.client.get_card_variants(&card.name))?;
+        hydrate(card, card_info)
+    }
+
+    /// Look up the printing of a token, emblem or meld result with a Scryfall id, as listed in
+    /// the parts of the card that makes it. These are kept apart from cards in the cache.
+    pub fn get_token(&self, id: &str, quantity: u32) -> Result<HydratedCard, ()> {
+        let key = format!("{}{}", TOKEN_KEY_PREFIX, id);
+        let card_info = self.card_info(&key, || {
+            self.client.get_card_by_id(id).map(|entry| vec![entry])
+        })?;
+        let card = DehydratedCard {
+            quantity,
+            set_code: None,
+            collector_number: None,
+            name: card_info.name.clone(),
+            flip_name: None,
+            double_sided: None,
+        };
+        hydrate(card, card_info)
+    }
+
+    /// Cached data for a card, fetched from Scryfall with `fetch` on a miss
+    fn card_info<F>(&self, key: &str, fetch: F) -> Result<CardInfo, ()>
+    where
+        F: FnOnce() -> Result<Vec<ScryfallSearchResultEntry>, SearchCardError>,
+    {
+        let res = self.db.get(cache_key(key)).map_err(|_| ())?;
+        if let Some(card_info) = res.and_then(|ivec| CardInfo::try_from(ivec).ok()) {
+            println!("Cache hit for '{}'", key);
+            return Ok(card_info);
+        }
+        println!("Cache miss for '{}', requesting scryfall data", key);
+        let vars = match fetch() {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("error: {}", e.error);
+                eprintln!("url: {}", e.url);
+                eprintln!("cause: {}", e.cause);
+                eprintln!("response: {}", e.response);
+                return Err(());
             }
         };
-        let first_item = card_info
-            .variants
-            .first()
-            .expect("There were no variants")
-            .clone();
-        Ok(HydratedCard {
-            quantity: card.quantity,
-            set_code: card
-                .set_code
-                .unwrap_or(first_item.set.clone())
-                .to_lowercase(),
-            collector_number: card
-                .collector_number
-                .unwrap_or(first_item.collector_number.clone())
-                .to_lowercase(),
-            name: card_info.name,
-            double_sided: card_info.double_sided,
-            variants: card_info
-                .variants
-                .into_iter()
-                .map(|cv| deck::CardVariant {
-                    set: cv.set.to_lowercase(),
-                    collector_number: cv.collector_number.to_lowercase(),
+        println!("Card variants: {:?}", vars);
+        let first = vars.first().ok_or(())?.clone();
+        let double_sided = first
+            .card_faces
+            .iter()
+            .flat_map(|card_faces_vec| card_faces_vec.iter())
+            .map(|card_face| card_face.image_uris.is_some())
+            .reduce(|a, b| a | b)
+            .unwrap_or(false);
+        // Printings of a card can make different tokens, so gather them from every printing
+        let mut related: Vec<RelatedCard> = vec![];
+        for var in &vars {
+            let parts = var.all_parts.iter().flatten();
+            for part in parts.filter(|part| part.is_extra()) {
+                if !related.iter().any(|r| r.id == part.id) {
+                    related.push(RelatedCard {
+                        id: part.id.clone(),
+                        name: part.name.clone(),
+                        set: var.set.to_lowercase(),
+                    });
+                }
+            }
+        }
+        let variants = vars
+            .into_iter()
+            .filter(|var| var.valid())
+            .map(|var| CardVariant {
+                set: var.set,
+                collector_number: var.collector_number,
+                lang: var.lang,
+                img_url: var.image_uris,
+                oversized: var.oversized,
+            })
+            .map(|mut var| match var.set == "plist" {
+                true => {
+                    let mut split_res = var.collector_number.split("-");
+                    let set = split_res.next().unwrap();
+                    let collector_number = split_res.next().unwrap();
+                    assert!(split_res.next().is_none());
+                    var.set = set.to_string();
+                    var.collector_number = collector_number.to_string();
+                    var
+                }
+                false => var,
+            })
+            .collect();
+        let faces = match &first.card_faces {
+            Some(faces) => faces
+                .iter()
+                .map(|face| CardText {
+                    name: face.name.clone(),
+                    mana_cost: face.mana_cost.clone().unwrap_or_default(),
+                    type_line: face.type_line.clone().unwrap_or_default(),
+                    oracle_text: face.oracle_text.clone().unwrap_or_default(),
+                    power: face.power.clone(),
+                    toughness: face.toughness.clone(),
+                    loyalty: face.loyalty.clone(),
                 })
                 .collect(),
-        })
+            None => vec![CardText {
+                name: first.name.clone(),
+                mana_cost: first.mana_cost.clone().unwrap_or_default(),
+                type_line: first.type_line.clone().unwrap_or_default(),
+                oracle_text: first.oracle_text.clone().unwrap_or_default(),
+                power: first.power.clone(),
+                toughness: first.toughness.clone(),
+                loyalty: first.loyalty.clone(),
+            }],
+        };
+        let entry = CardInfo {
+            name: first.name,
+            layout: first.layout,
+            double_sided,
+            variants,
+            faces,
+            related,
+        };
+        self.db
+            .insert(cache_key(key), entry.clone())
+            .expect("It should have been possible to insert into db");
+        Ok(entry)
     }
 }
 
+/// The key a card is cached under, for the current `CACHE_VERSION`
+fn cache_key(key: &str) -> String {
+    format!("v{}:{}", CACHE_VERSION, key)
+}
+
+fn hydrate(card: DehydratedCard, card_info: CardInfo) -> Result<HydratedCard, ()> {
+    let first_item = card_info.variants.first().ok_or(())?.clone();
+    Ok(HydratedCard {
+        quantity: card.quantity,
+        set_code: card
+            .set_code
+            .unwrap_or(first_item.set.clone())
+            .to_lowercase(),
+        collector_number: card
+            .collector_number
+            .unwrap_or(first_item.collector_number.clone())
+            .to_lowercase(),
+        name: card_info.name,
+        layout: card_info.layout,
+        double_sided: card_info.double_sided,
+        faces: card_info
+            .faces
+            .into_iter()
+            .map(|face| deck::CardText {
+                name: face.name,
+                mana_cost: face.mana_cost,
+                type_line: face.type_line,
+                oracle_text: face.oracle_text,
+                power: face.power,
+                toughness: face.toughness,
+                loyalty: face.loyalty,
+            })
+            .collect(),
+        related: card_info
+            .related
+            .into_iter()
+            .map(|related| deck::RelatedCard {
+                id: related.id,
+                name: related.name,
+                set: related.set,
+            })
+            .collect(),
+        variants: card_info
+            .variants
+            .into_iter()
+            .map(|cv| deck::CardVariant {
+                set: cv.set.to_lowercase(),
+                collector_number: cv.collector_number.to_lowercase(),
+                oversized: cv.oversized,
+            })
+            .collect(),
+    })
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
-struct CardInfo {
+pub struct CardInfo {
     name: String,
+    /// Scryfall layout of the card, such as `normal` or `planar`
+    layout: String,
     double_sided: bool,
     variants: Vec<CardVariant>,
+    /// Rules text of each face, or of the whole card if it has only one
+    faces: Vec<CardText>,
+    /// The tokens, emblems and meld results the card makes
+    related: Vec<RelatedCard>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct RelatedCard {
+    id: String,
+    name: String,
+    set: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CardText {
+    name: String,
+    mana_cost: String,
+    type_line: String,
+    oracle_text: String,
+    power: Option<String>,
+    toughness: Option<String>,
+    loyalty: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -153,19 +279,20 @@ struct CardVariant {
     collector_number: String,
     lang: String,
     img_url: Option<ScryfallSearchResultEntryImageUris>,
+    oversized: bool,
 }
 
-impl From<IVec> for CardInfo {
-    fn from(value: IVec) -> Self {
-        let card_info: CardInfo =
-            bincode::deserialize(value.as_ref()).expect("IVec of CardInfo can't be deserialized");
-        card_info
+impl TryFrom<IVec> for CardInfo {
+    type Error = bincode::Error;
+
+    fn try_from(value: IVec) -> Result<Self, Self::Error> {
+        bincode::deserialize(value.as_ref())
     }
 }
 
-impl Into<IVec> for CardInfo {
-    fn into(self) -> IVec {
-        let data = bincode::serialize(&self).expect("IVec of CardInfo can't be serialized");
+impl From<CardInfo> for IVec {
+    fn from(value: CardInfo) -> Self {
+        let data = bincode::serialize(&value).expect("IVec of CardInfo can't be serialized");
         IVec::from(data)
     }
 }
@@ -180,7 +307,7 @@ mod test {
     pub fn test_plist() {
         let card = "Stitcher's Supplier";
         let repo = DataRepository::new(Path::new("data_repository")).unwrap();
-        let res = repo.scan_range(&card);
+        let res = repo.scan_range(card);
         for (k, v) in res {
             println!("Scan result: {}", k);
             println!("Value: {:?}", v);
@@ -194,7 +321,7 @@ mod test {
         repo.delete("Expansion");
         repo.delete("Expansion // Explosion");
         repo.delete("Kabira Takedown");
-        let hydrated = repo.get(DehydratedCard {
+        let _hydrated = repo.get(DehydratedCard {
             quantity: 1,
             set_code: None,
             collector_number: None,
@@ -5,9 +5,13 @@ use std::io::{BufRead, BufReader, Read};
 
 pub const MAINBOARD: &str = "Mainboard";
 pub const SIDEBOARD: &str = "Sideboard";
+/// Set code given to custom cards that have no set of their own
+pub const CUSTOM_SET: &str = "custom";
+/// Section holding the tokens, emblems and meld results the rest of the deck makes
+pub const TOKENS: &str = "Tokens";
 
 /// Dehydrated card is a card processed from file input, but without scryfall card information
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DehydratedCard {
     pub quantity: u32,
     pub set_code: Option<String>,
@@ -22,26 +26,106 @@ pub struct DehydratedCard {
 pub struct HydratedCard {
     pub quantity: u32,
     pub name: String,
+    /// Scryfall layout of the card, such as `normal` or `planar`
+    pub layout: String,
     pub set_code: String,
     pub collector_number: String,
     pub double_sided: bool,
     pub variants: Vec<CardVariant>,
+    pub faces: Vec<CardText>,
+    /// The tokens, emblems and meld results the card makes
+    pub related: Vec<RelatedCard>,
+}
+
+impl HydratedCard {
+    /// A card with no Scryfall data, printed from artwork alone
+    fn custom(card: DehydratedCard) -> HydratedCard {
+        let set_code = card.set_code.unwrap_or_else(|| CUSTOM_SET.to_string());
+        let collector_number = card
+            .collector_number
+            .unwrap_or_else(|| card.name.to_lowercase().replace(' ', "-"));
+        let name = match card.flip_name {
+            Some(flip_name) => format!("{} // {}", card.name, flip_name),
+            None => card.name,
+        };
+        HydratedCard {
+            quantity: card.quantity,
+            variants: vec![CardVariant {
+                set: set_code.clone(),
+                collector_number: collector_number.clone(),
+                oversized: false,
+            }],
+            name,
+            layout: String::new(),
+            set_code,
+            collector_number,
+            double_sided: card.double_sided.unwrap_or(false),
+            faces: vec![],
+            related: vec![],
+        }
+    }
+}
+
+/// Rules text of one face of a card, as printed on a text proxy
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CardText {
+    pub name: String,
+    pub mana_cost: String,
+    pub type_line: String,
+    pub oracle_text: String,
+    pub power: Option<String>,
+    pub toughness: Option<String>,
+    pub loyalty: Option<String>,
+}
+
+/// A token, emblem or meld result a card makes, as Scryfall lists it with one printing of the card
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelatedCard {
+    /// Scryfall id of the printing of the token
+    pub id: String,
+    pub name: String,
+    /// Set of the printing of the card that lists the token
+    pub set: String,
 }
 
 #[derive(Debug)]
 pub struct CardVariant {
     pub set: String,
     pub collector_number: String,
+    /// True for printings larger than a standard card
+    pub oversized: bool,
 }
 
 /// PickedCard is a card that has a selected style
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct PickedCard {
     pub quantity: u32,
     pub set_code: String,
     pub collector_number: String,
     pub name: String,
     pub double_sided: bool,
+    pub faces: Vec<CardText>,
+    pub related: Vec<RelatedCard>,
+    /// Scryfall layout of the card, such as `normal` or `planar`
+    pub layout: String,
+    /// True if the picked printing is larger than a standard card
+    pub oversized: bool,
+    /// Name of the deck file the card came from, without its extension
+    pub deck: String,
+}
+
+impl PickedCard {
+    /// Copies of a single faced card printed as [M21:176], from the deck stompy
+    pub fn new_for_test(name: &str, quantity: u32) -> PickedCard {
+        PickedCard {
+            quantity,
+            set_code: "m21".to_string(),
+            collector_number: "176".to_string(),
+            name: name.to_string(),
+            deck: "stompy".to_string(),
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -66,20 +150,39 @@ impl DehydratedDeck {
     }
 
     pub fn as_hydrated(self, data_repository: &DataRepository) -> HydratedDeck {
+        self.as_hydrated_with_custom(data_repository, |_| false)
+            .expect("Expected the data repository to have data")
+    }
+
+    /// Hydrate the deck, keeping cards Scryfall does not know as custom cards when `is_custom`
+    /// says there is artwork to print them with
+    pub fn as_hydrated_with_custom<F: Fn(&DehydratedCard) -> bool>(
+        self,
+        data_repository: &DataRepository,
+        is_custom: F,
+    ) -> Result<HydratedDeck, String> {
         let mut hydrated_cards = BTreeMap::new();
         for (section, dehydrated_cards) in self.cards {
             let mut cards = vec![];
             for dehydrated_card in dehydrated_cards {
-                let hydrated_card = data_repository
-                    .get(dehydrated_card)
-                    .expect("Expected the data repository to have data");
+                let hydrated_card = match is_custom(&dehydrated_card) {
+                    true => data_repository
+                        .get(dehydrated_card.clone())
+                        .unwrap_or_else(|_| HydratedCard::custom(dehydrated_card)),
+                    false => {
+                        let name = dehydrated_card.name.clone();
+                        data_repository
+                            .get(dehydrated_card)
+                            .map_err(|_| format!("Could not find card data for '{}'", name))?
+                    }
+                };
                 cards.push(hydrated_card);
             }
             hydrated_cards.insert(section, cards);
         }
-        HydratedDeck {
+        Ok(HydratedDeck {
             cards: hydrated_cards,
-        }
+        })
     }
 }
 
@@ -113,12 +216,22 @@ impl HydratedDeck {
                         first_variant.collector_number.clone(),
                     ),
                 };
+                let oversized = card
+                    .variants
+                    .iter()
+                    .find(|variant| variant.set == set && variant.collector_number == num)
+                    .is_some_and(|variant| variant.oversized);
                 cards.push(PickedCard {
                     quantity: card.quantity,
                     set_code: set,
                     collector_number: num,
                     name: card.name,
                     double_sided: card.double_sided,
+                    faces: card.faces,
+                    related: card.related,
+                    layout: card.layout,
+                    oversized,
+                    deck: String::new(),
                 })
             }
             picked_cards.insert(section, cards);
@@ -129,13 +242,112 @@ impl HydratedDeck {
     }
 }
 
+#[derive(Debug, Default)]
 pub struct PickedDeck {
     pub cards: BTreeMap<String, Vec<PickedCard>>,
 }
 
+impl PickedDeck {
+    pub fn new() -> PickedDeck {
+        PickedDeck {
+            cards: BTreeMap::new(),
+        }
+    }
+
+    /// Mark every card as coming from the named deck
+    pub fn with_origin(mut self, deck: &str) -> PickedDeck {
+        for card in self.cards.values_mut().flatten() {
+            card.deck = deck.to_string();
+        }
+        self
+    }
+
+    /// Add the cards of another deck after the cards already in each section
+    pub fn merge(&mut self, other: PickedDeck) {
+        for (section, cards) in other.cards {
+            self.cards.entry(section).or_default().extend(cards);
+        }
+    }
+
+    /// Number of cards from each deck, in the order the decks first appear
+    pub fn counts_by_deck(&self) -> Vec<(String, u32)> {
+        let mut counts: Vec<(String, u32)> = vec![];
+        for card in self.cards.values().flatten() {
+            match counts.iter_mut().find(|(deck, _)| *deck == card.deck) {
+                Some((_, count)) => *count += card.quantity,
+                None => counts.push((card.deck.clone(), card.quantity)),
+            }
+        }
+        counts
+    }
+
+    /// Add a section with `copies` of each distinct token, emblem and meld result the deck makes.
+    /// Tokens that cannot be found or have no printable image are skipped with a warning.
+    pub fn with_tokens(
+        mut self,
+        data_repository: &DataRepository,
+        copies: u32,
+    ) -> Result<PickedDeck, String> {
+        let mut tokens = vec![];
+        for made in made_tokens(&self) {
+            let token = match data_repository.get_token(&made.id, copies) {
+                Ok(token) => token,
+                Err(_) => {
+                    println!(
+                        "Warning: could not find a printing of token '{}', skipping it",
+                        made.name
+                    );
+                    continue;
+                }
+            };
+            let Some(printing) = token.variants.first() else {
+                continue;
+            };
+            tokens.push(PickedCard {
+                quantity: copies,
+                set_code: printing.set.clone(),
+                collector_number: printing.collector_number.clone(),
+                name: token.name,
+                double_sided: token.double_sided,
+                faces: token.faces,
+                related: vec![],
+                layout: token.layout,
+                oversized: printing.oversized,
+                deck: String::new(),
+            });
+        }
+        if !tokens.is_empty() {
+            self.cards.insert(TOKENS.to_string(), tokens);
+        }
+        Ok(self)
+    }
+}
+
+/// Each distinct token, emblem and meld result the deck makes, by name. Each is the printing
+/// listed by the same set as the first card that makes it, where that set lists one.
+fn made_tokens(deck: &PickedDeck) -> Vec<&RelatedCard> {
+    let mut made: Vec<&RelatedCard> = vec![];
+    for card in deck.cards.values().flatten() {
+        let names = card.related.iter().map(|related| &related.name);
+        for name in names {
+            if made.iter().any(|token| token.name == *name) {
+                continue;
+            }
+            let listed: Vec<&RelatedCard> = card
+                .related
+                .iter()
+                .filter(|related| related.name == *name)
+                .collect();
+            let same_set = listed.iter().find(|related| related.set == card.set_code);
+            made.push(same_set.unwrap_or(&listed[0]));
+        }
+    }
+    made
+}
+
 pub fn process_input<READ: Read>(read: READ) -> Result<DehydratedDeck, &'static str> {
-    let mut buf_read = BufReader::new(read);
-    let lines: Vec<String> = buf_read.lines().flatten().collect();
+    let buf_read = BufReader::new(read);
+    let lines: Vec<String> = buf_read.lines().map_while(Result::ok).collect();
     if let Ok(deck) = try_xmage(&lines) {
         return Ok(deck);
     }
@@ -172,10 +384,7 @@ fn try_xmage(lines: &[String]) -> Result<DehydratedDeck, ()> {
         if line.trim().is_empty() {
             continue;
         }
-        let card = try_xmage_line(line, &re)
-            .map_err(|e| format!("Failed for line `{}`", line))
-            .unwrap();
-        match card {
+        match try_xmage_line(line, &re)? {
             XMageCard::Mainboard(c) => {
                 deck.cards
                     .entry(MAINBOARD.to_string())
@@ -201,14 +410,7 @@ fn try_xmage_line(line: &str, re: &Regex) -> Result<XMageCard, ()> {
             let quantity = &c[2];
             let set_code = &c[3];
             let collector_number = &c[4];
-            let mut name = c[5].trim().to_string();
-            let mut flip_name = None;
-            if name.contains("//") {
-                let split = name.split("//").collect::<Vec<&str>>();
-                assert_eq!(split.len(), 2);
-                flip_name = Some(split[1].trim().to_string());
-                name = split[0].trim().to_string();
-            }
+            let (name, flip_name) = split_flip_name(&c[5])?;
             let double_sided = match flip_name.is_some() {
                 true => Some(true),
                 false => None,
@@ -244,19 +446,73 @@ enum XMageCard {
     Sideboard(DehydratedCard),
 }
 
+/// `Front // Back` names a double faced card by both its faces
+fn split_flip_name(name: &str) -> Result<(String, Option<String>), ()> {
+    match name.split("//").collect::<Vec<&str>>()[..] {
+        [name] => Ok((name.trim().to_string(), None)),
+        [name, flip_name] => Ok((name.trim().to_string(), Some(flip_name.trim().to_string()))),
+        _ => Err(()),
+    }
+}
+
 /// Try processing the line of input as Magic the Gathering: Online format
 fn try_mtgo(lines: &[String]) -> Result<DehydratedDeck, ()> {
-    Err(())
+    // <quantity> <Name>, with the sideboard after a blank line or a `Sideboard` line. Lines
+    // ending in `(SET) NUMBER` are Arena's.
+    let re = Regex::new(r"^(\d+)x?\s+(.+)$").unwrap();
+    let arena = Regex::new(r"\([A-Z0-9]+\)\s+\S+$").unwrap();
+    let mut deck = DehydratedDeck {
+        cards: BTreeMap::new(),
+    };
+    let mut section = MAINBOARD;
+    for line in lines {
+        let line = line.trim();
+        if line.trim_end_matches(':').eq_ignore_ascii_case(SIDEBOARD) {
+            section = SIDEBOARD;
+            continue;
+        }
+        if line.is_empty() {
+            if !deck.cards.is_empty() {
+                section = SIDEBOARD;
+            }
+            continue;
+        }
+        let c = re.captures(line).ok_or(())?;
+        if arena.is_match(line) {
+            return Err(());
+        }
+        let (name, flip_name) = split_flip_name(&c[2])?;
+        let double_sided = flip_name.is_some().then_some(true);
+        deck.cards
+            .entry(section.to_string())
+            .or_default()
+            .push(DehydratedCard {
+                quantity: c[1].parse().map_err(|_| ())?,
+                set_code: None,
+                collector_number: None,
+                name,
+                flip_name,
+                double_sided,
+            });
+    }
+    match deck.cards.is_empty() {
+        true => Err(()),
+        false => Ok(deck),
+    }
 }
 
 /// Try processing the line of input as Magic the Gathering: Arena format
-fn try_mtga(lines: &[String]) -> Result<DehydratedDeck, ()> {
+fn try_mtga(_lines: &[String]) -> Result<DehydratedDeck, ()> {
     Err(())
 }
 
 #[cfg(test)]
 mod test {
-    use crate::deck::{process_input, MAINBOARD};
+    use crate::deck::{
+        made_tokens, process_input, HydratedCard, PickedCard, PickedDeck, RelatedCard, MAINBOARD,
+        SIDEBOARD,
+    };
+    use std::collections::BTreeMap;
     use std::io::Cursor;
 
     #[test]
@@ -281,6 +537,85 @@ SB: 1 [AKH:194] Ahn-Crop Champion
         assert_eq!(cards[6].flip_name, Some("Explosion".to_string()));
     }
 
+    #[test]
+    pub fn test_custom_card() {
+        let input = "2 [CUS:7] Goblin Guide // Goblin Tour Guide";
+        let processed = process_input(Cursor::new(input)).unwrap();
+        let card = processed.cards.get(MAINBOARD).unwrap()[0].clone();
+        let hydrated = HydratedCard::custom(card);
+        assert_eq!(hydrated.name, "Goblin Guide // Goblin Tour Guide");
+        assert_eq!(hydrated.set_code, "cus");
+        assert_eq!(hydrated.collector_number, "7");
+        assert!(hydrated.double_sided);
+        assert_eq!(hydrated.variants.len(), 1);
+    }
+
+    #[test]
+    pub fn test_merge_decks() {
+        let deck = |sections: &[(&str, &str, u32)]| {
+            let mut deck = PickedDeck::new();
+            for (section, name, quantity) in sections {
+                deck.cards
+                    .entry(section.to_string())
+                    .or_default()
+                    .push(PickedCard {
+                        quantity: *quantity,
+                        name: name.to_string(),
+                        ..Default::default()
+                    });
+            }
+            deck
+        };
+        let mut merged = PickedDeck::new();
+        merged.merge(deck(&[(MAINBOARD, "Opt", 4), (SIDEBOARD, "Negate", 2)]).with_origin("blue"));
+        merged.merge(deck(&[(MAINBOARD, "Shock", 3)]).with_origin("red"));
+        let mainboard: Vec<_> = merged.cards[MAINBOARD]
+            .iter()
+            .map(|card| (card.name.as_str(), card.deck.as_str()))
+            .collect();
+        assert_eq!(mainboard, vec![("Opt", "blue"), ("Shock", "red")]);
+        assert_eq!(
+            merged.counts_by_deck(),
+            vec![("blue".to_string(), 6), ("red".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    pub fn test_made_tokens() {
+        let related = |id: &str, name: &str, set: &str| RelatedCard {
+            id: id.to_string(),
+            name: name.to_string(),
+            set: set.to_string(),
+        };
+        let card = |set: &str, related: Vec<RelatedCard>| PickedCard {
+            quantity: 1,
+            set_code: set.to_string(),
+            related,
+            ..Default::default()
+        };
+        let mut cards = BTreeMap::new();
+        cards.insert(
+            MAINBOARD.to_string(),
+            vec![
+                card(
+                    "m21",
+                    vec![
+                        related("a", "Dinosaur", "znr"),
+                        related("b", "Dinosaur", "m21"),
+                    ],
+                ),
+                card("rix", vec![related("c", "Dinosaur", "rix")]),
+                card("war", vec![related("d", "Saheeli", "mh1")]),
+            ],
+        );
+        let deck = PickedDeck { cards };
+        let ids: Vec<&str> = made_tokens(&deck)
+            .iter()
+            .map(|token| token.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["b", "d"]);
+    }
+
     #[test]
     pub fn test_mtgo() {
         let input = r#"
@@ -288,15 +623,23 @@ SB: 1 [AKH:194] Ahn-Crop Champion
 1 Murderous Rider
 1 Aurelia, Exemplar of Justice
 1 Circle of Protection: Black
-1 Ahn-Crop Champion
+4 Expansion // Explosion
 
 1 Ahn-Crop Champion
         "#;
+        let processed = process_input(Cursor::new(input)).unwrap();
+        let cards = processed.cards.get(MAINBOARD).unwrap();
+        assert_eq!(cards.len(), 5);
+        assert_eq!(cards[0].set_code, None);
+        assert_eq!(cards[4].quantity, 4);
+        assert_eq!(cards[4].flip_name, Some("Explosion".to_string()));
+        assert_eq!(processed.cards[SIDEBOARD][0].name, "Ahn-Crop Champion");
+        assert!(process_input(Cursor::new("Tavern Ruffian")).is_err());
     }
 
     #[test]
     pub fn test_mtga() {
-        let input = r#"
+        let _input = r#"
 1 Tavern Ruffian (MID) 163
 1 Murderous Rider (ELD) 287
 1 Aurelia, Exemplar of Justice (GRN) 153
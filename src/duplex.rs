@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{create_dir_all, File};
+use std::path::{Path, PathBuf};
+
+pub const PRINTER_PROFILE_DIRECTORY: &str = "printer_profiles";
+
+/// Which edge of the sheet the printer turns it over on when printing the back
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+pub enum DuplexFlip {
+    LongEdge,
+    ShortEdge,
+}
+
+/// How the back page is placed relative to the front page
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Duplex {
+    /// None mirrors the back page left to right whichever way the page is turned, as before
+    /// duplex settings existed
+    pub flip: Option<DuplexFlip>,
+    /// Turn the whole back page by 180°, for printers that feed the sheet back in upside down
+    pub rotate_back: bool,
+    /// Horizontal shift of the back page in mm, positive moves it right
+    pub back_offset_x: f32,
+    /// Vertical shift of the back page in mm, positive moves it up
+    pub back_offset_y: f32,
+}
+
+impl Duplex {
+    /// Load the duplex settings saved for a printer, or the defaults if there are none yet
+    pub fn load(printer: &str) -> Result<Duplex, String> {
+        let path = profile_path(printer);
+        if !path.exists() {
+            return Ok(Duplex::default());
+        }
+        let file = File::open(&path)
+            .map_err(|e| format!("Could not open printer profile {:?}: {}", path, e))?;
+        serde_json::from_reader(file)
+            .map_err(|e| format!("Could not read printer profile {:?}: {}", path, e))
+    }
+
+    /// Save the duplex settings so later runs for the same printer pick them up
+    pub fn save(&self, printer: &str) -> Result<(), String> {
+        let path = profile_path(printer);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).map_err(|e| format!("Could not create directory: {}", e))?;
+        }
+        let file = File::create(&path)
+            .map_err(|e| format!("Could not create printer profile {:?}: {}", path, e))?;
+        serde_json::to_writer_pretty(file, self)
+            .map_err(|e| format!("Could not write printer profile {:?}: {}", path, e))
+    }
+
+    /// True if turning the sheet over mirrors it left to right, false if it mirrors top to bottom
+    pub fn mirrors_horizontally(&self, portrait: bool) -> bool {
+        match self.flip {
+            Some(flip) => (flip == DuplexFlip::LongEdge) == portrait,
+            None => true,
+        }
+    }
+}
+
+fn profile_path(printer: &str) -> PathBuf {
+    Path::new(PRINTER_PROFILE_DIRECTORY).join(format!("{}.json", printer))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::duplex::{Duplex, DuplexFlip};
+
+    #[test]
+    pub fn test_profile_round_trip() {
+        let duplex = Duplex {
+            flip: Some(DuplexFlip::ShortEdge),
+            rotate_back: true,
+            back_offset_x: 1.5,
+            back_offset_y: -0.5,
+        };
+        let json = serde_json::to_string(&duplex).unwrap();
+        let read: Duplex = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, duplex);
+        assert!(!duplex.mirrors_horizontally(true));
+        assert!(duplex.mirrors_horizontally(false));
+        assert!(Duplex::default().mirrors_horizontally(false));
+    }
+}
@@ -0,0 +1,329 @@
+use crate::bleed::add_bleed;
+use crate::card_size::CardSize;
+use crate::deck::{PickedCard, PickedDeck};
+use crate::image_repository::ImageRepository;
+use crate::pdf_calc::pixels_at_dpi;
+use crate::render::{prepare_card_image, RenderOptions};
+use printpdf::image_crate::imageops::FilterType;
+use printpdf::image_crate::{self, DynamicImage};
+use printpdf::Mm;
+use std::fs::{create_dir_all, write, File};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Name of the file listing which card is in which image
+const MANIFEST: &str = "manifest.csv";
+
+/// What the print run is written out as
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Sheets of cards in a PDF
+    #[default]
+    Pdf,
+    /// A directory of numbered card images with a manifest
+    Images,
+    /// The numbered card images and the manifest in one ZIP file
+    Zip,
+}
+
+/// File format of exported card images
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+        }
+    }
+
+    fn encode(&self, image: &DynamicImage) -> Result<Vec<u8>, String> {
+        let mut bytes = vec![];
+        let result = match self {
+            ImageFormat::Png => {
+                image.write_to(&mut Cursor::new(&mut bytes), image_crate::ImageFormat::Png)
+            }
+            // JPEG has no transparency
+            ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_to(&mut Cursor::new(&mut bytes), image_crate::ImageFormat::Jpeg),
+        };
+        result.map_err(|e| format!("Could not encode image: {}", e))?;
+        Ok(bytes)
+    }
+}
+
+/// How card images are written when exporting them instead of a PDF
+#[derive(Debug, Copy, Clone)]
+pub struct ImageExport {
+    pub format: ImageFormat,
+    /// Resolution every image is scaled to, so the card comes out at its real size
+    pub dpi: f32,
+    /// Bundle the images and the manifest into a ZIP file instead of a directory
+    pub zip: bool,
+}
+
+/// Pixel size of an exported card image: the card itself, and the bleed added on every side
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ImagePixels {
+    pub width: u32,
+    pub height: u32,
+    pub bleed: u32,
+}
+
+impl ImagePixels {
+    pub fn at_dpi(size: &CardSize, bleed: Mm, dpi: f32) -> ImagePixels {
+        ImagePixels {
+            width: pixels_at_dpi(size.width, dpi),
+            height: pixels_at_dpi(size.height, dpi),
+            bleed: pixels_at_dpi(bleed, dpi),
+        }
+    }
+}
+
+/// One card of the print run, written as a front and a back image however many copies are wanted
+#[derive(Debug)]
+pub struct ExportEntry<'a> {
+    pub number: usize,
+    pub section: &'a str,
+    pub card: &'a PickedCard,
+}
+
+impl ExportEntry<'_> {
+    fn file_name(&self, side: &str, format: ImageFormat) -> String {
+        format!("{:03}_{}.{}", self.number, side, format.extension())
+    }
+}
+
+/// Where exported files go: a directory, or a ZIP file being written
+pub enum ExportTarget {
+    Directory(PathBuf),
+    Zip(ZipWriter<File>),
+}
+
+impl ExportTarget {
+    pub fn new(path: &Path, zip: bool) -> Result<ExportTarget, String> {
+        match zip {
+            true => {
+                let file = File::create(path)
+                    .map_err(|e| format!("Could not create {:?}: {}", path, e))?;
+                Ok(ExportTarget::Zip(ZipWriter::new(file)))
+            }
+            false => {
+                create_dir_all(path)
+                    .map_err(|e| format!("Could not create directory {:?}: {}", path, e))?;
+                Ok(ExportTarget::Directory(path.to_path_buf()))
+            }
+        }
+    }
+
+    /// Add a file, whose name may start with the directories it goes in
+    pub fn add(&mut self, name: &str, bytes: &[u8]) -> Result<(), String> {
+        match self {
+            ExportTarget::Directory(directory) => {
+                let path = directory.join(name);
+                if let Some(parent) = path.parent() {
+                    create_dir_all(parent)
+                        .map_err(|e| format!("Could not create directory {:?}: {}", parent, e))?;
+                }
+                write(&path, bytes).map_err(|e| format!("Could not write {}: {}", name, e))
+            }
+            ExportTarget::Zip(zip) => {
+                // The images are compressed already
+                let options =
+                    SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+                zip.start_file(name, options)
+                    .and_then(|_| zip.write_all(bytes).map_err(Into::into))
+                    .map_err(|e| format!("Could not add {} to the ZIP file: {}", name, e))
+            }
+        }
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        if let ExportTarget::Zip(zip) = self {
+            zip.finish()
+                .map_err(|e| format!("Could not finish the ZIP file: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Every card of the deck in print order, numbered from 1
+pub fn export_entries(deck: &PickedDeck) -> Vec<ExportEntry<'_>> {
+    deck.cards
+        .iter()
+        .flat_map(|(section, cards)| cards.iter().map(move |card| (section.as_str(), card)))
+        .enumerate()
+        .map(|(index, (section, card))| ExportEntry {
+            number: index + 1,
+            section,
+            card,
+        })
+        .collect()
+}
+
+/// Quote a CSV field if it holds a comma, a quote or a line break
+pub fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+/// A CSV mapping each image file to the card on it and how many copies of it to print
+fn manifest(entries: &[ExportEntry], format: ImageFormat) -> String {
+    let mut csv = String::from("front,back,name,set,collector_number,quantity,section,deck\n");
+    for entry in entries {
+        let card = entry.card;
+        let fields = [
+            entry.file_name("front", format),
+            entry.file_name("back", format),
+            card.name.clone(),
+            card.set_code.to_uppercase(),
+            card.collector_number.clone(),
+            card.quantity.to_string(),
+            entry.section.to_string(),
+            card.deck.clone(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Check the print run asks for nothing that is only drawn onto PDF pages
+pub fn check_options(options: &RenderOptions) -> Result<(), String> {
+    let pdf_only: Vec<&str> = [
+        (options.text_proxies, "text proxies"),
+        (options.watermark.is_some(), "watermarks"),
+        (options.checklist, "checklists"),
+        (options.dividers, "dividers"),
+        (options.deck_tags, "deck tags"),
+    ]
+    .iter()
+    .filter(|(wanted, _)| *wanted)
+    .map(|(_, name)| *name)
+    .collect();
+    match pdf_only.is_empty() {
+        true => Ok(()),
+        false => Err(format!("Only PDF output can have {}", pdf_only.join(", "))),
+    }
+}
+
+/// The back image set for a single faced card in place of the shared one, if there is one
+pub fn back_override(options: &RenderOptions, entry: &ExportEntry) -> Result<Option<File>, String> {
+    if entry.card.double_sided {
+        return Ok(None);
+    }
+    options
+        .card_backs
+        .back_for(entry.section, entry.card)
+        .map(|path| {
+            File::open(path).map_err(|e| format!("Could not open card back {:?}: {}", path, e))
+        })
+        .transpose()
+}
+
+/// Scale a card image of the given size to exactly `pixels` and add the bleed
+pub fn export_image(
+    file: &mut File,
+    size: &CardSize,
+    pixels: ImagePixels,
+    art: bool,
+    options: &RenderOptions,
+    format: ImageFormat,
+) -> Result<Vec<u8>, String> {
+    let image = prepare_card_image(file, size, art, options)?;
+    let image = image.resize_exact(pixels.width, pixels.height, FilterType::Lanczos3);
+    format.encode(&add_bleed(&image, pixels.bleed, options.bleed_fill))
+}
+
+/// Write the front and back of every card of the deck as numbered image files, with a manifest
+/// CSV saying which card each file holds and how many copies of it to print
+pub fn export_images(
+    deck: &PickedDeck,
+    image_repo: &ImageRepository,
+    options: &RenderOptions,
+    export: &ImageExport,
+    path: &Path,
+) -> Result<(), String> {
+    check_options(options)?;
+    let entries = export_entries(deck);
+    let mut target = ExportTarget::new(path, export.zip)?;
+    for entry in &entries {
+        let card = entry.card;
+        println!(
+            "[{}/{}] Exporting card {} [{}:{}]",
+            entry.number,
+            entries.len(),
+            card.name,
+            card.set_code,
+            card.collector_number
+        );
+        let (mut front, back) = image_repo.get_image(card)?;
+        let mut back = back_override(options, entry)?.unwrap_or(back);
+        let size = options.card_sizes.size_for(entry.section, card);
+        let pixels = ImagePixels::at_dpi(&size, options.layout.bleed(), export.dpi);
+        let front = export_image(&mut front, &size, pixels, true, options, export.format)?;
+        target.add(&entry.file_name("front", export.format), &front)?;
+        let art = card.double_sided;
+        let back = export_image(&mut back, &size, pixels, art, options, export.format)?;
+        target.add(&entry.file_name("back", export.format), &back)?;
+    }
+    target.add(MANIFEST, manifest(&entries, export.format).as_bytes())?;
+    target.finish()?;
+    println!("Wrote {} cards to {}", entries.len(), path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::deck::{PickedCard, PickedDeck, MAINBOARD, SIDEBOARD};
+    use crate::export::{csv_field, export_entries, manifest, ImageFormat};
+    use std::collections::BTreeMap;
+
+    #[test]
+    pub fn test_manifest() {
+        let mut cards = BTreeMap::new();
+        cards.insert(
+            MAINBOARD.to_string(),
+            vec![
+                PickedCard::new_for_test("Colossal Dreadmaw", 4),
+                PickedCard::new_for_test("Ghalta, Primal Hunger", 1),
+            ],
+        );
+        cards.insert(
+            SIDEBOARD.to_string(),
+            vec![PickedCard::new_for_test("Llanowar Elves", 2)],
+        );
+        let deck = PickedDeck { cards };
+        let entries = export_entries(&deck);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[2].file_name("back", ImageFormat::Jpeg),
+            "003_back.jpg"
+        );
+        assert_eq!(
+            manifest(&entries[..2], ImageFormat::Png),
+            "front,back,name,set,collector_number,quantity,section,deck\n\
+             001_front.png,001_back.png,Colossal Dreadmaw,M21,176,4,Mainboard,stompy\n\
+             002_front.png,002_back.png,\"Ghalta, Primal Hunger\",M21,176,1,Mainboard,stompy\n"
+        );
+    }
+
+    #[test]
+    pub fn test_csv_field() {
+        assert_eq!(csv_field("Opt"), "Opt");
+        assert_eq!(
+            csv_field("\"Ach! Hans, Run!\""),
+            "\"\"\"Ach! Hans, Run!\"\"\""
+        );
+    }
+}
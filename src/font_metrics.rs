@@ -0,0 +1,57 @@
+use printpdf::{BuiltinFont, Mm, Pt};
+
+/// Advance widths of the printable ASCII characters, space to tilde, in 1/1000 em, from the
+/// Adobe font metrics of the standard 14 fonts
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667,
+    611, 778, 722, 278, 556, 722, 611, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 333, 278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556,
+    278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+/// Width used for characters outside the table
+const DEFAULT_WIDTH: u16 = 556;
+
+/// Width of a line of text set in a built in Helvetica font at `size` points. Other built in
+/// fonts are measured as Helvetica, which is close enough for laying out labels.
+pub fn text_width(text: &str, font: BuiltinFont, size: f32) -> Mm {
+    let widths = match font {
+        BuiltinFont::HelveticaBold | BuiltinFont::HelveticaBoldOblique => &HELVETICA_BOLD_WIDTHS,
+        _ => &HELVETICA_WIDTHS,
+    };
+    let units: u32 = text
+        .chars()
+        .map(|c| match c as usize {
+            c @ 32..=126 => widths[c - 32] as u32,
+            _ => DEFAULT_WIDTH as u32,
+        })
+        .sum();
+    Mm::from(Pt(units as f32 * size / 1000.0))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::font_metrics::text_width;
+    use printpdf::{BuiltinFont, Mm};
+
+    #[test]
+    pub fn test_text_width() {
+        // 1000 units at 72pt is an inch
+        assert_eq!(
+            text_width("MM", BuiltinFont::HelveticaBold, 72.0 / 1.666),
+            Mm(25.4)
+        );
+        assert!(
+            text_width("proxy", BuiltinFont::HelveticaBold, 10.0)
+                > text_width("proxy", BuiltinFont::Helvetica, 10.0)
+        );
+        assert_eq!(text_width("", BuiltinFont::Helvetica, 10.0), Mm(0.0));
+    }
+}
@@ -0,0 +1,295 @@
+use crate::layout::{Layout, Rect};
+use crate::packing::Packing;
+use printpdf::{Line, Mm, Point, Pt};
+
+/// Length of a crop mark
+const CROP_MARK_LENGTH: Mm = Mm(5.0);
+/// Space left between the card grid and the start of a crop mark
+const CROP_MARK_OFFSET: Mm = Mm(1.0);
+
+/// How the cut lines are marked on the page
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum CutGuides {
+    /// Lines across the whole page along every cut
+    #[default]
+    Lines,
+    /// Short ticks outside the card grid, in line with every cut. Pages mixing card sizes get
+    /// lines, as there is no room for ticks between rows of different cards.
+    CropMarks,
+    /// No guides at all
+    None,
+}
+
+/// Build the cutting guides for a page of the given layout
+pub fn guide_lines(layout: &Layout, guides: CutGuides, back: bool) -> Vec<Line> {
+    let lines = match guides {
+        CutGuides::Lines => full_lines(layout),
+        CutGuides::CropMarks => crop_marks(layout),
+        CutGuides::None => vec![],
+    };
+    match back {
+        true => shift_to_back(lines, layout),
+        false => lines,
+    }
+}
+
+/// Build the cutting guides for one page of a print run that may mix card sizes. Pages of one
+/// size get the guides of its layout, and mixed pages get a line along every row and lines
+/// between the cards of each row.
+pub fn page_guide_lines(
+    layouts: &[Layout],
+    packing: &Packing,
+    page: usize,
+    guides: CutGuides,
+    back: bool,
+) -> Vec<Line> {
+    if let Some(layout) = packing.page_layout(page) {
+        return guide_lines(&layouts[layout], guides, back);
+    }
+    if guides == CutGuides::None {
+        return vec![];
+    }
+    let layout = &layouts[0];
+    let mut lines = vec![];
+    for band in packing.bands_on(page) {
+        let band_layout = &layouts[band.layout];
+        let (bottom, top) = (band.top - band_layout.row_height(), band.top);
+        let mut xs: Vec<Mm> = vec![];
+        for column in 0..band.cards {
+            let card = band_layout.slot_in_row(page, column, band.top).front;
+            xs.push(card.x);
+            xs.push(card.right());
+            if column == 0 {
+                for y in [card.y, card.top()] {
+                    lines.push(line((Mm(0.0), y), (layout.page_width(), y)));
+                }
+            }
+        }
+        xs.dedup();
+        for x in xs {
+            lines.push(line((x, bottom), (x, top)));
+        }
+    }
+    if !back {
+        return lines;
+    }
+    // Rows are stacked from the top, so are only symmetric side to side. Each point goes to
+    // wherever the sheet turning over puts it.
+    for line in lines.iter_mut() {
+        for (point, _) in line.points.iter_mut() {
+            let front = Rect::new(Mm::from(point.x), Mm::from(point.y), Mm(0.0), Mm(0.0));
+            let back = layout.back_of(&front);
+            *point = Point::new(back.x, back.y);
+        }
+    }
+    lines
+}
+
+/// Cut the parts of guides that cross any of `zones` out of them, such as the space around the
+/// registration marks of a cutting machine. Only horizontal and vertical lines are cut.
+pub fn clip_lines(lines: Vec<Line>, zones: &[Rect]) -> Vec<Line> {
+    let mut clipped = vec![];
+    for line in lines {
+        let (from, to) = match line.points[..] {
+            [(from, _), (to, _)] if from.x == to.x || from.y == to.y => (from, to),
+            _ => {
+                clipped.push(line);
+                continue;
+            }
+        };
+        // Spans along the line, at `across` on the other axis
+        let vertical = from.x == to.x;
+        let (across, start, end) = match vertical {
+            true => (from.x.0, from.y.0.min(to.y.0), from.y.0.max(to.y.0)),
+            false => (from.y.0, from.x.0.min(to.x.0), from.x.0.max(to.x.0)),
+        };
+        let mut spans = vec![(start, end)];
+        for zone in zones {
+            let (low, high, zone_start, zone_end) = match vertical {
+                true => (zone.x, zone.right(), zone.y, zone.top()),
+                false => (zone.y, zone.top(), zone.x, zone.right()),
+            };
+            if across <= low.into_pt().0 || across >= high.into_pt().0 {
+                continue;
+            }
+            let (zone_start, zone_end) = (zone_start.into_pt().0, zone_end.into_pt().0);
+            spans = spans
+                .into_iter()
+                .flat_map(|(a, b)| [(a, b.min(zone_start)), (a.max(zone_end), b)])
+                .filter(|(a, b)| a < b)
+                .collect();
+        }
+        let point = |along: f32| match vertical {
+            true => Point {
+                x: Pt(across),
+                y: Pt(along),
+            },
+            false => Point {
+                x: Pt(along),
+                y: Pt(across),
+            },
+        };
+        for (a, b) in spans {
+            clipped.push(Line {
+                points: vec![(point(a), false), (point(b), false)],
+                is_closed: false,
+            });
+        }
+    }
+    clipped
+}
+
+/// Move guides drawn for the front page by the back page offset. The grid is symmetric, so that
+/// is all the back page needs.
+fn shift_to_back(lines: Vec<Line>, layout: &Layout) -> Vec<Line> {
+    let offset = Point::new(
+        Mm(layout.duplex().back_offset_x),
+        Mm(layout.duplex().back_offset_y),
+    );
+    lines
+        .into_iter()
+        .map(|mut line| {
+            for (point, _) in line.points.iter_mut() {
+                point.x.0 += offset.x.0;
+                point.y.0 += offset.y.0;
+            }
+            line
+        })
+        .collect()
+}
+
+fn line(from: (Mm, Mm), to: (Mm, Mm)) -> Line {
+    Line {
+        points: vec![
+            (Point::new(from.0, from.1), false),
+            (Point::new(to.0, to.1), false),
+        ],
+        is_closed: false,
+    }
+}
+
+fn full_lines(layout: &Layout) -> Vec<Line> {
+    let (xs, ys) = layout.cut_positions();
+    let vertical = xs
+        .into_iter()
+        .map(|x| line((x, Mm(0.0)), (x, layout.page_height())));
+    let horizontal = ys
+        .into_iter()
+        .map(|y| line((Mm(0.0), y), (layout.page_width(), y)));
+    vertical.chain(horizontal).collect()
+}
+
+fn crop_marks(layout: &Layout) -> Vec<Line> {
+    let grid = layout.grid_area();
+    let (xs, ys) = layout.cut_positions();
+    // Marks are clipped to the page, and dropped if there is no room for them at all
+    let bottom = (
+        grid.y - CROP_MARK_OFFSET,
+        grid.y - CROP_MARK_OFFSET - CROP_MARK_LENGTH,
+    );
+    let top = (
+        grid.top() + CROP_MARK_OFFSET,
+        grid.top() + CROP_MARK_OFFSET + CROP_MARK_LENGTH,
+    );
+    let left = (
+        grid.x - CROP_MARK_OFFSET,
+        grid.x - CROP_MARK_OFFSET - CROP_MARK_LENGTH,
+    );
+    let right = (
+        grid.right() + CROP_MARK_OFFSET,
+        grid.right() + CROP_MARK_OFFSET + CROP_MARK_LENGTH,
+    );
+    let clip_y = |(from, to): (Mm, Mm)| clip(from, to, layout.page_height());
+    let clip_x = |(from, to): (Mm, Mm)| clip(from, to, layout.page_width());
+
+    let mut lines = vec![];
+    for x in xs {
+        for (from, to) in [bottom, top].into_iter().filter_map(clip_y) {
+            lines.push(line((x, from), (x, to)));
+        }
+    }
+    for y in ys {
+        for (from, to) in [left, right].into_iter().filter_map(clip_x) {
+            lines.push(line((from, y), (to, y)));
+        }
+    }
+    lines
+}
+
+/// Clip a span to the page, returning None if the span starts off the page
+fn clip(from: Mm, to: Mm, page: Mm) -> Option<(Mm, Mm)> {
+    if from.0 <= 0.0 || from.0 >= page.0 {
+        return None;
+    }
+    Some((from, Mm(to.0.clamp(0.0, page.0))))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::duplex::Duplex;
+    use crate::guides::{clip_lines, guide_lines, line, CutGuides};
+    use crate::layout::{Layout, Rect};
+    use crate::pdf_calc::PageSpec;
+    use printpdf::{Mm, Point};
+
+    #[test]
+    pub fn test_guides() {
+        let layout = Layout::new(&PageSpec::default());
+        // 4 vertical and 4 horizontal cuts for a 3x3 grid
+        assert_eq!(guide_lines(&layout, CutGuides::Lines, false).len(), 8);
+        // One mark at each end of every cut
+        let marks = guide_lines(&layout, CutGuides::CropMarks, false);
+        assert_eq!(marks.len(), 16);
+        let first = &marks[0].points;
+        assert_eq!(first[0].0, Point::new(Mm(10.5), Mm(15.5)));
+        assert_eq!(first[1].0, Point::new(Mm(10.5), Mm(10.5)));
+        assert!(guide_lines(&layout, CutGuides::None, false).is_empty());
+
+        let duplex = Duplex {
+            back_offset_x: 2.0,
+            ..Default::default()
+        };
+        let shifted = Layout::new(&PageSpec::default()).with_duplex(duplex);
+        let back = guide_lines(&shifted, CutGuides::CropMarks, true);
+        assert_eq!(back[0].points[0].0, Point::new(Mm(12.5), Mm(15.5)));
+
+        // Cards right up against the page edge leave no room for marks beside them
+        let layout = Layout::new(&PageSpec::default()).with_columns(Some(3));
+        let layout = layout.with_gutter(Mm(10.5));
+        assert_eq!(guide_lines(&layout, CutGuides::CropMarks, false).len(), 12);
+    }
+
+    #[test]
+    pub fn test_clip_lines() {
+        let zone = Rect::new(Mm(5.0), Mm(40.0), Mm(10.0), Mm(10.0));
+        let lines = vec![
+            line((Mm(10.0), Mm(5.0)), (Mm(10.0), Mm(100.0))),
+            line((Mm(5.0), Mm(60.0)), (Mm(100.0), Mm(60.0))),
+        ];
+        let clipped = clip_lines(lines, &[zone]);
+        assert_eq!(clipped.len(), 3);
+        let ends = |index: usize| (clipped[index].points[0].0, clipped[index].points[1].0);
+        assert_eq!(
+            ends(0),
+            (
+                Point::new(Mm(10.0), Mm(5.0)),
+                Point::new(Mm(10.0), Mm(40.0))
+            )
+        );
+        assert_eq!(
+            ends(1),
+            (
+                Point::new(Mm(10.0), Mm(50.0)),
+                Point::new(Mm(10.0), Mm(100.0))
+            )
+        );
+        // Lines that miss the zone are kept whole
+        assert_eq!(
+            ends(2),
+            (
+                Point::new(Mm(5.0), Mm(60.0)),
+                Point::new(Mm(100.0), Mm(60.0))
+            )
+        );
+    }
+}
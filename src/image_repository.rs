@@ -1,5 +1,6 @@
+use crate::artwork::Artwork;
 use crate::deck::PickedCard;
-use crate::scryfall_client::ScryfallClient;
+use crate::scryfall_client::{ImageQuality, ScryfallClient};
 use reqwest::blocking::Response;
 use std::fmt::{Display, Formatter};
 use std::fs::{create_dir_all, File};
@@ -15,6 +16,8 @@ pub struct ImageRepository<'a> {
     path: &'a Path,
     client: ScryfallClient,
     default_back: PathBuf,
+    artwork: Artwork,
+    quality: ImageQuality,
 }
 
 impl<'a> ImageRepository<'a> {
@@ -31,7 +34,8 @@ impl<'a> ImageRepository<'a> {
                 let client = ScryfallClient::new();
                 let default_back = match default_back {
                     None => path.join(DEFAULT_BACK_FILENAME),
-                    Some(s) => s.to_path_buf(),
+                    Some(s) if s.is_file() => s.to_path_buf(),
+                    Some(s) => return Err(format!("Card back {:?} does not exist", s)),
                 };
                 if !default_back.exists() {
                     // let resp = client.get_image("m21", "176", true);
@@ -45,22 +49,71 @@ impl<'a> ImageRepository<'a> {
                     path,
                     client,
                     default_back,
+                    artwork: Artwork::new(),
+                    quality: ImageQuality::default(),
                 })
             }
             false => Err("Provided path was not a directory".to_string()),
         }
     }
 
-    /// Return 2 files for requested image (foreground, background), or error if no such card
+    /// Use local artwork in place of Scryfall images where there is some
+    pub fn with_artwork(mut self, artwork: Artwork) -> Self {
+        self.artwork = artwork;
+        self
+    }
+
+    /// Download and cache images at this quality. Each quality is cached separately.
+    pub fn with_quality(mut self, quality: ImageQuality) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Cache file for one face of a card. Large images keep the plain names used before
+    /// qualities could be picked, so existing caches stay valid.
+    fn cache_path(&self, card: &PickedCard, face: &str) -> PathBuf {
+        let quality = match self.quality {
+            ImageQuality::Large => String::new(),
+            quality => format!("_{}", quality.version()),
+        };
+        self.path.join(format!(
+            "{}_{}{}_{}.{}",
+            card.set_code,
+            card.collector_number,
+            quality,
+            face,
+            self.quality.extension()
+        ))
+    }
+
+    /// Return 2 files for requested image (foreground, background), or error if no such card.
+    /// Artwork overrides are used before anything cached or downloaded from Scryfall.
     pub fn get_image(&self, card: &PickedCard) -> Result<(File, File), String> {
-        let front_path = self.path.join(format!(
-            "{}_{}_front.jpg",
-            card.set_code, card.collector_number
-        ));
-        let back_path = self.path.join(format!(
-            "{}_{}_back.jpg",
-            card.set_code, card.collector_number
-        ));
+        let open = |path: &Path| {
+            File::open(path).map_err(|e| format!("Could not open artwork {:?}: {}", path, e))
+        };
+        let front = self.artwork.front(card).map(open).transpose()?;
+        let back = self.artwork.back(card).map(open).transpose()?;
+        match (front, back) {
+            (Some(front), Some(back)) => Ok((front, back)),
+            (Some(front), None) if !card.double_sided => {
+                let back = File::open(&self.default_back)
+                    .map_err(|e| format!("Could not open card back: {}", e))?;
+                Ok((front, back))
+            }
+            (front, back) => {
+                let (scryfall_front, scryfall_back) = self.get_scryfall_image(card)?;
+                Ok((
+                    front.unwrap_or(scryfall_front),
+                    back.unwrap_or(scryfall_back),
+                ))
+            }
+        }
+    }
+
+    fn get_scryfall_image(&self, card: &PickedCard) -> Result<(File, File), String> {
+        let front_path = self.cache_path(card, "front");
+        let back_path = self.cache_path(card, "back");
         if front_path.exists() && front_path.is_file() {
             if card.double_sided {
                 if back_path.exists() {
@@ -73,7 +126,7 @@ impl<'a> ImageRepository<'a> {
                 }
             } else {
                 let front = File::open(front_path).expect("Expected front image file to open");
-                let back = File::open(self.default_back.clone()).map_err(|e| {
+                let back = File::open(self.default_back.clone()).map_err(|_| {
                     format!(
                         "Unable to open back image for '{}' {} {}",
                         card.name, card.set_code, card.collector_number
@@ -128,16 +181,16 @@ impl<'a> ImageRepository<'a> {
         // TODO check if card has face=back not 422 - https://scryfall.com/docs/api/cards/collector
         // TODO clap args
         // TODO rename front cards to have FRONT; BACK should be generic back (copy) or actual
-        let resp = self
-            .client
-            .get_image(&card.set_code, &card.collector_number, false);
+        let resp =
+            self.client
+                .get_image(&card.set_code, &card.collector_number, false, self.quality);
 
         save_image_response_to_file(resp, front)?;
 
         if let Some(back) = back {
-            let resp = self
-                .client
-                .get_image(&card.set_code, &card.collector_number, true);
+            let resp =
+                self.client
+                    .get_image(&card.set_code, &card.collector_number, true, self.quality);
 
             save_image_response_to_file(resp, back)?;
         }
@@ -154,14 +207,24 @@ pub enum ImageRequestError {
 
 impl Display for ImageRequestError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("{:?}", self))
+        match self {
+            ImageRequestError::NotDoubleSided => f.write_str("Card is not double sided"),
+            ImageRequestError::OtherStatus(status, body) => {
+                f.write_fmt(format_args!("Unexpected status {}: {}", status, body))
+            }
+            ImageRequestError::Other(e) => f.write_str(e),
+        }
     }
 }
 
 fn save_image_response_to_file(resp: Response, file: &mut File) -> Result<(), ImageRequestError> {
     match resp.status().as_u16() {
         200 => {
-            io::copy(&mut resp.bytes().unwrap().as_ref(), file).unwrap();
+            let bytes = resp
+                .bytes()
+                .map_err(|e| ImageRequestError::Other(e.to_string()))?;
+            io::copy(&mut bytes.as_ref(), file)
+                .map_err(|e| ImageRequestError::Other(e.to_string()))?;
             Ok(())
         }
         422 => Err(ImageRequestError::NotDoubleSided),
@@ -172,9 +235,10 @@ fn save_image_response_to_file(resp: Response, file: &mut File) -> Result<(), Im
 #[cfg(test)]
 mod test {
     use crate::data_repository::DataRepository;
-    use crate::deck::{process_input, MAINBOARD};
+    use crate::deck::{process_input, PickedCard, MAINBOARD};
     use crate::image_repository::ImageRepository;
-    use std::fs::File;
+    use crate::scryfall_client::ImageQuality;
+    use std::fs::{remove_dir_all, File};
     use std::io::Cursor;
     use std::path::Path;
 
@@ -202,7 +266,7 @@ mod test {
         let mut front = File::create(Path::new("Test_Temp_Front.jpg")).unwrap();
         let mut back = File::create(Path::new("Test_Temp_Back.jpg")).unwrap();
         img_repo
-            .retrieve_images_from_scryfall(&card, &mut front, Some(&mut back))
+            .retrieve_images_from_scryfall(card, &mut front, Some(&mut back))
             .unwrap()
     }
 
@@ -229,7 +293,33 @@ mod test {
         let img_repo = ImageRepository::new(Path::new("data_repository"), None).unwrap();
         let mut front = File::create(Path::new("Test_Temp_Front.jpg")).unwrap();
         img_repo
-            .retrieve_images_from_scryfall(&card, &mut front, None)
+            .retrieve_images_from_scryfall(card, &mut front, None)
             .unwrap()
     }
+
+    #[test]
+    pub fn test_cache_path_per_quality() {
+        let directory = Path::new("Test_Temp_Quality");
+        let card = PickedCard {
+            quantity: 1,
+            set_code: "m21".to_string(),
+            collector_number: "176".to_string(),
+            name: "Colossal Dreadmaw".to_string(),
+            double_sided: false,
+            ..Default::default()
+        };
+        let repo = ImageRepository::new(directory, None).unwrap();
+        let large = repo.cache_path(&card, "front");
+        let png = repo
+            .clone()
+            .with_quality(ImageQuality::Png)
+            .cache_path(&card, "front");
+        let small = repo
+            .with_quality(ImageQuality::Small)
+            .cache_path(&card, "back");
+        remove_dir_all(directory).unwrap();
+        assert_eq!(large, directory.join("m21_176_front.jpg"));
+        assert_eq!(png, directory.join("m21_176_png_front.png"));
+        assert_eq!(small, directory.join("m21_176_small_back.jpg"));
+    }
 }
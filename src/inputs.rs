@@ -0,0 +1,117 @@
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+
+/// Expand the deck files given on the command line. A `*` or `?` in the file name part of an
+/// input matches any run of characters or any one character, for shells that do not expand
+/// them. Each pattern must match at least one file.
+pub fn expand_inputs(patterns: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut inputs = vec![];
+    for pattern in patterns {
+        let path = Path::new(pattern);
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if !file_name.contains(['*', '?']) {
+            inputs.push(path.to_path_buf());
+            continue;
+        }
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let entries = read_dir(directory)
+            .map_err(|e| format!("Could not read directory {:?}: {}", directory, e))?;
+        let mut matched: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|entry| entry.is_file())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .is_some_and(|name| matches(&file_name, &name.to_string_lossy()))
+            })
+            .collect();
+        if matched.is_empty() {
+            return Err(format!("No deck files match '{}'", pattern));
+        }
+        matched.sort();
+        inputs.extend(matched);
+    }
+    Ok(inputs)
+}
+
+/// Match a file name against a pattern of literal characters, `*` and `?`
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Where to go back to on a mismatch: the last `*` and how much of the name it has taken
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+            }
+            Some(c) if *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use crate::inputs::{expand_inputs, matches};
+    use std::fs::{create_dir_all, remove_dir_all, File};
+    use std::path::Path;
+
+    #[test]
+    pub fn test_matches() {
+        assert!(matches("*.dck", "league.dck"));
+        assert!(matches("league-?.dck", "league-3.dck"));
+        assert!(matches("*-*.dck", "a-b-c.dck"));
+        assert!(!matches("*.dck", "league.dck.pdf"));
+        assert!(!matches("league-?.dck", "league-10.dck"));
+        assert!(matches("*", ""));
+    }
+
+    #[test]
+    pub fn test_expand_inputs() {
+        let directory = Path::new("Test_Temp_Inputs");
+        create_dir_all(directory).unwrap();
+        for file in ["b.dck", "a.dck", "notes.txt"] {
+            File::create(directory.join(file)).unwrap();
+        }
+        let inputs = expand_inputs(&[
+            "Test_Temp_Inputs/*.dck".to_string(),
+            "other.dck".to_string(),
+        ]);
+        let missing = expand_inputs(&["Test_Temp_Inputs/*.mtga".to_string()]);
+        remove_dir_all(directory).unwrap();
+        assert_eq!(
+            inputs.unwrap(),
+            vec![
+                directory.join("a.dck"),
+                directory.join("b.dck"),
+                Path::new("other.dck").to_path_buf(),
+            ]
+        );
+        assert!(missing.is_err());
+    }
+}
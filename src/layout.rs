@@ -0,0 +1,643 @@
+use crate::card_size::CardSize;
+use crate::duplex::Duplex;
+use crate::pdf_calc::{PageSpec, CARD_HEIGHT, CARD_WIDTH};
+use printpdf::Mm;
+
+/// A rectangle on a page, positioned from the bottom left corner like the rest of printpdf
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rect {
+    pub x: Mm,
+    pub y: Mm,
+    pub width: Mm,
+    pub height: Mm,
+}
+
+impl Rect {
+    pub fn new(x: Mm, y: Mm, width: Mm, height: Mm) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> Mm {
+        self.x + self.width
+    }
+
+    pub fn top(&self) -> Mm {
+        self.y + self.height
+    }
+
+    /// True if the two rectangles share some area, not just an edge
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.x.0 < other.right().0
+            && other.x.0 < self.right().0
+            && self.y.0 < other.top().0
+            && other.y.0 < self.top().0
+    }
+
+    /// The smallest rectangle holding both rectangles
+    pub fn union(&self, other: &Rect) -> Rect {
+        let (x, y) = (self.x.0.min(other.x.0), self.y.0.min(other.y.0));
+        let right = self.right().0.max(other.right().0);
+        let top = self.top().0.max(other.top().0);
+        Rect::new(Mm(x), Mm(y), Mm(right - x), Mm(top - y))
+    }
+
+    /// The rectangle grown by `amount` on every side
+    pub fn expand(&self, amount: Mm) -> Rect {
+        Rect::new(
+            self.x - amount,
+            self.y - amount,
+            self.width + amount * 2.0,
+            self.height + amount * 2.0,
+        )
+    }
+}
+
+/// Where a point on an upright card lands on the page, for a card in `rect` turned clockwise by
+/// `rotation` degrees
+pub fn card_to_page(rect: &Rect, rotation: u16, (u, v): (Mm, Mm)) -> (Mm, Mm) {
+    match rotation {
+        90 => (rect.x + v, rect.top() - u),
+        180 => (rect.right() - u, rect.top() - v),
+        270 => (rect.right() - v, rect.y + u),
+        _ => (rect.x + u, rect.y + v),
+    }
+}
+
+/// Where a single card lands in the printed document
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Slot {
+    /// Sheet number, counting from 0. Each sheet has a front and a back page.
+    pub page: usize,
+    /// Position of the card on the front page
+    pub front: Rect,
+    /// Position of the card on the back page, mirrored so it lines up after a duplex flip
+    pub back: Rect,
+    /// True if the card is turned 90° on the page to fit more cards
+    pub rotated: bool,
+    /// Clockwise rotation in degrees the back image needs so it is the right way up once turned
+    pub back_rotation: u16,
+}
+
+/// Imposition of cards onto pages: a centred grid of card slots separated by gutters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    page_width: Mm,
+    page_height: Mm,
+    card_width: Mm,
+    card_height: Mm,
+    gutter: Mm,
+    margin: Mm,
+    bleed: Mm,
+    columns: Option<usize>,
+    rows: Option<usize>,
+    rotated: bool,
+    /// How `rotated` was chosen, so it can be chosen again for another card size
+    card_rotation: CardRotation,
+    duplex: Duplex,
+    /// Areas of the page no card or its bleed may cover, such as a cutter's registration marks
+    keep_out: Vec<Rect>,
+}
+
+/// Whether cards are turned sideways on the page
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum CardRotation {
+    /// Rotate cards only if more of them fit on a page that way
+    Auto,
+    #[default]
+    Never,
+    Always,
+}
+
+impl Layout {
+    /// Standard sized cards packed edge to edge on the given page
+    pub fn new(page_spec: &PageSpec) -> Layout {
+        Layout {
+            page_width: page_spec.width(),
+            page_height: page_spec.height(),
+            card_width: CARD_WIDTH,
+            card_height: CARD_HEIGHT,
+            gutter: Mm(0.0),
+            margin: Mm(0.0),
+            bleed: Mm(0.0),
+            columns: None,
+            rows: None,
+            rotated: false,
+            card_rotation: CardRotation::Never,
+            duplex: Duplex::default(),
+            keep_out: vec![],
+        }
+    }
+
+    /// Extra image printed past the cut line on every side of a card. Bleeds never overlap,
+    /// the gutter is the space left between the bleed edges of neighbouring cards.
+    pub fn with_bleed(mut self, bleed: Mm) -> Layout {
+        self.bleed = bleed;
+        self
+    }
+
+    /// Space left between neighbouring cards
+    pub fn with_gutter(mut self, gutter: Mm) -> Layout {
+        self.gutter = gutter;
+        self
+    }
+
+    /// Minimum space left between the cards and the edge of the page
+    pub fn with_margin(mut self, margin: Mm) -> Layout {
+        self.margin = margin;
+        self
+    }
+
+    /// Use a fixed number of columns instead of fitting as many as possible
+    pub fn with_columns(mut self, columns: Option<usize>) -> Layout {
+        self.columns = columns;
+        self
+    }
+
+    /// Use a fixed number of rows instead of fitting as many as possible
+    pub fn with_rows(mut self, rows: Option<usize>) -> Layout {
+        self.rows = rows;
+        self
+    }
+
+    /// Turn every card 90° on the page
+    pub fn with_rotation(mut self, rotated: bool) -> Layout {
+        self.rotated = rotated;
+        self
+    }
+
+    /// How the back page lines up with the front page
+    pub fn with_duplex(mut self, duplex: Duplex) -> Layout {
+        self.duplex = duplex;
+        self
+    }
+
+    /// Leave empty any slot whose card would cover part of one of these areas
+    pub fn with_keep_out(mut self, keep_out: Vec<Rect>) -> Layout {
+        self.keep_out = keep_out;
+        self
+    }
+
+    pub fn with_card_rotation(mut self, rotation: CardRotation) -> Layout {
+        self.card_rotation = rotation;
+        match rotation {
+            CardRotation::Auto => self.best_fit(),
+            CardRotation::Never => self.with_rotation(false),
+            CardRotation::Always => self.with_rotation(true),
+        }
+    }
+
+    /// The same layout for cards of another size, turning them on the page the same way the
+    /// layout was told to
+    pub fn with_card_size(mut self, size: CardSize) -> Layout {
+        self.card_width = size.width;
+        self.card_height = size.height;
+        let rotation = self.card_rotation;
+        self.with_card_rotation(rotation)
+    }
+
+    /// Rotate the cards if that fits more of them on a page. Upright wins a tie.
+    pub fn best_fit(self) -> Layout {
+        let upright = self.clone().with_rotation(false);
+        let rotated = self.with_rotation(true);
+        match rotated.cards_per_page() > upright.cards_per_page() {
+            true => rotated,
+            false => upright,
+        }
+    }
+
+    pub fn page_width(&self) -> Mm {
+        self.page_width
+    }
+
+    pub fn page_height(&self) -> Mm {
+        self.page_height
+    }
+
+    pub fn bleed(&self) -> Mm {
+        self.bleed
+    }
+
+    pub fn gutter(&self) -> Mm {
+        self.gutter
+    }
+
+    pub fn margin(&self) -> Mm {
+        self.margin
+    }
+
+    pub fn duplex(&self) -> &Duplex {
+        &self.duplex
+    }
+
+    /// Width a card takes up on the page, after rotation
+    pub fn slot_width(&self) -> Mm {
+        match self.rotated {
+            true => self.card_height,
+            false => self.card_width,
+        }
+    }
+
+    /// Height a card takes up on the page, after rotation
+    pub fn slot_height(&self) -> Mm {
+        match self.rotated {
+            true => self.card_width,
+            false => self.card_height,
+        }
+    }
+
+    /// Distance from one column to the next
+    fn pitch_x(&self) -> Mm {
+        self.slot_width() + self.bleed * 2.0 + self.gutter
+    }
+
+    /// Distance from one row to the next
+    fn pitch_y(&self) -> Mm {
+        self.slot_height() + self.bleed * 2.0 + self.gutter
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns.unwrap_or_else(|| {
+            let width = self.slot_width() + self.bleed * 2.0;
+            fit(self.page_width, self.margin, width, self.gutter)
+        })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows.unwrap_or_else(|| {
+            let height = self.slot_height() + self.bleed * 2.0;
+            fit(self.page_height, self.margin, height, self.gutter)
+        })
+    }
+
+    pub fn cards_per_page(&self) -> usize {
+        self.columns() * self.rows()
+    }
+
+    /// Number of sheets needed to print the given number of cards of this layout's size
+    pub fn page_count(&self, cards: usize) -> usize {
+        cards.div_ceil(self.open_slots_per_page())
+    }
+
+    /// Checks that at least one card fits and that the grid does not run off the page
+    pub fn validate(&self) -> Result<(), String> {
+        if self.cards_per_page() == 0 {
+            return Err(format!(
+                "No {}x{}mm cards fit on a {}x{}mm page",
+                self.card_width.0, self.card_height.0, self.page_width.0, self.page_height.0
+            ));
+        }
+        let grid = self.grid_area();
+        if grid.x.0 < self.margin.0 || grid.y.0 < self.margin.0 {
+            return Err(format!(
+                "A {}x{} grid of cards does not fit on a {}x{}mm page",
+                self.columns(),
+                self.rows(),
+                self.page_width.0,
+                self.page_height.0
+            ));
+        }
+        if self.open_slots_per_page() == 0 {
+            return Err(
+                "Every card on the page would cover an area that has to be kept clear".to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Number of slots on a page that are not kept clear
+    pub fn open_slots_per_page(&self) -> usize {
+        (0..self.cards_per_page())
+            .filter(|index| !self.is_kept_out(&self.slot(*index)))
+            .count()
+    }
+
+    /// True if the card in a slot, with its bleed, covers part of an area that has to be kept
+    /// clear
+    pub fn is_kept_out(&self, slot: &Slot) -> bool {
+        let card = slot.front.expand(self.bleed);
+        self.keep_out.iter().any(|zone| card.overlaps(zone))
+    }
+
+    /// The area covered by the card grid, including bleeds and the gutters between cards
+    pub fn grid_area(&self) -> Rect {
+        let columns = self.columns() as f32;
+        let rows = self.rows() as f32;
+        let width = self.pitch_x() * columns - self.gutter;
+        let height = self.pitch_y() * rows - self.gutter;
+        Rect::new(
+            (self.page_width - width) / 2.0,
+            (self.page_height - height) / 2.0,
+            width,
+            height,
+        )
+    }
+
+    /// Given a card index, return where the card goes on the front and back pages
+    pub fn slot(&self, index: usize) -> Slot {
+        let per_page = self.cards_per_page();
+        let page = index / per_page;
+        let position = index % per_page;
+        let column = position % self.columns();
+        // Rows count down from the top of the page, but pdf coordinates count up from the bottom
+        let row = (position / self.columns()) as f32;
+        let top = self.grid_area().top() - self.pitch_y() * row;
+        self.slot_in_row(page, column, top)
+    }
+
+    /// Height of a row of cards, from the top of their bleed to the bottom
+    pub fn row_height(&self) -> Mm {
+        self.slot_height() + self.bleed * 2.0
+    }
+
+    /// Where the card in a column goes, in a row whose bleed starts at `top` on the page
+    pub fn slot_in_row(&self, page: usize, column: usize, top: Mm) -> Slot {
+        let x = self.grid_area().x + self.bleed + self.pitch_x() * column as f32;
+        let y = top - self.bleed - self.slot_height();
+        let front = Rect::new(x, y, self.slot_width(), self.slot_height());
+        let front_rotation = match self.rotated {
+            true => 90,
+            false => 0,
+        };
+        Slot {
+            page,
+            front,
+            back: self.back_of(&front),
+            rotated: self.rotated,
+            back_rotation: self.back_rotation_of(front_rotation),
+        }
+    }
+
+    /// Where something printed at `front` on the front page has to go on the back page to land
+    /// directly behind it, once the sheet has been turned over
+    pub fn back_of(&self, front: &Rect) -> Rect {
+        let portrait = self.page_height.0 >= self.page_width.0;
+        let mut back = match self.duplex.mirrors_horizontally(portrait) {
+            true => Rect::new(
+                self.page_width - front.right(),
+                front.y,
+                front.width,
+                front.height,
+            ),
+            false => Rect::new(
+                front.x,
+                self.page_height - front.top(),
+                front.width,
+                front.height,
+            ),
+        };
+        if self.duplex.rotate_back {
+            back.x = self.page_width - back.right();
+            back.y = self.page_height - back.top();
+        }
+        back.x += Mm(self.duplex.back_offset_x);
+        back.y += Mm(self.duplex.back_offset_y);
+        back
+    }
+
+    /// Clockwise rotation for the back of something turned `front_rotation` degrees clockwise on
+    /// the front page. Turning the sheet mirrors the direction the top of a card points in.
+    pub fn back_rotation_of(&self, front_rotation: u16) -> u16 {
+        let portrait = self.page_height.0 >= self.page_width.0;
+        let rotation = match self.duplex.mirrors_horizontally(portrait) {
+            true => 360 - front_rotation,
+            false => 180 + 360 - front_rotation,
+        };
+        let rotation = match self.duplex.rotate_back {
+            true => rotation + 180,
+            false => rotation,
+        };
+        rotation % 360
+    }
+}
+
+impl Layout {
+    /// Positions of the vertical and horizontal cut lines on the front page, left to right and
+    /// bottom to top. Neighbouring cards with no gutter or bleed share a cut line.
+    pub fn cut_positions(&self) -> (Vec<Mm>, Vec<Mm>) {
+        let mut xs: Vec<Mm> = vec![];
+        for column in 0..self.columns() {
+            let card = self.slot(column).front;
+            xs.push(card.x);
+            xs.push(card.right());
+        }
+        let mut ys: Vec<Mm> = vec![];
+        for row in (0..self.rows()).rev() {
+            let card = self.slot(row * self.columns()).front;
+            ys.push(card.y);
+            ys.push(card.top());
+        }
+        xs.dedup();
+        ys.dedup();
+        (xs, ys)
+    }
+}
+
+/// How many items of `size` separated by `gutter` fit in `length` less a margin on each side
+fn fit(length: Mm, margin: Mm, size: Mm, gutter: Mm) -> usize {
+    let usable = length.0 - 2.0 * margin.0;
+    if usable < size.0 {
+        return 0;
+    }
+    ((usable + gutter.0) / (size.0 + gutter.0)).floor() as usize
+}
+
+#[cfg(test)]
+mod test {
+    use crate::duplex::{Duplex, DuplexFlip};
+    use crate::layout::{card_to_page, CardRotation, Layout, Rect, Slot};
+    use crate::pdf_calc::{Orientation, PageSpec, PaperSize};
+    use printpdf::Mm;
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect::new(Mm(x), Mm(y), Mm(width), Mm(height))
+    }
+
+    #[test]
+    pub fn test_nine_up_a4() {
+        let layout = Layout::new(&PageSpec::default());
+        assert_eq!((layout.columns(), layout.rows()), (3, 3));
+        let slots: Vec<Slot> = (0..10).map(|i| layout.slot(i)).collect();
+        assert_eq!(
+            slots[0],
+            Slot {
+                page: 0,
+                front: rect(10.5, 192.5, 63.0, 88.0),
+                back: rect(136.5, 192.5, 63.0, 88.0),
+                rotated: false,
+                back_rotation: 0,
+            }
+        );
+        assert_eq!(slots[1].front, rect(73.5, 192.5, 63.0, 88.0));
+        assert_eq!(slots[1].back, rect(73.5, 192.5, 63.0, 88.0));
+        assert_eq!(slots[3].front, rect(10.5, 104.5, 63.0, 88.0));
+        assert_eq!(slots[8].front, rect(136.5, 16.5, 63.0, 88.0));
+        assert_eq!(slots[8].back, rect(10.5, 16.5, 63.0, 88.0));
+        assert_eq!(slots[8].page, 0);
+        assert_eq!(slots[9].page, 1);
+        assert_eq!(slots[9].front, slots[0].front);
+        assert_eq!(layout.page_count(18), 2);
+        assert_eq!(layout.page_count(19), 3);
+    }
+
+    #[test]
+    pub fn test_eight_up_landscape() {
+        let page = PageSpec::new(PaperSize::A4, Orientation::Landscape);
+        let layout = Layout::new(&page);
+        assert_eq!((layout.columns(), layout.rows()), (4, 2));
+        assert_eq!(layout.slot(0).front, rect(22.5, 105.0, 63.0, 88.0));
+        assert_eq!(layout.slot(0).back, rect(211.5, 105.0, 63.0, 88.0));
+        assert_eq!(layout.slot(7).front, rect(211.5, 17.0, 63.0, 88.0));
+        assert_eq!(layout.slot(7).back, rect(22.5, 17.0, 63.0, 88.0));
+        assert_eq!(layout.slot(8).page, 1);
+
+        // A landscape sheet turned over on its long edge flips top to bottom
+        let long_edge = Duplex {
+            flip: Some(DuplexFlip::LongEdge),
+            ..Default::default()
+        };
+        let layout = Layout::new(&page).with_duplex(long_edge);
+        assert_eq!(layout.slot(0).back, rect(22.5, 17.0, 63.0, 88.0));
+        assert_eq!(layout.slot(0).back_rotation, 180);
+
+        let letter = PageSpec::new(PaperSize::Letter, Orientation::Landscape);
+        let layout = Layout::new(&letter);
+        assert_eq!((layout.columns(), layout.rows()), (4, 2));
+    }
+
+    #[test]
+    pub fn test_rotated_cards() {
+        let page = PageSpec::new(PaperSize::A4, Orientation::Landscape);
+        let layout = Layout::new(&page).with_card_rotation(CardRotation::Auto);
+        assert_eq!((layout.columns(), layout.rows()), (3, 3));
+        let slot = layout.slot(0);
+        assert!(slot.rotated);
+        assert_eq!(slot.front, rect(16.5, 136.5, 88.0, 63.0));
+        assert_eq!(slot.back, rect(192.5, 136.5, 88.0, 63.0));
+        assert_eq!(slot.back_rotation, 270);
+
+        // Portrait A4 already fits best upright
+        let layout = Layout::new(&PageSpec::default()).best_fit();
+        assert!(!layout.slot(0).rotated);
+    }
+
+    #[test]
+    pub fn test_gutters_and_margins() {
+        let layout = Layout::new(&PageSpec::default()).with_gutter(Mm(3.0));
+        assert_eq!((layout.columns(), layout.rows()), (3, 3));
+        assert_eq!(layout.grid_area(), rect(7.5, 13.5, 195.0, 270.0));
+        assert_eq!(layout.slot(4).front, rect(73.5, 104.5, 63.0, 88.0));
+
+        let layout = layout.with_margin(Mm(20.0));
+        assert_eq!((layout.columns(), layout.rows()), (2, 2));
+
+        let layout = Layout::new(&PageSpec::default()).with_columns(Some(4));
+        assert_eq!((layout.columns(), layout.rows()), (4, 3));
+        assert!(layout.validate().is_err());
+        let layout = Layout::new(&PageSpec::default())
+            .with_columns(Some(2))
+            .with_rows(Some(2));
+        assert!(layout.validate().is_ok());
+        assert_eq!(layout.slot(0).front, rect(42.0, 148.5, 63.0, 88.0));
+    }
+
+    #[test]
+    pub fn test_duplex() {
+        let duplex = Duplex {
+            flip: Some(DuplexFlip::LongEdge),
+            rotate_back: false,
+            back_offset_x: 1.0,
+            back_offset_y: -2.0,
+        };
+        let layout = Layout::new(&PageSpec::default()).with_duplex(duplex);
+        let slot = layout.slot(0);
+        assert_eq!(slot.front, rect(10.5, 192.5, 63.0, 88.0));
+        assert_eq!(slot.back, rect(137.5, 190.5, 63.0, 88.0));
+        assert_eq!(slot.back_rotation, 0);
+
+        let short_edge = Duplex {
+            flip: Some(DuplexFlip::ShortEdge),
+            ..Default::default()
+        };
+        let layout = Layout::new(&PageSpec::default()).with_duplex(short_edge);
+        let slot = layout.slot(0);
+        assert_eq!(slot.back, rect(10.5, 16.5, 63.0, 88.0));
+        assert_eq!(slot.back_rotation, 180);
+
+        // Short edge flip of a landscape page turns over left to right
+        let page = PageSpec::new(PaperSize::A4, Orientation::Landscape);
+        let layout = Layout::new(&page).with_duplex(short_edge).best_fit();
+        let slot = layout.slot(0);
+        assert_eq!(slot.back, rect(192.5, 136.5, 88.0, 63.0));
+        assert_eq!(slot.back_rotation, 270);
+
+        let upside_down = Duplex {
+            rotate_back: true,
+            ..Default::default()
+        };
+        let layout = Layout::new(&PageSpec::default()).with_duplex(upside_down);
+        let slot = layout.slot(0);
+        assert_eq!(slot.back, rect(10.5, 16.5, 63.0, 88.0));
+        assert_eq!(slot.back_rotation, 180);
+    }
+
+    #[test]
+    pub fn test_bleed() {
+        let layout = Layout::new(&PageSpec::default()).with_bleed(Mm(3.0));
+        assert_eq!((layout.columns(), layout.rows()), (3, 3));
+        assert_eq!(layout.grid_area(), rect(1.5, 7.5, 207.0, 282.0));
+        assert_eq!(layout.slot(0).front, rect(4.5, 198.5, 63.0, 88.0));
+        assert_eq!(layout.slot(1).front, rect(73.5, 198.5, 63.0, 88.0));
+        assert_eq!(layout.slot(3).front, rect(4.5, 104.5, 63.0, 88.0));
+        assert_eq!(layout.slot(0).back, rect(142.5, 198.5, 63.0, 88.0));
+
+        let (xs, ys) = layout.cut_positions();
+        assert_eq!(xs.len(), 6);
+        assert_eq!(
+            ys,
+            vec![
+                Mm(10.5),
+                Mm(98.5),
+                Mm(104.5),
+                Mm(192.5),
+                Mm(198.5),
+                Mm(286.5)
+            ]
+        );
+
+        let layout = layout.with_gutter(Mm(2.0));
+        assert_eq!((layout.columns(), layout.rows()), (2, 3));
+
+        let (xs, ys) = Layout::new(&PageSpec::default()).cut_positions();
+        assert_eq!(xs, vec![Mm(10.5), Mm(73.5), Mm(136.5), Mm(199.5)]);
+        assert_eq!(ys.len(), 4);
+    }
+
+    #[test]
+    pub fn test_card_to_page() {
+        let rect = Rect::new(Mm(10.0), Mm(20.0), Mm(88.0), Mm(63.0));
+        // The bottom left of a card turned clockwise is at the top left of its slot
+        assert_eq!(
+            card_to_page(&rect, 90, (Mm(0.0), Mm(0.0))),
+            (Mm(10.0), Mm(83.0))
+        );
+        // Its top right is at the bottom right
+        assert_eq!(
+            card_to_page(&rect, 90, (Mm(63.0), Mm(88.0))),
+            (Mm(98.0), Mm(20.0))
+        );
+        let rect = Rect::new(Mm(10.0), Mm(20.0), Mm(63.0), Mm(88.0));
+        assert_eq!(
+            card_to_page(&rect, 180, (Mm(0.0), Mm(0.0))),
+            (Mm(73.0), Mm(108.0))
+        );
+        assert_eq!(
+            card_to_page(&rect, 0, (Mm(1.0), Mm(2.0))),
+            (Mm(11.0), Mm(22.0))
+        );
+    }
+}
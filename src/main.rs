@@ -1,33 +1,99 @@
+mod artwork;
+mod bleed;
+mod calibration;
+mod card_back;
+mod card_size;
+mod checklist;
+mod collection;
+mod cutter;
 mod data_repository;
 pub mod deck;
+mod duplex;
+mod export;
+mod font_metrics;
+mod guides;
 mod image_repository;
+mod inputs;
+mod layout;
+mod mapping;
+mod packing;
 mod pdf_calc;
+mod pdf_images;
+mod pdf_writer;
+mod pdf_x;
+mod preview;
+mod print_shop;
+mod processing;
+mod render;
+mod reprint;
 mod scryfall_client;
+mod selection;
+mod text_proxy;
+mod watermark;
 
+use crate::artwork::Artwork;
+use crate::bleed::BleedFill;
+use crate::calibration::write_calibration_sheet;
+use crate::card_back::{parse_override, CardBacks};
+use crate::card_size::{parse_size_override, CardSize, CardSizes};
+use crate::collection::{exclude_collection, Collection, CollectionMatch};
+use crate::cutter::Cutter;
 use crate::data_repository::DataRepository;
+use crate::deck::PickedDeck;
+use crate::duplex::{Duplex, DuplexFlip};
+use crate::export::{export_images, ImageExport, ImageFormat, OutputFormat};
+use crate::guides::CutGuides;
 use crate::image_repository::ImageRepository;
-use crate::pdf_calc::{
-    calculate_dpi_image, grid_translator, CARD_HEIGHT, CARD_WIDTH, HEIGHT_OFFSET_MM,
-    PAGE_HEIGHT_A4, PAGE_WIDTH_A4, WIDTH_OFFSET_MM,
-};
-use clap::Parser;
-use printpdf::{Image, ImageTransform, Mm, PdfDocument, PdfLayerIndex, PdfPageIndex, Point};
+use crate::inputs::expand_inputs;
+use crate::layout::{CardRotation, Layout};
+use crate::mapping::CardMap;
+use crate::pdf_calc::{Orientation, PageSpec, PaperSize};
+use crate::pdf_images::ImageCompression;
+use crate::preview::{preview_deck, PreviewFormat};
+use crate::print_shop::{export_print_shop, PrintShop};
+use crate::processing::{Pipeline, ProcessingStep};
+use crate::render::{render_deck, PrintMode, RenderOptions};
+use crate::reprint::{Reprint, SheetList};
+use crate::scryfall_client::ImageQuality;
+use crate::selection::{CardRange, Selection};
+use crate::watermark::{Colour, Watermark, WatermarkPosition};
+use clap::{Parser, Subcommand};
+use printpdf::Mm;
 use std::fmt::Debug;
 use std::fs::File;
-use std::io::BufWriter;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicU16, Ordering};
+
+/// Output file name when several decks are printed together
+const COMBINED_PDF: &str = "combined.pdf";
 
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[command(author, version, about, subcommand_negates_reqs = true)]
 struct Args {
-    /// Input file: xmage (dck), forge (dek), mtgo (mtgo), arena (mtga) file
-    #[arg(short, long)]
-    input_file: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Input files: xmage (dck), forge (dek), mtgo (mtgo), arena (mtga) files, or patterns such
+    /// as decks/*.dck. Several decks are packed into one print run.
+    #[arg(short, long, num_args = 1.., global = true)]
+    input_file: Vec<String>,
+
+    /// Print only the first this many of the selected cards
+    #[arg(short = 'n', long, alias = "card-count")]
+    limit: Option<usize>,
+
+    /// Print only the cards of this section, such as Sideboard. Can be repeated.
+    #[arg(long)]
+    only_section: Vec<String>,
 
-    /// Number of cards to process (optional)
-    #[arg(short = 'n', long)]
-    card_count: Option<u16>,
+    /// Print only the cards listed in this file, one name or [SET:NUMBER] per line
+    #[arg(long)]
+    only_names: Option<PathBuf>,
+
+    /// Print only the cards at these positions in print order, counted from 1 after the other
+    /// selections. Counts cards, not sheets: 10..19 is the 10th to the 19th card, both included,
+    /// and 10.. or ..19 leave an end open.
+    #[arg(long)]
+    range: Option<CardRange>,
 
     /// Path to the image repository
     #[arg(short = 'r', long, default_value = "./data_repository")]
@@ -37,197 +103,560 @@ struct Args {
     #[arg(short = 'd', long, default_value = "./image_repository")]
     data_repository: Option<String>,
 
-    /// Output PDF name (defaults to same name as input file)
+    /// Output PDF name (defaults to same name as input file, or combined.pdf for several). Image
+    /// output goes to a directory or ZIP file named after it.
     #[arg(short = 'o', long)]
     output_pdf_name: Option<String>,
+
+    /// Write sheets to a PDF, or each card front and back as numbered images with a manifest
+    #[arg(long, value_enum, default_value_t = OutputFormat::Pdf)]
+    output_format: OutputFormat,
+
+    /// File format of card images written by the images and zip output formats
+    #[arg(long, value_enum, default_value_t = ImageFormat::Png)]
+    image_format: ImageFormat,
+
+    /// Resolution of card images written by the images and zip output formats, and for
+    /// --print-shop generic
+    #[arg(long, default_value_t = 300.0)]
+    dpi: f32,
+
+    /// Write the images and upload list a print shop asks for instead of a PDF, in a directory
+    /// unless --output-format zip is given
+    #[arg(long, value_enum)]
+    print_shop: Option<PrintShop>,
+
+    /// Paper size: a4, a3, letter, legal, tabloid, or a custom size such as 210x297mm or 8.5x11in
+    #[arg(short = 'p', long, default_value = "a4", global = true)]
+    paper: PaperSize,
+
+    /// Page orientation
+    #[arg(long, value_enum, default_value_t = Orientation::Portrait, global = true)]
+    orientation: Orientation,
+
+    /// Number of card columns per page (defaults to as many as fit)
+    #[arg(long)]
+    columns: Option<usize>,
+
+    /// Number of card rows per page (defaults to as many as fit)
+    #[arg(long)]
+    rows: Option<usize>,
+
+    /// Minimum margin around the card grid in mm
+    #[arg(long, default_value_t = 0.0)]
+    margin: f32,
+
+    /// Space between neighbouring cards in mm
+    #[arg(long, default_value_t = 0.0)]
+    gutter: f32,
+
+    /// Size to print cards at: standard, mini, euro (59x92mm), tarot (70x120mm), japanese
+    /// (59x86mm), oversized, or a custom size such as 2.5x3.5in
+    #[arg(long, default_value = "standard")]
+    card_size: CardSize,
+
+    /// Size for the cards of one section, as <section>=<size>, for example Commander=oversized.
+    /// Can be repeated.
+    #[arg(long, value_parser = parse_size_override)]
+    section_size: Vec<(String, CardSize)>,
+
+    /// File of `<card> = <size>` lines giving sizes for individual cards, where a card is its
+    /// name or its printing as [SET:NUMBER]
+    #[arg(long)]
+    card_size_map: Option<PathBuf>,
+
+    /// Turn cards sideways on the page. Auto turns them only if more fit on a page that way.
+    #[arg(long, value_enum, default_value_t = CardRotation::Never)]
+    rotate: CardRotation,
+
+    /// Author written into the PDF's document information
+    #[arg(long)]
+    author: Option<String>,
+
+    /// Write PDF/X-4 for print shops: a FOGRA39 output intent, a trim box around the cards and a
+    /// bleed box around that on every page, and RGB colours tagged as sRGB. PDF/X needs every
+    /// font embedded, so it cannot be combined with anything that writes text: text proxies,
+    /// watermarks, checklists, dividers or deck tags.
+    #[arg(long)]
+    pdf_x: bool,
+
+    /// Write the print run as several PDFs of at most this many pages each, named deck_part1.pdf
+    /// and so on, so very large runs don't have to fit in memory at once. 0 writes a single PDF
+    /// however long the run is.
+    #[arg(long, default_value_t = 100)]
+    split_pages: usize,
+
+    /// Print only these sheets of the run again, counted from 1, such as 3,7-9. Counts sheets,
+    /// not PDF pages: each sheet is a page of fronts and, unless printing fronts only, its page of
+    /// backs, and checklist pages are not counted. Written to <output>_reprint.pdf with every
+    /// card in the slot it had in the original run.
+    #[arg(long)]
+    pages: Option<SheetList>,
+
+    /// Print only the copies of this card again, by name or as [SET:NUMBER], each in the slot it
+    /// had in the original run. Can be repeated, and combined with --pages.
+    #[arg(long)]
+    reprint: Vec<String>,
+
+    /// Reprint even if the run is laid out differently than its layout lock says the original
+    /// run was, such as after changing the deck or the layout options
+    #[arg(long)]
+    ignore_layout_lock: bool,
+
+    /// Print registration marks for a cutting machine, keep cards clear of them, and write an
+    /// SVG cut file of the card outlines for each sheet
+    #[arg(long, value_enum)]
+    cutter: Option<Cutter>,
+
+    /// How cut lines are marked
+    #[arg(long, value_enum, default_value_t = CutGuides::Lines)]
+    guides: CutGuides,
+
+    /// Image printed past the cut line on every side of a card, in mm
+    #[arg(long, default_value_t = 0.0)]
+    bleed: f32,
+
+    /// How the bleed is filled in
+    #[arg(long, value_enum, default_value_t = BleedFill::Edge)]
+    bleed_fill: BleedFill,
+
+    /// How fronts and backs are arranged across pages and files
+    #[arg(short = 'm', long, value_enum, default_value_t = PrintMode::Duplex)]
+    mode: PrintMode,
+
+    /// Which Scryfall image to download for each card. Each quality is cached separately.
+    #[arg(long, value_enum, default_value_t = ImageQuality::Large)]
+    image_quality: ImageQuality,
+
+    /// Image processing step, applied to every card image in the order given. One of
+    /// fill-corners, brightness=<-255..255>, gamma=<g>, saturation=<s>, sharpen=<sigma> or
+    /// upscale=<dpi>. Can be repeated.
+    #[arg(long = "process", allow_hyphen_values = true)]
+    processing: Vec<ProcessingStep>,
+
+    /// Scale down card images that would print at more dots per inch than this, to make the PDF
+    /// smaller
+    #[arg(long)]
+    max_dpi: Option<f32>,
+
+    /// Store card images in the PDF as JPEGs of this quality, from 1 to 100, instead of
+    /// losslessly. Lower is smaller.
+    #[arg(long)]
+    jpeg_quality: Option<u8>,
+
+    /// Text stamped over the front of every card, such as PROXY or PLAYTEST. {deck} is replaced
+    /// by the name of the deck file.
+    #[arg(long)]
+    watermark: Option<String>,
+
+    /// Where on the card the watermark goes
+    #[arg(long, value_enum, default_value_t = WatermarkPosition::Centre)]
+    watermark_position: WatermarkPosition,
+
+    /// Font size of the watermark in points
+    #[arg(long, default_value_t = 24.0)]
+    watermark_size: f32,
+
+    /// Opacity of the watermark, from 0 for invisible to 1 for solid
+    #[arg(long, default_value_t = 0.5)]
+    watermark_opacity: f32,
+
+    /// Colour of the watermark as a hex triplet
+    #[arg(long, default_value = "d01010")]
+    watermark_colour: Colour,
+
+    /// Stamp the watermark on the back faces of double sided cards as well
+    #[arg(long)]
+    watermark_backs: bool,
+
+    /// Print card art in greyscale, so the proxies can't pass for the real thing
+    #[arg(long)]
+    grey_art: bool,
+
+    /// Print each card as its name, type line and rules text instead of its image, so no images
+    /// are downloaded
+    #[arg(long)]
+    text_proxies: bool,
+
+    /// Write the name of the deck each card came from in the gutter below it
+    #[arg(long)]
+    deck_tags: bool,
+
+    /// Start with pages listing every card to print by section, with its set and number
+    #[arg(long)]
+    checklist: bool,
+
+    /// Print a divider card naming the section and deck before each section of each deck
+    #[arg(long)]
+    dividers: bool,
+
+    /// Add a Tokens section with this many copies of each token, emblem and meld result the deck
+    /// makes, one copy if no number is given
+    #[arg(long, num_args = 0..=1, default_missing_value = "1")]
+    include_tokens: Option<u32>,
+
+    /// Cards already owned, as a deck file or a CSV with name, and optionally count, set and
+    /// collector number columns. Owned copies are left out of the print run.
+    #[arg(long)]
+    exclude_collection: Option<PathBuf>,
+
+    /// Whether an owned card has to be the same printing to be left out
+    #[arg(long, value_enum, default_value_t = CollectionMatch::Name)]
+    collection_match: CollectionMatch,
+
+    /// JPEG or PNG image printed behind every single faced card, instead of the built in back
+    #[arg(long)]
+    card_back: Option<PathBuf>,
+
+    /// Back for the single faced cards of one deck, as <deck file name>=<image>. Can be repeated.
+    #[arg(long, value_parser = parse_override)]
+    deck_back: Vec<(String, PathBuf)>,
+
+    /// Back for the single faced cards of one section, as <section>=<image>, for example
+    /// Sideboard=sideboard.png. Can be repeated.
+    #[arg(long, value_parser = parse_override)]
+    section_back: Vec<(String, PathBuf)>,
+
+    /// File of `<card> = <image>` lines giving backs for individual cards, where a card is its
+    /// name or its printing as [SET:NUMBER]
+    #[arg(long)]
+    card_back_map: Option<PathBuf>,
+
+    /// Directory of local images used in place of Scryfall's, named after the card
+    /// (`Lightning Bolt.png`) or the printing (`m21_176.jpg`), with `_back` added for back faces
+    #[arg(long)]
+    art_dir: Option<PathBuf>,
+
+    /// File of `<card> = <image>` lines giving local front images, where a card is its name or
+    /// its printing as [SET:NUMBER]
+    #[arg(long)]
+    art_map: Option<PathBuf>,
+
+    /// File of `<card> = <image>` lines giving local back images
+    #[arg(long)]
+    art_back_map: Option<PathBuf>,
+
+    /// Printer profile to load duplex settings from. Settings given on the command line override
+    /// the saved ones for this run only, unless --save-printer is given.
+    #[arg(long, global = true)]
+    printer: Option<String>,
+
+    /// Save the duplex settings of this run to the --printer profile
+    #[arg(long, global = true, requires = "printer")]
+    save_printer: bool,
+
+    /// Which edge of the sheet the printer turns it over on. Without it the back page is
+    /// mirrored left to right, whichever way the page is turned.
+    #[arg(long, value_enum, global = true)]
+    duplex_flip: Option<DuplexFlip>,
+
+    /// Turn the back page by 180°, for printers that feed the sheet back in upside down
+    #[arg(long, global = true, overrides_with = "no_rotate_back")]
+    rotate_back: bool,
+
+    /// Do not turn the back page, even if the printer profile does
+    #[arg(long, global = true, overrides_with = "rotate_back")]
+    no_rotate_back: bool,
+
+    /// Shift the back page right by this many mm (negative shifts left)
+    #[arg(long, global = true, allow_hyphen_values = true)]
+    back_offset_x: Option<f32>,
+
+    /// Shift the back page up by this many mm (negative shifts down)
+    #[arg(long, global = true, allow_hyphen_values = true)]
+    back_offset_y: Option<f32>,
 }
 
-fn process_dck_file(
-    file_path: &Path,
-    pdf_file_path: &Path,
-    counter: Option<AtomicU16>,
-) -> Result<(), String> {
-    let file = File::open(file_path)
-        .map_err(|e| format!("Could not open data file: {}", e.to_string()))?;
-    let deck = crate::deck::process_input(file).unwrap();
-    let data_repo = DataRepository::new(Path::new("data_repository")).unwrap();
-    let deck = deck.as_hydrated(&data_repo);
-    let deck = deck.as_picked();
-    let image_repo = ImageRepository::new(Path::new("image_repository"), None)
-        .expect("Expected image repository constructor to work");
-    let layer = "Layer 1";
-    let (doc, front_page, front_layer) =
-        PdfDocument::new("Proxy Deck", PAGE_WIDTH_A4, PAGE_HEIGHT_A4, layer);
-    let mut index = 0;
-    let mut page = 0;
-    let (back_page, back_layer) = doc.add_page(PAGE_WIDTH_A4, PAGE_HEIGHT_A4, layer);
-    let mut pdf_indexes: ((PdfPageIndex, PdfLayerIndex), (PdfPageIndex, PdfLayerIndex)) =
-        ((front_page, front_layer), (back_page, back_layer));
-    let total = deck
-        .cards
-        .iter()
-        .map(|(_board, cards)| {
-            cards
-                .into_iter()
-                .map(|c| c.quantity)
-                .reduce(|a, b| a + b)
-                .unwrap()
-        })
-        .reduce(|a, b| a + b)
-        .unwrap();
-    for (_board, cards) in &deck.cards {
-        for card in cards {
-            for _ in 0..card.quantity {
-                println!(
-                    "[{}/{}] Rendering card {} [{}:{}] to pdf",
-                    index, total, card.name, card.set_code, card.collector_number
-                );
-
-                let (new_page, x, y, x_flip) = grid_translator(index);
-                let mut draw_grid = false;
-                if new_page > page {
-                    let (front_page, front_layer) =
-                        doc.add_page(PAGE_WIDTH_A4, PAGE_HEIGHT_A4, layer);
-                    let (back_page, back_layer) =
-                        doc.add_page(PAGE_WIDTH_A4, PAGE_HEIGHT_A4, layer);
-                    pdf_indexes = ((front_page, front_layer), (back_page, back_layer));
-                    page = new_page;
-                    draw_grid = true;
-                }
-
-                let front_layer_reference =
-                    doc.get_page(pdf_indexes.0 .0).get_layer(pdf_indexes.0 .1);
-                let back_layer_reference =
-                    doc.get_page(pdf_indexes.1 .0).get_layer(pdf_indexes.1 .1);
-
-                if (index == 0) || draw_grid {
-                    // Draw 4 (2x2) black lines
-                    let lines = [
-                        printpdf::Line {
-                            points: vec![
-                                (Point::new(WIDTH_OFFSET_MM + CARD_WIDTH, Mm(0.0)), false),
-                                (
-                                    Point::new(WIDTH_OFFSET_MM + CARD_WIDTH, PAGE_HEIGHT_A4),
-                                    false,
-                                ),
-                            ],
-                            is_closed: false,
-                        },
-                        printpdf::Line {
-                            points: vec![
-                                (
-                                    Point::new(WIDTH_OFFSET_MM + CARD_WIDTH + CARD_WIDTH, Mm(0.0)),
-                                    false,
-                                ),
-                                (
-                                    Point::new(
-                                        WIDTH_OFFSET_MM + CARD_WIDTH + CARD_WIDTH,
-                                        PAGE_HEIGHT_A4,
-                                    ),
-                                    false,
-                                ),
-                            ],
-                            is_closed: false,
-                        },
-                        printpdf::Line {
-                            points: vec![
-                                (Point::new(Mm(0.0), HEIGHT_OFFSET_MM + CARD_HEIGHT), false),
-                                (
-                                    Point::new(PAGE_WIDTH_A4, HEIGHT_OFFSET_MM + CARD_HEIGHT),
-                                    false,
-                                ),
-                            ],
-                            is_closed: false,
-                        },
-                        printpdf::Line {
-                            points: vec![
-                                (
-                                    Point::new(
-                                        Mm(0.0),
-                                        HEIGHT_OFFSET_MM + CARD_HEIGHT + CARD_HEIGHT,
-                                    ),
-                                    false,
-                                ),
-                                (
-                                    Point::new(
-                                        PAGE_WIDTH_A4,
-                                        HEIGHT_OFFSET_MM + CARD_HEIGHT + CARD_HEIGHT,
-                                    ),
-                                    false,
-                                ),
-                            ],
-                            is_closed: false,
-                        },
-                    ];
-                    for line in lines.clone() {
-                        front_layer_reference.add_line(line)
-                    }
-                    for line in lines {
-                        back_layer_reference.add_line(line);
-                    }
-                }
-
-                let (mut front, mut back) = image_repo.get_image(card).unwrap();
-                let front_decoder =
-                    printpdf::image_crate::codecs::jpeg::JpegDecoder::new(&mut front).unwrap();
-                let front_img = Image::try_from(front_decoder).unwrap();
-
-                let back_decoder =
-                    printpdf::image_crate::codecs::jpeg::JpegDecoder::new(&mut back).unwrap();
-                let back_img = Image::try_from(back_decoder).unwrap();
-
-                let front_dpi = calculate_dpi_image(&front_img);
-                front_img.add_to_layer(
-                    front_layer_reference,
-                    ImageTransform {
-                        translate_x: Some(x),
-                        translate_y: Some(y),
-                        rotate: None,
-                        scale_x: None,
-                        scale_y: None,
-                        dpi: Some(front_dpi),
-                    },
-                );
-
-                let back_dpi = calculate_dpi_image(&back_img);
-                back_img.add_to_layer(
-                    back_layer_reference,
-                    ImageTransform {
-                        translate_x: Some(x_flip),
-                        translate_y: Some(y),
-                        rotate: None,
-                        scale_x: None,
-                        scale_y: None,
-                        dpi: Some(back_dpi),
-                    },
-                );
-                if let Some(counter) = &counter {
-                    let val = counter.fetch_sub(1, Ordering::SeqCst);
-                    if val <= 1 {
-                        break;
-                    }
-                }
-                index += 1;
-            }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a duplex calibration sheet to measure how far the printer shifts the back page
+    Calibrate {
+        /// Output PDF name
+        #[arg(short = 'o', long, default_value = "calibration.pdf")]
+        output_pdf_name: String,
+    },
+    /// Draw the planned pages with small card images into PNG or SVG files named after the
+    /// output PDF, to check the layout without printing. Options other than the input files go
+    /// before the command.
+    Preview {
+        /// File format of the preview pages
+        #[arg(long, value_enum, default_value_t = PreviewFormat::Png)]
+        format: PreviewFormat,
+
+        /// Pixels per mm of PNG pages
+        #[arg(long, default_value_t = 2.0)]
+        scale: f32,
+    },
+}
+
+/// Duplex settings from the printer profile, overridden by any given on the command line
+fn duplex_settings(args: &Args) -> Result<Duplex, String> {
+    let saved = match &args.printer {
+        Some(printer) => Duplex::load(printer)?,
+        None => Duplex::default(),
+    };
+    let rotate_back = match (args.rotate_back, args.no_rotate_back) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => saved.rotate_back,
+    };
+    let duplex = Duplex {
+        flip: args.duplex_flip.or(saved.flip),
+        rotate_back,
+        back_offset_x: args.back_offset_x.unwrap_or(saved.back_offset_x),
+        back_offset_y: args.back_offset_y.unwrap_or(saved.back_offset_y),
+    };
+    if let (Some(printer), true) = (&args.printer, args.save_printer) {
+        if duplex != saved {
+            duplex.save(printer)?;
+            println!("Saved duplex settings for printer '{}'", printer);
         }
     }
-    doc.save(&mut BufWriter::new(File::create(pdf_file_path).unwrap()))
-        .unwrap();
+    Ok(duplex)
+}
 
-    Ok(())
+/// Card back overrides from --deck-back, --section-back and --card-back-map
+fn card_backs(args: &Args) -> Result<CardBacks, String> {
+    let cards = match &args.card_back_map {
+        Some(path) => CardMap::load(path)?,
+        None => CardMap::default(),
+    };
+    let backs = CardBacks::new()
+        .with_decks(args.deck_back.clone())
+        .with_sections(args.section_back.clone())
+        .with_cards(cards);
+    backs.validate()?;
+    Ok(backs)
 }
 
-fn main() {
-    let args = Args::parse();
+/// Card sizes from the command line. Cards Scryfall marks as oversized, such as planes, keep
+/// their size unless one is given for them.
+fn card_sizes(args: &Args) -> Result<CardSizes, String> {
+    let cards = match &args.card_size_map {
+        Some(path) => CardMap::load_with(path, str::parse)?,
+        None => CardMap::default(),
+    };
+    Ok(CardSizes::new()
+        .with_default(args.card_size)
+        .with_sections(args.section_size.clone())
+        .with_cards(cards))
+}
+
+/// Artwork overrides from the directory and mapping files given on the command line
+fn artwork(args: &Args) -> Result<Artwork, String> {
+    let mut artwork = Artwork::new();
+    if let Some(directory) = &args.art_dir {
+        artwork = artwork.with_directory(directory)?;
+    }
+    if let Some(mapping) = &args.art_map {
+        artwork = artwork.with_mapping(mapping, false)?;
+    }
+    if let Some(mapping) = &args.art_back_map {
+        artwork = artwork.with_mapping(mapping, true)?;
+    }
+    artwork.validate()?;
+    Ok(artwork)
+}
+
+/// How card images are stored in the PDF
+fn image_compression(args: &Args) -> Result<ImageCompression, String> {
+    let compression = ImageCompression {
+        max_dpi: args.max_dpi,
+        jpeg_quality: args.jpeg_quality,
+    };
+    compression.validate()?;
+    Ok(compression)
+}
+
+/// The cards of each deck to print, from the section, name, range and limit options
+fn selection(args: &Args) -> Result<Selection, String> {
+    let names = match &args.only_names {
+        Some(path) => Some(CardMap::load_list(path)?),
+        None => None,
+    };
+    Ok(Selection::new()
+        .with_sections(args.only_section.clone())
+        .with_names(names)
+        .with_range(args.range.clone())
+        .with_limit(args.limit))
+}
+
+/// The watermark from the watermark options, if one was asked for. Its {deck} placeholder is
+/// filled in with the deck of each card as the card is drawn.
+fn watermark(args: &Args) -> Result<Option<Watermark>, String> {
+    let Some(text) = &args.watermark else {
+        return Ok(None);
+    };
+    if !(0.0..=1.0).contains(&args.watermark_opacity) {
+        return Err("Watermark opacity must be between 0 and 1".to_string());
+    }
+    let watermark = Watermark {
+        text: text.clone(),
+        position: args.watermark_position,
+        size: args.watermark_size,
+        opacity: args.watermark_opacity,
+        colour: args.watermark_colour,
+        backs: args.watermark_backs,
+    };
+    Ok(Some(watermark))
+}
+
+/// Read, look up and pick the printings of one deck file, marking its cards with the deck name
+fn load_deck(
+    file_path: &Path,
+    data_repo: &DataRepository,
+    artwork: &Artwork,
+    include_tokens: Option<u32>,
+    options: &RenderOptions,
+) -> Result<PickedDeck, String> {
+    let file = File::open(file_path).map_err(|e| format!("Could not open data file: {}", e))?;
+    let deck = crate::deck::process_input(file).map_err(|e| format!("{:?}: {}", file_path, e))?;
+    // A text proxy only needs a name, so cards Scryfall does not know can still be printed
+    let deck = deck.as_hydrated_with_custom(data_repo, |card| {
+        options.text_proxies || artwork.has_front(card)
+    })?;
+    let deck = deck.as_picked();
+    let deck = match include_tokens {
+        Some(copies) => deck.with_tokens(data_repo, copies)?,
+        None => deck,
+    };
+    let deck_name = file_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(deck.with_origin(&deck_name))
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let page_spec = PageSpec::new(args.paper, args.orientation);
+    let (page_width, page_height) = (page_spec.width(), page_spec.height());
+    let (margin, keep_out) = match args.cutter {
+        Some(cutter) => (
+            Mm(args.margin).max(cutter.margin(page_width, page_height)),
+            cutter.keep_out(page_width, page_height),
+        ),
+        None => (Mm(args.margin), vec![]),
+    };
+    let layout = Layout::new(&page_spec)
+        .with_margin(margin)
+        .with_keep_out(keep_out)
+        .with_gutter(Mm(args.gutter))
+        .with_bleed(Mm(args.bleed))
+        .with_columns(args.columns)
+        .with_rows(args.rows)
+        .with_card_rotation(args.rotate)
+        .with_duplex(duplex_settings(&args)?);
+    if let Some(Command::Calibrate { output_pdf_name }) = &args.command {
+        write_calibration_sheet(&layout, Path::new(output_pdf_name))?;
+        println!("Wrote calibration sheet to {}", output_pdf_name);
+        return Ok(());
+    }
     // let file_path =
     //     "/Users/hugh/Downloads/mtg-cube-project-halloween/CalebGannonsPoweredSynergyCube.dck";
-    let file_path = Path::new(&args.input_file);
+    let inputs = expand_inputs(&args.input_file)?;
+    let first_input = inputs.first().ok_or("No input file given")?;
     let output = match &args.output_pdf_name {
-        None => file_path.with_extension("pdf"),
         Some(f) => PathBuf::from(Path::new(f)),
+        None if inputs.len() == 1 => first_input.with_extension("pdf"),
+        None => first_input.with_file_name(COMBINED_PDF),
+    };
+    let title = inputs
+        .iter()
+        .map(|input| {
+            input
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+    let options = RenderOptions {
+        layout,
+        guides: args.guides,
+        bleed_fill: args.bleed_fill,
+        mode: args.mode,
+        card_backs: card_backs(&args)?,
+        card_sizes: card_sizes(&args)?,
+        processing: Pipeline::new(args.processing.clone())
+            .with_cache(Path::new("image_repository").join("processed")),
+        compression: image_compression(&args)?,
+        watermark: watermark(&args)?,
+        grey_art: args.grey_art,
+        text_proxies: args.text_proxies,
+        deck_tags: args.deck_tags,
+        title,
+        author: args.author.clone(),
+        pdf_x: args.pdf_x,
+        checklist: args.checklist,
+        dividers: args.dividers,
+        cutter: args.cutter,
+        split_pages: Some(args.split_pages).filter(|pages| *pages > 0),
+        reprint: Reprint::new(args.pages.clone(), &args.reprint, &output)
+            .map(|reprint| reprint.with_ignore_lock(args.ignore_layout_lock)),
     };
-    let counter = args.card_count.map(|c| AtomicU16::new(c));
-    if let Err(e) = process_dck_file(file_path, &output, counter) {
+    if options.reprint.is_some()
+        && (args.command.is_some()
+            || args.print_shop.is_some()
+            || args.output_format != OutputFormat::Pdf)
+    {
+        return Err("--pages and --reprint only apply to PDF output".to_string());
+    }
+    let artwork = artwork(&args)?;
+    let data_repo = DataRepository::new(Path::new("data_repository")).unwrap();
+    let mut deck = PickedDeck::new();
+    for input in &inputs {
+        deck.merge(load_deck(
+            input,
+            &data_repo,
+            &artwork,
+            args.include_tokens,
+            &options,
+        )?);
+    }
+    // Owned cards are taken out of the whole run, so a card owned once is only skipped once
+    if let Some(path) = &args.exclude_collection {
+        let collection = Collection::load(path)?;
+        exclude_collection(&mut deck, collection, args.collection_match).print();
+    }
+    selection(&args)?.apply(&mut deck)?;
+    if inputs.len() > 1 {
+        for (deck_name, count) in deck.counts_by_deck() {
+            println!("{}: {} cards", deck_name, count);
+        }
+    }
+    let image_repo =
+        ImageRepository::new(Path::new("image_repository"), args.card_back.as_deref())?
+            .with_artwork(artwork)
+            .with_quality(args.image_quality);
+    if let Some(Command::Preview { format, scale }) = &args.command {
+        let image_repo = image_repo.with_quality(ImageQuality::Small);
+        return preview_deck(&deck, &image_repo, &options, *format, *scale, &output);
+    }
+    let export = ImageExport {
+        format: args.image_format,
+        dpi: args.dpi,
+        zip: args.output_format == OutputFormat::Zip,
+    };
+    let output = match args.output_format {
+        OutputFormat::Pdf if args.print_shop.is_none() => {
+            let output = match &options.reprint {
+                Some(reprint) => reprint.output_path(),
+                None => output,
+            };
+            return render_deck(&deck, &image_repo, &options, &output);
+        }
+        OutputFormat::Zip => output.with_extension("zip"),
+        _ => output.with_extension(""),
+    };
+    match args.print_shop {
+        Some(shop) => export_print_shop(&deck, &image_repo, &options, shop, &export, &output),
+        None => export_images(&deck, &image_repo, &options, &export, &output),
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Err(e) = run(args) {
         eprintln!("Error processing file: {}", e);
     }
 }
@@ -0,0 +1,198 @@
+use crate::deck::PickedCard;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+/// Files, or other values, assigned to individual cards, read from a mapping file with one
+/// `<card> = <file>` per line. A card is either its name or its printing as `[SET:NUMBER]`, the
+/// same way an XMage deck names it. Blank lines and lines starting with `#` are skipped, and
+/// relative files are taken relative to the mapping file.
+#[derive(Debug, Clone)]
+pub struct CardMap<T = PathBuf> {
+    by_name: HashMap<String, T>,
+    by_printing: HashMap<(String, String), T>,
+}
+
+impl<T> Default for CardMap<T> {
+    fn default() -> Self {
+        CardMap {
+            by_name: HashMap::new(),
+            by_printing: HashMap::new(),
+        }
+    }
+}
+
+impl CardMap {
+    pub fn load(path: &Path) -> Result<CardMap, String> {
+        let text = read_to_string(path)
+            .map_err(|e| format!("Could not read mapping file {:?}: {}", path, e))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        CardMap::parse(&text, base).map_err(|e| format!("{:?}: {}", path, e))
+    }
+
+    pub fn parse(text: &str, base: &Path) -> Result<CardMap, String> {
+        CardMap::parse_with(text, |file| Ok(base.join(file)))
+    }
+
+    /// Every file in the map
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.by_name
+            .values()
+            .chain(self.by_printing.values())
+            .map(PathBuf::as_path)
+    }
+
+    /// The file for a card, matching its exact printing first and then its name. Double faced
+    /// cards also match on the name of their front face.
+    pub fn get(&self, card: &PickedCard) -> Option<&Path> {
+        self.get_value(card).map(PathBuf::as_path)
+    }
+
+    /// As `get`, for a card that may not have a printing picked yet
+    pub fn lookup(&self, name: &str, printing: Option<(&str, &str)>) -> Option<&Path> {
+        self.lookup_value(name, printing).map(PathBuf::as_path)
+    }
+}
+
+impl CardMap<()> {
+    /// Read a list of cards, one name or `[SET:NUMBER]` per line, skipping blank lines and lines
+    /// starting with `#`
+    pub fn load_list(path: &Path) -> Result<CardMap<()>, String> {
+        let text = read_to_string(path)
+            .map_err(|e| format!("Could not read card list {:?}: {}", path, e))?;
+        Ok(CardMap::parse_list(&text))
+    }
+
+    pub fn parse_list(text: &str) -> CardMap<()> {
+        let mut map = CardMap::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_printing(line) {
+                Some((set, number)) => map.insert_printing(&set, &number, ()),
+                None => map.insert_name(line, ()),
+            };
+        }
+        map
+    }
+
+    /// Whether the list has the card, matched the same way as `get`
+    pub fn contains(&self, card: &PickedCard) -> bool {
+        self.get_value(card).is_some()
+    }
+}
+
+impl<T> CardMap<T> {
+    /// Read a mapping file whose values are read by `value` instead of taken as files
+    pub fn load_with<F>(path: &Path, value: F) -> Result<CardMap<T>, String>
+    where
+        F: Fn(&str) -> Result<T, String>,
+    {
+        let text = read_to_string(path)
+            .map_err(|e| format!("Could not read mapping file {:?}: {}", path, e))?;
+        CardMap::parse_with(&text, value).map_err(|e| format!("{:?}: {}", path, e))
+    }
+
+    pub fn parse_with<F>(text: &str, value: F) -> Result<CardMap<T>, String>
+    where
+        F: Fn(&str) -> Result<T, String>,
+    {
+        let mut map = CardMap::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (card, file) = line
+                .rsplit_once('=')
+                .ok_or_else(|| format!("line {}: expected `<card> = <file>`", number + 1))?;
+            let card = card.trim();
+            let file = value(file.trim()).map_err(|e| format!("line {}: {}", number + 1, e))?;
+            match parse_printing(card) {
+                Some((set, number)) => map.insert_printing(&set, &number, file),
+                None => map.insert_name(card, file),
+            };
+        }
+        Ok(map)
+    }
+
+    pub fn insert_name(&mut self, name: &str, file: T) {
+        self.by_name.insert(name.to_lowercase(), file);
+    }
+
+    pub fn insert_printing(&mut self, set: &str, collector_number: &str, file: T) {
+        let printing = (set.to_lowercase(), collector_number.to_lowercase());
+        self.by_printing.insert(printing, file);
+    }
+
+    /// Add every entry of another map, replacing any for the same card
+    pub fn extend(&mut self, other: CardMap<T>) {
+        self.by_name.extend(other.by_name);
+        self.by_printing.extend(other.by_printing);
+    }
+
+    /// The value for a card, matched the same way as `get`
+    pub fn get_value(&self, card: &PickedCard) -> Option<&T> {
+        self.lookup_value(&card.name, Some((&card.set_code, &card.collector_number)))
+    }
+
+    /// As `get_value`, for a card that may not have a printing picked yet
+    pub fn lookup_value(&self, name: &str, printing: Option<(&str, &str)>) -> Option<&T> {
+        let printing = printing.map(|(set, number)| (set.to_lowercase(), number.to_lowercase()));
+        let name = name.to_lowercase();
+        let front_name = name.split("//").next().unwrap_or_default().trim();
+        printing
+            .and_then(|printing| self.by_printing.get(&printing))
+            .or_else(|| self.by_name.get(&name))
+            .or_else(|| self.by_name.get(front_name))
+    }
+}
+
+/// `[SET:NUMBER]` to a lower case (set, collector number) pair
+fn parse_printing(card: &str) -> Option<(String, String)> {
+    let inner = card.strip_prefix('[')?.strip_suffix(']')?;
+    let (set, number) = inner.split_once(':')?;
+    Some((set.trim().to_lowercase(), number.trim().to_lowercase()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::deck::PickedCard;
+    use crate::mapping::CardMap;
+    use std::path::Path;
+
+    #[test]
+    pub fn test_card_map() {
+        let card = |name, set_code: &str, collector_number: &str| PickedCard {
+            set_code: set_code.to_string(),
+            collector_number: collector_number.to_string(),
+            ..PickedCard::new_for_test(name, 1)
+        };
+        let text = r#"
+# Alters
+Lightning Bolt = bolt.png
+[M21:176] = dreadmaw.jpg
+Delver of Secrets = /art/delver.png
+        "#;
+        let map = CardMap::parse(text, Path::new("backs")).unwrap();
+        assert_eq!(
+            map.get(&card("Lightning Bolt", "m10", "146")),
+            Some(Path::new("backs/bolt.png"))
+        );
+        assert_eq!(
+            map.get(&card("Colossal Dreadmaw", "m21", "176")),
+            Some(Path::new("backs/dreadmaw.jpg"))
+        );
+        assert_eq!(map.get(&card("Colossal Dreadmaw", "xln", "180")), None);
+        assert_eq!(
+            map.get(&card(
+                "Delver of Secrets // Insectile Aberration",
+                "isd",
+                "51"
+            )),
+            Some(Path::new("/art/delver.png"))
+        );
+        assert!(CardMap::parse("Lightning Bolt", Path::new("")).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use crate::layout::{Layout, Slot};
+use printpdf::Mm;
+
+/// Slack allowed when checking a row fits, for rounding in the row positions
+const FIT_TOLERANCE: f32 = 0.01;
+
+/// A row of cards of one size across a page
+#[derive(Debug, Clone, PartialEq)]
+pub struct Band {
+    pub page: usize,
+    /// Index of the layout of the cards in the row
+    pub layout: usize,
+    /// Top of the bleed of the cards in the row
+    pub top: Mm,
+    /// Number of places in the row used so far, by cards or left empty
+    pub cards: usize,
+}
+
+/// Where each card of a print run goes when cards of different sizes share pages. Pages are
+/// filled from the top with rows of cards in print order, each row holding cards of one size
+/// laid out on the grid of their own layout. A run of cards all the same size lands exactly
+/// where its layout's grid puts it, skipping any slot its layout keeps clear.
+#[derive(Debug, Clone, Default)]
+pub struct Packing {
+    pub slots: Vec<Slot>,
+    pub bands: Vec<Band>,
+}
+
+impl Packing {
+    /// Pack cards given as the index of their layout in `layouts`, in print order
+    pub fn new(layouts: &[Layout], cards: &[usize]) -> Packing {
+        let mut packing = Packing::default();
+        for &card in cards {
+            let layout = &layouts[card];
+            // Slots in areas that have to be kept clear are left empty
+            loop {
+                let has_room = packing
+                    .bands
+                    .last()
+                    .is_some_and(|band| band.layout == card && band.cards < layout.columns());
+                if !has_room {
+                    let band = packing.next_band(layouts, card);
+                    packing.bands.push(band);
+                }
+                let band = packing.bands.last_mut().expect("A row was just added");
+                let slot = layout.slot_in_row(band.page, band.cards, band.top);
+                band.cards += 1;
+                if !layout.is_kept_out(&slot) {
+                    packing.slots.push(slot);
+                    break;
+                }
+            }
+        }
+        packing
+    }
+
+    /// A new row for a card, below the last row if it fits there or else at the top of a new
+    /// page where the card's grid starts
+    fn next_band(&self, layouts: &[Layout], card: usize) -> Band {
+        let layout = &layouts[card];
+        let Some(last) = self.bands.last() else {
+            return Band {
+                page: 0,
+                layout: card,
+                top: layout.grid_area().top(),
+                cards: 0,
+            };
+        };
+        let top = last.top - layouts[last.layout].row_height() - layout.gutter();
+        let rows = self
+            .bands_on(last.page)
+            .filter(|band| band.layout == card)
+            .count();
+        let fits = (top - layout.row_height()).0 >= layout.margin().0 - FIT_TOLERANCE;
+        match fits && rows < layout.rows() {
+            true => Band {
+                page: last.page,
+                layout: card,
+                top,
+                cards: 0,
+            },
+            false => Band {
+                page: last.page + 1,
+                layout: card,
+                top: layout.grid_area().top(),
+                cards: 0,
+            },
+        }
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.bands.last().map_or(0, |band| band.page + 1)
+    }
+
+    pub fn bands_on(&self, page: usize) -> impl Iterator<Item = &Band> {
+        self.bands.iter().filter(move |band| band.page == page)
+    }
+
+    /// The layout of every card on a page, or None if the page mixes card sizes
+    pub fn page_layout(&self, page: usize) -> Option<usize> {
+        let mut layouts = self.bands_on(page).map(|band| band.layout);
+        let first = layouts.next()?;
+        layouts.all(|layout| layout == first).then_some(first)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::card_size::CardSize;
+    use crate::layout::{CardRotation, Layout};
+    use crate::packing::Packing;
+    use crate::pdf_calc::PageSpec;
+
+    #[test]
+    pub fn test_uniform_packing_matches_grid() {
+        let layouts = [Layout::new(&PageSpec::default())];
+        let packing = Packing::new(&layouts, &[0; 20]);
+        for (index, slot) in packing.slots.iter().enumerate() {
+            assert_eq!(*slot, layouts[0].slot(index));
+        }
+        assert_eq!(packing.page_count(), 3);
+        assert_eq!(packing.page_layout(0), Some(0));
+        assert_eq!(Packing::new(&layouts, &[0; 18]).page_count(), 2);
+        assert_eq!(Packing::new(&layouts, &[0; 19]).page_count(), 3);
+    }
+
+    #[test]
+    pub fn test_mixed_packing() {
+        let layout = Layout::new(&PageSpec::default()).with_card_rotation(CardRotation::Auto);
+        let oversized = layout.clone().with_card_size(CardSize::OVERSIZED);
+        let planes = layout
+            .clone()
+            .with_card_size(CardSize::OVERSIZED.landscape());
+        // Oversized cards fit two across, planes one across unless turned
+        assert_eq!(oversized.columns(), 2);
+        assert_eq!(planes.columns(), 2);
+        let layouts = [layout, oversized, planes];
+        let packing = Packing::new(&layouts, &[1, 0, 0, 0, 0, 2]);
+        let slots = &packing.slots;
+        // The commander row is followed by a row of three standard cards
+        assert_eq!(slots[1].page, 0);
+        assert_eq!(slots[1].front.top(), slots[0].front.y);
+        assert_eq!(slots[3].front.y, slots[1].front.y);
+        // The next row of standard cards is too tall for what is left of the page
+        assert_eq!(slots[4].page, 1);
+        assert_eq!(slots[4].front, layouts[0].slot(0).front);
+        // The plane, turned to fit two across, fits below it
+        assert_eq!(slots[5].page, 1);
+        assert!(slots[5].rotated);
+        assert_eq!(slots[5].front.top(), slots[4].front.y);
+        assert_eq!(packing.page_layout(0), None);
+        assert_eq!(packing.page_count(), 2);
+    }
+}
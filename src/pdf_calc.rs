@@ -1,4 +1,7 @@
-use printpdf::{Image, Mm};
+use crate::card_size::CardSize;
+use printpdf::Mm;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 pub const CARD_WIDTH: Mm = Mm(63.0);
 pub const CARD_HEIGHT: Mm = Mm(88.0);
@@ -6,43 +9,144 @@ pub const CARD_HEIGHT: Mm = Mm(88.0);
 pub const PAGE_WIDTH_A4: Mm = Mm(210.0);
 pub const PAGE_HEIGHT_A4: Mm = Mm(297.0);
 
-///
-/// 3 cards wide at
-/// A4 is 210mm x 297mm
-pub const WIDTH_OFFSET_MM: Mm = Mm((PAGE_WIDTH_A4.0 - (3f32 * CARD_WIDTH.0)) / 2.0);
-pub const HEIGHT_OFFSET_MM: Mm = Mm((PAGE_HEIGHT_A4.0 - (3f32 * CARD_HEIGHT.0)) / 2.0);
-
-/// Given a card index, return it's position in a pdf
-/// (page_number from 0, x position, y position, x position on other side)
-pub fn grid_translator(index: usize) -> (usize, Mm, Mm, Mm) {
-    let page = index / 9;
-    let pos_index = index % 9;
-    let grid_x = pos_index % 3;
-    let grid_x_flip = 2 - grid_x;
-    let grid_x = grid_x as f32;
-    let grid_x_flip = grid_x_flip as f32;
-    let grid_y = pos_index / 3;
-    // Offsets are calculated from left bottom so we need to invert top (0,1 2) becomes (2,1,0)
-    let grid_y = 2 - grid_y;
-    let grid_y = grid_y as f32;
-    let x = Mm(CARD_WIDTH.0 * grid_x);
-    let x = Mm(WIDTH_OFFSET_MM.0 + x.0);
-    let y = Mm(CARD_HEIGHT.0 * grid_y);
-    let y = Mm(HEIGHT_OFFSET_MM.0 + y.0);
-    let x_flip = Mm(CARD_WIDTH.0 * grid_x_flip);
-    let x_flip = Mm(WIDTH_OFFSET_MM.0 + x_flip.0);
-    (page, x, y, x_flip)
+const MM_PER_INCH: f32 = 25.4;
+
+/// Named paper presets, or a custom size given in millimetres
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PaperSize {
+    A4,
+    A3,
+    Letter,
+    Legal,
+    Tabloid,
+    Custom { width: Mm, height: Mm },
+}
+
+impl PaperSize {
+    /// Dimensions of the paper in portrait, as (width, height)
+    pub fn dimensions(&self) -> (Mm, Mm) {
+        match self {
+            PaperSize::A4 => (PAGE_WIDTH_A4, PAGE_HEIGHT_A4),
+            PaperSize::A3 => (Mm(297.0), Mm(420.0)),
+            PaperSize::Letter => (Mm(8.5 * MM_PER_INCH), Mm(11.0 * MM_PER_INCH)),
+            PaperSize::Legal => (Mm(8.5 * MM_PER_INCH), Mm(14.0 * MM_PER_INCH)),
+            PaperSize::Tabloid => (Mm(11.0 * MM_PER_INCH), Mm(17.0 * MM_PER_INCH)),
+            PaperSize::Custom { width, height } => match width.0 <= height.0 {
+                true => (*width, *height),
+                false => (*height, *width),
+            },
+        }
+    }
+}
+
+impl FromStr for PaperSize {
+    type Err = String;
+
+    /// Accepts a preset name (a4, a3, letter, legal, tabloid) or a custom size such as
+    /// `210x297mm` or `8.5x11in`. Sizes without a unit are treated as millimetres.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        match s.as_str() {
+            "a4" => return Ok(PaperSize::A4),
+            "a3" => return Ok(PaperSize::A3),
+            "letter" => return Ok(PaperSize::Letter),
+            "legal" => return Ok(PaperSize::Legal),
+            "tabloid" | "ledger" => return Ok(PaperSize::Tabloid),
+            _ => {}
+        }
+        let (width, height) =
+            parse_dimensions(&s).map_err(|e| format!("Unknown paper size '{}': {}", s, e))?;
+        Ok(PaperSize::Custom { width, height })
+    }
+}
+
+/// Parse a size such as `210x297mm` or `8.5x11in` into (width, height). Sizes without a unit
+/// are treated as millimetres.
+pub fn parse_dimensions(s: &str) -> Result<(Mm, Mm), String> {
+    let s = s.trim().to_lowercase();
+    let (dims, scale) = if let Some(dims) = s.strip_suffix("mm") {
+        (dims, 1.0)
+    } else if let Some(dims) = s.strip_suffix("in") {
+        (dims, MM_PER_INCH)
+    } else {
+        (s.as_str(), 1.0)
+    };
+    let (width, height) = dims
+        .split_once('x')
+        .ok_or_else(|| "expected <width>x<height>".to_string())?;
+    let parse = |v: &str| {
+        v.trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|v| *v > 0.0)
+            .ok_or_else(|| format!("invalid dimension '{}'", v))
+    };
+    Ok((Mm(parse(width)? * scale), Mm(parse(height)? * scale)))
+}
+
+impl Display for PaperSize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaperSize::A4 => f.write_str("a4"),
+            PaperSize::A3 => f.write_str("a3"),
+            PaperSize::Letter => f.write_str("letter"),
+            PaperSize::Legal => f.write_str("legal"),
+            PaperSize::Tabloid => f.write_str("tabloid"),
+            PaperSize::Custom { width, height } => {
+                f.write_fmt(format_args!("{}x{}mm", width.0, height.0))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Orientation {
+    #[default]
+    Portrait,
+    Landscape,
 }
 
-pub fn calculate_dpi_image(img: &Image) -> f32 {
-    calculate_dpi(img.image.width.0, img.image.height.0)
+/// The sheet of paper cards are printed on, and how a grid of cards is centred on it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PageSpec {
+    pub paper: PaperSize,
+    pub orientation: Orientation,
+}
+
+impl Default for PageSpec {
+    fn default() -> Self {
+        PageSpec::new(PaperSize::A4, Orientation::Portrait)
+    }
+}
+
+impl PageSpec {
+    pub fn new(paper: PaperSize, orientation: Orientation) -> PageSpec {
+        PageSpec { paper, orientation }
+    }
+
+    pub fn width(&self) -> Mm {
+        let (width, height) = self.paper.dimensions();
+        match self.orientation {
+            Orientation::Portrait => width,
+            Orientation::Landscape => height,
+        }
+    }
+
+    pub fn height(&self) -> Mm {
+        let (width, height) = self.paper.dimensions();
+        match self.orientation {
+            Orientation::Portrait => height,
+            Orientation::Landscape => width,
+        }
+    }
 }
 
-/// Given an image width and height, and assuming it is 63mm x 88mm, calculate the DPI
-pub fn calculate_dpi(width: usize, height: usize) -> f32 {
+/// Given an image width and height, and assuming it covers a card of the given size, calculate
+/// the DPI
+pub fn calculate_dpi(width: usize, height: usize, size: &CardSize) -> f32 {
     // Convert mm to inches
-    let width_in_inches = 63.0 / 25.4;
-    let height_in_inches = 88.0 / 25.4;
+    let width_in_inches = size.width.0 / MM_PER_INCH;
+    let height_in_inches = size.height.0 / MM_PER_INCH;
 
     // Calculate DPI for width and height
     let dpi_width = width as f32 / width_in_inches;
@@ -56,13 +160,19 @@ pub fn calculate_dpi(width: usize, height: usize) -> f32 {
     }
 }
 
+/// Number of pixels that cover a length at the given DPI
+pub fn pixels_at_dpi(length: Mm, dpi: f32) -> u32 {
+    (length.0 / MM_PER_INCH * dpi).round() as u32
+}
+
 #[cfg(test)]
 mod test {
+    use crate::card_size::CardSize;
     use crate::data_repository::DataRepository;
     use crate::deck::{DehydratedDeck, MAINBOARD};
     use crate::image_repository::ImageRepository;
-    use crate::pdf_calc::{calculate_dpi, grid_translator};
-    use printpdf::Image;
+    use crate::pdf_calc::{calculate_dpi, PaperSize};
+    use printpdf::{Image, Mm};
     use std::path::Path;
 
     #[test]
@@ -72,7 +182,11 @@ mod test {
         let deck = DehydratedDeck::new_for_test("Colossal Dreadmaw");
         let deck = deck.as_hydrated(&data_repo);
         let mut deck = deck.as_picked();
-        let card = deck.cards.get_mut(MAINBOARD).map(|v| v.into_iter().next().unwrap()).unwrap();
+        let card = deck
+            .cards
+            .get_mut(MAINBOARD)
+            .map(|v| v.iter_mut().next().unwrap())
+            .unwrap();
 
         let (mut front, mut back) = image_repo.get_image(card).unwrap();
         let front_decoder =
@@ -86,121 +200,27 @@ mod test {
         let (bw, bh) = (&back_img.image.width.0, &back_img.image.height.0);
         assert_eq!((fw, fh), (&672usize, &936usize));
         assert_eq!((bw, bh), (&488usize, &680usize));
-        let front_dpi = calculate_dpi(*fw, *fh);
-        let back_dpi = calculate_dpi(*bw, *bh);
+        let front_dpi = calculate_dpi(*fw, *fh, &CardSize::STANDARD);
+        let back_dpi = calculate_dpi(*bw, *bh, &CardSize::STANDARD);
         assert_eq!(front_dpi, 270.54846f32);
         assert_eq!(back_dpi, 196.51096f32);
     }
 
     #[test]
-    pub fn test_grid_translator() {
-        let mut results = Vec::new();
-        for index in 0..10 {
-            let (page, x, y, x_flip) = grid_translator(index);
-            results.push(GridTranslateTestCase {
-                index,
-                page,
-                x_offset: x.0,
-                y_offset: y.0,
-                x_flip_offset: x_flip.0,
-            });
-        }
-        assert_eq!(results, &[
-            GridTranslateTestCase {
-                index: 0,
-                page: 0,
-                x_offset: 10.5,
-                y_offset: 88.0,
-                x_flip_offset: 0.0,
-            },
-            GridTranslateTestCase {
-                index: 1,
-                page: 0,
-                x_offset: 0.0,
-                y_offset: 0.0,
-                x_flip_offset: 0.0,
-            },
-            GridTranslateTestCase {
-                index: 2,
-                page: 0,
-                x_offset: 0.0,
-                y_offset: 0.0,
-                x_flip_offset: 0.0,
-            },
-            GridTranslateTestCase {
-                index: 3,
-                page: 0,
-                x_offset: 0.0,
-                y_offset: 0.0,
-                x_flip_offset: 0.0,
-            },
-            GridTranslateTestCase {
-                index: 4,
-                page: 0,
-                x_offset: 0.0,
-                y_offset: 0.0,
-                x_flip_offset: 0.0,
-            },
-            GridTranslateTestCase {
-                index: 5,
-                page: 0,
-                x_offset: 0.0,
-                y_offset: 0.0,
-                x_flip_offset: 0.0,
-            },
-            GridTranslateTestCase {
-                index: 6,
-                page: 0,
-                x_offset: 0.0,
-                y_offset: 0.0,
-                x_flip_offset: 0.0,
-            },
-            GridTranslateTestCase {
-                index: 7,
-                page: 0,
-                x_offset: 0.0,
-                y_offset: 0.0,
-                x_flip_offset: 0.0,
-            },
-            GridTranslateTestCase {
-                index: 8,
-                page: 0,
-                x_offset: 0.0,
-                y_offset: 0.0,
-                x_flip_offset: 0.0,
-            },
-            GridTranslateTestCase {
-                index: 9,
-                page: 0,
-                x_offset: 0.0,
-                y_offset: 0.0,
-                x_flip_offset: 0.0,
-            },
-            GridTranslateTestCase {
-                index: 10,
-                page: 1,
-                x_offset: 199.5,
-                y_offset: 176.0,
-                x_flip_offset: 0.0,
-            },
-        ]);
+    pub fn test_parse_paper_size() {
+        assert_eq!("Letter".parse::<PaperSize>(), Ok(PaperSize::Letter));
+        assert_eq!(
+            "100x150mm".parse::<PaperSize>(),
+            Ok(PaperSize::Custom {
+                width: Mm(100.0),
+                height: Mm(150.0)
+            })
+        );
+        assert_eq!(
+            "11x8.5in".parse::<PaperSize>().unwrap().dimensions(),
+            PaperSize::Letter.dimensions()
+        );
+        assert!("x297mm".parse::<PaperSize>().is_err());
+        assert!("folio".parse::<PaperSize>().is_err());
     }
-
-    #[derive(Debug)]
-    struct GridTranslateTestCase {
-        index: usize,
-        page: usize,
-        x_offset: f32,
-        y_offset: f32,
-        x_flip_offset: f32,
-    }
-
-    impl PartialEq for GridTranslateTestCase {
-        fn eq(&self, other: &Self) -> bool {
-            self.index == other.index &&
-                self.page == other.page &&
-                (self.x_offset - other.x_offset).abs() < 0.0001 &&
-                (self.y_offset - other.y_offset).abs() < 0.0001
-        }
-    }
-}
\ No newline at end of file
+}
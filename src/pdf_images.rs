@@ -0,0 +1,88 @@
+use printpdf::image_crate::codecs::jpeg::JpegEncoder;
+use printpdf::image_crate::imageops::FilterType;
+use printpdf::image_crate::DynamicImage;
+use printpdf::{ColorBits, ColorSpace, Image, ImageFilter, ImageXObject, Px};
+
+/// How card images are stored in the PDF
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct ImageCompression {
+    /// Scale down images that would print at a higher resolution than this
+    pub max_dpi: Option<f32>,
+    /// Store images as JPEGs of this quality, from 1 to 100, instead of losslessly
+    pub jpeg_quality: Option<u8>,
+}
+
+impl ImageCompression {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(dpi) = self.max_dpi.filter(|dpi| *dpi <= 0.0) {
+            return Err(format!("Maximum DPI must be above 0, got {}", dpi));
+        }
+        if let Some(quality) = self.jpeg_quality.filter(|q| !(1..=100).contains(q)) {
+            return Err(format!(
+                "JPEG quality must be between 1 and 100, got {}",
+                quality
+            ));
+        }
+        Ok(())
+    }
+
+    /// Scale an image that prints at `dpi` down to the maximum resolution, if it is above it
+    pub fn downsample(&self, image: DynamicImage, dpi: f32) -> DynamicImage {
+        let Some(max_dpi) = self.max_dpi.filter(|max_dpi| dpi > *max_dpi) else {
+            return image;
+        };
+        let scale = max_dpi / dpi;
+        let width = ((image.width() as f32 * scale).round() as u32).max(1);
+        let height = ((image.height() as f32 * scale).round() as u32).max(1);
+        image.resize_exact(width, height, FilterType::Lanczos3)
+    }
+
+    /// The image to add to a page, encoded as a JPEG if a quality was given
+    pub fn embed(&self, image: &DynamicImage) -> Result<ImageXObject, String> {
+        let Some(quality) = self.jpeg_quality else {
+            return Ok(Image::from_dynamic_image(image).image);
+        };
+        // JPEG has no transparency
+        let image = image.to_rgb8();
+        let mut jpeg = vec![];
+        JpegEncoder::new_with_quality(&mut jpeg, quality)
+            .encode_image(&image)
+            .map_err(|e| format!("Could not encode image as JPEG: {}", e))?;
+        Ok(ImageXObject {
+            width: Px(image.width() as usize),
+            height: Px(image.height() as usize),
+            color_space: ColorSpace::Rgb,
+            bits_per_component: ColorBits::Bit8,
+            interpolate: true,
+            image_data: jpeg,
+            image_filter: Some(ImageFilter::DCT),
+            smask: None,
+            clipping_bbox: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pdf_images::ImageCompression;
+    use printpdf::image_crate::{DynamicImage, RgbImage};
+
+    #[test]
+    pub fn test_downsample() {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(744, 1039));
+        let compression = ImageCompression {
+            max_dpi: Some(150.0),
+            jpeg_quality: None,
+        };
+        let smaller = compression.downsample(image.clone(), 300.0);
+        assert_eq!((smaller.width(), smaller.height()), (372, 520));
+        let same = compression.downsample(image, 120.0);
+        assert_eq!((same.width(), same.height()), (744, 1039));
+        assert!(ImageCompression {
+            max_dpi: None,
+            jpeg_quality: Some(0),
+        }
+        .validate()
+        .is_err());
+    }
+}
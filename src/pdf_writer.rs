@@ -0,0 +1,576 @@
+use crate::layout::Rect;
+use printpdf::lopdf::content::{Content, Operation};
+use printpdf::lopdf::{Dictionary, Document, Object, ObjectId, Stream, StringFormat};
+use printpdf::{
+    BuiltinFont, Color, CurTransMat, ImageXObject, Line, Mm, PdfColor, PdfMetadata, Polygon,
+    TextMatrix, XmpMetadata,
+};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+/// Version of the PDFs written, the first with transparency
+const PDF_VERSION: &str = "1.4";
+/// The printing condition of the output intent of PDF/X, which printpdf embeds the profile of
+const OUTPUT_CONDITION: &str = "Coated FOGRA39 (ISO 12647-2:2004)";
+const OUTPUT_CONDITION_ID: &str = "FOGRA39";
+const OUTPUT_CONDITION_REGISTRY: &str = "http://www.color.org";
+
+/// A built in font of a document, as its pages name it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    name: String,
+}
+
+/// An image stored once in a document, which any of its pages can draw
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageRef {
+    name: String,
+}
+
+#[derive(Debug, Default)]
+struct ImageStore {
+    /// Images already stored, by the key they were added with
+    keys: HashMap<u64, ImageRef>,
+    /// The image streams by their name, to add to the document when it is saved
+    streams: Vec<(String, Stream)>,
+}
+
+/// The images of a document. Clones share the same images.
+#[derive(Debug, Clone, Default)]
+pub struct Images {
+    store: Rc<RefCell<ImageStore>>,
+}
+
+impl Images {
+    /// The image stored under `key`, adding the one `image` makes if there is none yet. The key
+    /// has to cover everything that changes the image, so that equal keys draw equal images.
+    pub fn get_or_add<F>(&self, key: u64, image: F) -> Result<ImageRef, String>
+    where
+        F: FnOnce() -> Result<ImageXObject, String>,
+    {
+        if let Some(image) = self.store.borrow().keys.get(&key) {
+            return Ok(image.clone());
+        }
+        let mut stream = Stream::from(image()?);
+        // Compressed now rather than on saving, so the raw pixels are not held until then
+        stream
+            .compress()
+            .map_err(|e| format!("Could not compress image: {}", e))?;
+        let mut store = self.store.borrow_mut();
+        let image = ImageRef {
+            name: format!("Im{}", store.streams.len() + 1),
+        };
+        store.streams.push((image.name.clone(), stream));
+        store.keys.insert(key, image.clone());
+        Ok(image)
+    }
+}
+
+#[derive(Debug, Default)]
+struct PageContent {
+    operations: Vec<Operation>,
+    /// Names of the images drawn on the page
+    images: Vec<String>,
+    /// Graphics states used on the page, by name, with their opacity
+    opacities: BTreeMap<String, f32>,
+}
+
+/// Drawing on one page of a document. Clones draw on the same page.
+#[derive(Debug, Clone, Default)]
+pub struct Layer {
+    content: Rc<RefCell<PageContent>>,
+}
+
+impl Layer {
+    pub fn add_operation<T: Into<Operation>>(&self, operation: T) {
+        self.content.borrow_mut().operations.push(operation.into());
+    }
+
+    pub fn add_line(&self, line: Line) {
+        for operation in line.into_stream_op() {
+            self.add_operation(operation);
+        }
+    }
+
+    pub fn add_polygon(&self, polygon: Polygon) {
+        for operation in polygon.into_stream_op() {
+            self.add_operation(operation);
+        }
+    }
+
+    pub fn set_fill_color(&self, color: Color) {
+        self.add_operation(PdfColor::FillColor(color));
+    }
+
+    /// Width of lines drawn from here on, in points
+    pub fn set_outline_thickness(&self, thickness: f32) {
+        self.add_operation(Operation::new("w", vec![Object::Real(thickness)]));
+    }
+
+    pub fn save_graphics_state(&self) {
+        self.add_operation(Operation::new("q", vec![]));
+    }
+
+    pub fn restore_graphics_state(&self) {
+        self.add_operation(Operation::new("Q", vec![]));
+    }
+
+    pub fn set_ctm(&self, ctm: CurTransMat) {
+        self.add_operation(ctm);
+    }
+
+    /// Draw everything from here on until the graphics state is restored at an opacity, from 0
+    /// for invisible to 1 for solid
+    pub fn set_opacity(&self, opacity: f32) {
+        let name = format!("GsOpacity{}", (opacity * 1000.0).round() as u32);
+        let operation = Operation::new("gs", vec![Object::Name(name.as_bytes().to_vec())]);
+        let mut content = self.content.borrow_mut();
+        content.opacities.insert(name, opacity);
+        content.operations.push(operation);
+    }
+
+    pub fn begin_text_section(&self) {
+        self.add_operation(Operation::new("BT", vec![]));
+    }
+
+    pub fn end_text_section(&self) {
+        self.add_operation(Operation::new("ET", vec![]));
+    }
+
+    /// Font and size in points of text written from here on in the text section
+    pub fn set_font(&self, font: &Font, size: f32) {
+        let name = Object::Name(font.name.as_bytes().to_vec());
+        self.add_operation(Operation::new("Tf", vec![name, Object::Real(size)]));
+    }
+
+    pub fn set_text_matrix(&self, matrix: TextMatrix) {
+        self.add_operation(matrix);
+    }
+
+    /// Write text at the current text position. Every font is set in WinAnsi, so text outside
+    /// Windows-1252 does not come out.
+    pub fn write_text<S: Into<String>>(&self, text: S) {
+        let encoded = Document::encode_text(Some("WinAnsiEncoding"), &text.into());
+        let text = Object::String(encoded, StringFormat::Hexadecimal);
+        self.add_operation(Operation::new("Tj", vec![text]));
+    }
+
+    /// Write a line of text starting at a point on the page
+    pub fn use_text<S: Into<String>>(&self, text: S, size: f32, x: Mm, y: Mm, font: &Font) {
+        self.begin_text_section();
+        self.set_font(font, size);
+        let position = vec![Object::Real(x.into_pt().0), Object::Real(y.into_pt().0)];
+        self.add_operation(Operation::new("Td", position));
+        self.write_text(text);
+        self.end_text_section();
+    }
+
+    /// Draw an image of the document stretched over `rect`
+    pub fn draw_image(&self, image: &ImageRef, rect: &Rect) {
+        let matrix = [
+            rect.width.into_pt().0,
+            0.0,
+            0.0,
+            rect.height.into_pt().0,
+            rect.x.into_pt().0,
+            rect.y.into_pt().0,
+        ];
+        self.save_graphics_state();
+        self.add_operation(Operation::new(
+            "cm",
+            matrix.iter().map(|value| Object::Real(*value)).collect(),
+        ));
+        let name = Object::Name(image.name.as_bytes().to_vec());
+        self.add_operation(Operation::new("Do", vec![name]));
+        self.restore_graphics_state();
+        self.content.borrow_mut().images.push(image.name.clone());
+    }
+}
+
+/// An entry of the outline of a document, leading to a page, and the entries nested under it
+#[derive(Debug, Clone)]
+struct Bookmark {
+    title: String,
+    page: usize,
+    children: Vec<Bookmark>,
+}
+
+/// A PDF being written. Pages keep what is drawn on them until the document is saved, and each
+/// image is stored once however often it is drawn.
+pub struct PdfWriter {
+    document: Document,
+    metadata: PdfMetadata,
+    page_size: (Mm, Mm),
+    pages: Vec<Layer>,
+    fonts: Dictionary,
+    images: Images,
+    bookmarks: Vec<Bookmark>,
+    /// Trim and bleed box of the pages that have them
+    boxes: BTreeMap<usize, (Rect, Rect)>,
+    /// ICC profile that the RGB colours of every page are drawn in
+    default_rgb: Option<Vec<u8>>,
+}
+
+impl PdfWriter {
+    /// A document with no pages yet, whose pages are all `width` by `height`
+    pub fn new(metadata: PdfMetadata, width: Mm, height: Mm) -> PdfWriter {
+        PdfWriter {
+            document: Document::with_version(PDF_VERSION),
+            metadata,
+            page_size: (width, height),
+            pages: vec![],
+            fonts: Dictionary::new(),
+            images: Images::default(),
+            bookmarks: vec![],
+            boxes: BTreeMap::new(),
+            default_rgb: None,
+        }
+    }
+
+    /// Write the document with another PDF version in its header
+    pub fn with_version(mut self, version: &str) -> PdfWriter {
+        self.document.version = version.to_string();
+        self
+    }
+
+    pub fn add_builtin_font(&mut self, font: BuiltinFont) -> Font {
+        let id = self.document.add_object(Dictionary::from(font));
+        let font = Font {
+            name: format!("F{}", self.fonts.len() + 1),
+        };
+        self.fonts.set(font.name.clone(), Object::Reference(id));
+        font
+    }
+
+    pub fn images(&self) -> Images {
+        self.images.clone()
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// The layer of a page, adding the pages before it that are not there yet
+    pub fn layer(&mut self, page: usize) -> Layer {
+        while self.pages.len() <= page {
+            self.pages.push(Layer::default());
+        }
+        self.pages[page].clone()
+    }
+
+    /// Add an entry to the outline of the document, nested under the entries titled by the rest
+    /// of `path`. Those that are not there yet are added, leading to the same page.
+    pub fn add_bookmark(&mut self, path: &[String], page: usize) {
+        let mut entries = &mut self.bookmarks;
+        for title in path {
+            let index = match entries.iter().position(|entry| entry.title == *title) {
+                Some(index) => index,
+                None => {
+                    entries.push(Bookmark {
+                        title: title.clone(),
+                        page,
+                        children: vec![],
+                    });
+                    entries.len() - 1
+                }
+            };
+            entries = &mut entries[index].children;
+        }
+    }
+
+    /// Give a page a trim box, where the paper is cut, and a bleed box around that
+    pub fn set_page_boxes(&mut self, page: usize, trim: Rect, bleed: Rect) {
+        self.boxes.insert(page, (trim, bleed));
+    }
+
+    /// Draw the RGB colours and images of every page in the colour space of an ICC profile,
+    /// rather than in whatever RGB the printer assumes
+    pub fn set_default_rgb(&mut self, profile: Vec<u8>) {
+        self.default_rgb = Some(profile);
+    }
+
+    pub fn save_to_bytes(mut self) -> Result<Vec<u8>, String> {
+        let pages_id = self.document.new_object_id();
+        let fonts_id = self.document.add_object(self.fonts.clone());
+        let images = self.add_images();
+        let default_rgb = self.default_rgb.take().map(|profile| {
+            let mut dict = Dictionary::new();
+            dict.set("N", Object::Integer(3));
+            dict.set("Alternate", Object::Name(b"DeviceRGB".to_vec()));
+            let profile_id = self.document.add_object(Stream::new(dict, profile));
+            Object::Array(vec![
+                Object::Name(b"ICCBased".to_vec()),
+                Object::Reference(profile_id),
+            ])
+        });
+        let mut states: BTreeMap<String, ObjectId> = BTreeMap::new();
+        let mut page_ids = vec![];
+        for (index, layer) in std::mem::take(&mut self.pages).into_iter().enumerate() {
+            let content = layer.content.take();
+            let mut resources = Dictionary::new();
+            resources.set("Font", Object::Reference(fonts_id));
+            if !content.images.is_empty() {
+                let mut xobjects = Dictionary::new();
+                for name in &content.images {
+                    xobjects.set(name.clone(), Object::Reference(images[name]));
+                }
+                resources.set("XObject", xobjects);
+            }
+            if !content.opacities.is_empty() {
+                let mut page_states = Dictionary::new();
+                for (name, opacity) in &content.opacities {
+                    let id = *states.entry(name.clone()).or_insert_with(|| {
+                        let mut state = Dictionary::new();
+                        state.set("Type", Object::Name(b"ExtGState".to_vec()));
+                        state.set("ca", Object::Real(*opacity));
+                        state.set("CA", Object::Real(*opacity));
+                        self.document.add_object(state)
+                    });
+                    page_states.set(name.clone(), Object::Reference(id));
+                }
+                resources.set("ExtGState", page_states);
+            }
+            if let Some(default_rgb) = &default_rgb {
+                let mut colour_spaces = Dictionary::new();
+                // Device RGB on a page with a DefaultRGB colour space is drawn in that space
+                colour_spaces.set("DefaultRGB", default_rgb.clone());
+                resources.set("ColorSpace", colour_spaces);
+            }
+            let operations = Content {
+                operations: content.operations,
+            }
+            .encode()
+            .map_err(|e| format!("Could not write page {}: {}", index + 1, e))?;
+            let mut stream = Stream::new(Dictionary::new(), operations);
+            let _ = stream.compress();
+            let contents_id = self.document.add_object(stream);
+
+            let (width, height) = self.page_size;
+            let mut page = Dictionary::new();
+            page.set("Type", Object::Name(b"Page".to_vec()));
+            page.set("Parent", Object::Reference(pages_id));
+            page.set(
+                "MediaBox",
+                pdf_box(&Rect::new(Mm(0.0), Mm(0.0), width, height)),
+            );
+            if let Some((trim, bleed)) = self.boxes.get(&index) {
+                page.set("TrimBox", pdf_box(trim));
+                page.set("BleedBox", pdf_box(bleed));
+            }
+            page.set("Resources", resources);
+            page.set("Contents", Object::Reference(contents_id));
+            page_ids.push(self.document.add_object(page));
+        }
+        let mut pages = Dictionary::new();
+        pages.set("Type", Object::Name(b"Pages".to_vec()));
+        pages.set("Count", Object::Integer(page_ids.len() as i64));
+        pages.set(
+            "Kids",
+            Object::Array(page_ids.iter().map(|id| Object::Reference(*id)).collect()),
+        );
+        self.document.objects.insert(pages_id, pages.into());
+
+        let mut catalog = Dictionary::new();
+        catalog.set("Type", Object::Name(b"Catalog".to_vec()));
+        catalog.set("Pages", Object::Reference(pages_id));
+        if let Some(outline) = self.add_outline(&page_ids) {
+            catalog.set("Outlines", Object::Reference(outline));
+            catalog.set("PageMode", Object::Name(b"UseOutlines".to_vec()));
+        }
+        let document_id = self.metadata.xmp_metadata.document_id.clone();
+        let (xmp, info, icc) = self.metadata.into_obj();
+        if let Some(xmp) = xmp {
+            catalog.set("Metadata", Object::Reference(self.document.add_object(xmp)));
+        }
+        if let Some(icc) = icc {
+            let profile_id = self.document.add_object(Stream::from(icc));
+            let mut intent = Dictionary::new();
+            intent.set("Type", Object::Name(b"OutputIntent".to_vec()));
+            intent.set("S", Object::Name(b"GTS_PDFX".to_vec()));
+            intent.set("OutputCondition", Object::string_literal(OUTPUT_CONDITION));
+            intent.set(
+                "OutputConditionIdentifier",
+                Object::string_literal(OUTPUT_CONDITION_ID),
+            );
+            intent.set(
+                "RegistryName",
+                Object::string_literal(OUTPUT_CONDITION_REGISTRY),
+            );
+            intent.set("Info", Object::string_literal(OUTPUT_CONDITION));
+            intent.set("DestOutputProfile", Object::Reference(profile_id));
+            catalog.set("OutputIntents", Object::Array(vec![intent.into()]));
+        }
+        let catalog_id = self.document.add_object(catalog);
+        let info_id = self.document.add_object(info);
+        self.document
+            .trailer
+            .set("Root", Object::Reference(catalog_id));
+        self.document
+            .trailer
+            .set("Info", Object::Reference(info_id));
+        // A fresh instance id every time the document is written
+        let instance_id = XmpMetadata::new(None, 1).document_id;
+        self.document.trailer.set(
+            "ID",
+            Object::Array(vec![
+                Object::string_literal(document_id),
+                Object::string_literal(instance_id),
+            ]),
+        );
+        let mut pdf = vec![];
+        self.document
+            .save_to(&mut pdf)
+            .map_err(|e| format!("Could not write PDF: {}", e))?;
+        Ok(pdf)
+    }
+
+    /// Add every image stored to the document, returning their objects by name
+    fn add_images(&mut self) -> HashMap<String, ObjectId> {
+        let streams = std::mem::take(&mut self.images.store.borrow_mut().streams);
+        streams
+            .into_iter()
+            .map(|(name, stream)| (name, self.document.add_object(stream)))
+            .collect()
+    }
+
+    /// Add the outline of the document, if it has any bookmarks
+    fn add_outline(&mut self, page_ids: &[ObjectId]) -> Option<ObjectId> {
+        if self.bookmarks.is_empty() {
+            return None;
+        }
+        let outline_id = self.document.new_object_id();
+        let bookmarks = std::mem::take(&mut self.bookmarks);
+        let (first, last, count) = self.add_outline_entries(&bookmarks, outline_id, page_ids);
+        let mut outline = Dictionary::new();
+        outline.set("Type", Object::Name(b"Outlines".to_vec()));
+        outline.set("First", Object::Reference(first));
+        outline.set("Last", Object::Reference(last));
+        outline.set("Count", Object::Integer(count));
+        self.document.objects.insert(outline_id, outline.into());
+        Some(outline_id)
+    }
+
+    /// Add outline entries under `parent`, returning the first and last of them and how many
+    /// entries they hold, nested ones included
+    fn add_outline_entries(
+        &mut self,
+        bookmarks: &[Bookmark],
+        parent: ObjectId,
+        page_ids: &[ObjectId],
+    ) -> (ObjectId, ObjectId, i64) {
+        let ids: Vec<ObjectId> = bookmarks
+            .iter()
+            .map(|_| self.document.new_object_id())
+            .collect();
+        let mut count = ids.len() as i64;
+        for (index, bookmark) in bookmarks.iter().enumerate() {
+            let mut entry = Dictionary::new();
+            entry.set("Title", text_string(&bookmark.title));
+            entry.set("Parent", Object::Reference(parent));
+            entry.set(
+                "Dest",
+                Object::Array(vec![
+                    Object::Reference(page_ids[bookmark.page]),
+                    Object::Name(b"Fit".to_vec()),
+                ]),
+            );
+            if index > 0 {
+                entry.set("Prev", Object::Reference(ids[index - 1]));
+            }
+            if let Some(next) = ids.get(index + 1) {
+                entry.set("Next", Object::Reference(*next));
+            }
+            if !bookmark.children.is_empty() {
+                let (first, last, children) =
+                    self.add_outline_entries(&bookmark.children, ids[index], page_ids);
+                entry.set("First", Object::Reference(first));
+                entry.set("Last", Object::Reference(last));
+                // Entries start open, showing what is nested under them
+                entry.set("Count", Object::Integer(children));
+                count += children;
+            }
+            self.document.objects.insert(ids[index], entry.into());
+        }
+        (ids[0], ids[ids.len() - 1], count)
+    }
+}
+
+/// A PDF text string, in UTF-16 if it is not plain ASCII
+fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::string_literal(text);
+    }
+    let mut bytes = vec![0xfe, 0xff];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+/// A PDF rectangle in points, as [left bottom right top]
+fn pdf_box(rect: &Rect) -> Object {
+    let corners = [rect.x, rect.y, rect.right(), rect.top()];
+    Object::Array(
+        corners
+            .iter()
+            .map(|mm| Object::Real(mm.into_pt().0))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::layout::Rect;
+    use crate::pdf_writer::PdfWriter;
+    use printpdf::image_crate::{DynamicImage, RgbImage};
+    use printpdf::lopdf::{Document, Object};
+    use printpdf::{Image, ImageXObject, Mm, PdfConformance, PdfMetadata};
+
+    fn image() -> Result<ImageXObject, String> {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(4, 4));
+        Ok(Image::from_dynamic_image(&image).image)
+    }
+
+    #[test]
+    pub fn test_images_are_stored_once() {
+        let metadata = PdfMetadata::new("test", 1, false, PdfConformance::default());
+        let mut writer = PdfWriter::new(metadata, Mm(210.0), Mm(297.0));
+        let images = writer.images();
+        let first = images.get_or_add(1, image).unwrap();
+        let again = images
+            .get_or_add(1, || Err("Stored images are not made again".to_string()))
+            .unwrap();
+        assert_eq!(first, again);
+        let second = images.get_or_add(2, image).unwrap();
+        assert_ne!(first, second);
+
+        let rect = Rect::new(Mm(10.0), Mm(10.0), Mm(63.0), Mm(88.0));
+        for page in 0..3 {
+            writer.layer(page).draw_image(&first, &rect);
+        }
+        writer.layer(1).draw_image(&second, &rect);
+        let path = |titles: &[&str]| titles.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        writer.add_bookmark(&path(&["stompy", "Mainboard"]), 1);
+        writer.add_bookmark(&path(&["stompy", "Sideboard"]), 2);
+        writer.add_bookmark(&path(&["delver", "Mainboard"]), 2);
+        let pdf = writer.save_to_bytes().unwrap();
+
+        let document = Document::load_mem(&pdf).unwrap();
+        assert_eq!(document.get_pages().len(), 3);
+        let is_image = |object: &Object| {
+            let stream = object.as_stream().ok();
+            let subtype = stream.and_then(|stream| stream.dict.get(b"Subtype").ok());
+            subtype.and_then(|subtype| subtype.as_name().ok()) == Some(b"Image".as_slice())
+        };
+        assert_eq!(document.objects.values().filter(|o| is_image(o)).count(), 2);
+
+        // Two decks with their sections nested under them
+        let catalog = document.catalog().unwrap();
+        let outline_id = catalog.get(b"Outlines").unwrap().as_reference().unwrap();
+        let outline = document.get_dictionary(outline_id).unwrap();
+        assert_eq!(outline.get(b"Count").unwrap().as_i64().unwrap(), 5);
+        let first_id = outline.get(b"First").unwrap().as_reference().unwrap();
+        let first = document.get_dictionary(first_id).unwrap();
+        assert_eq!(first.get(b"Title").unwrap().as_str().unwrap(), b"stompy");
+        assert_eq!(first.get(b"Count").unwrap().as_i64().unwrap(), 2);
+    }
+}
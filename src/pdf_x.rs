@@ -0,0 +1,288 @@
+use crate::deck::PickedDeck;
+use crate::layout::{Layout, Rect};
+use crate::pdf_writer::PdfWriter;
+use crate::render::RenderOptions;
+use printpdf::{Mm, PdfConformance, PdfMetadata};
+use std::collections::BTreeMap;
+
+/// Conformance claimed by PDF/X output. PDF/X-4 allows the transparency of watermarks.
+const PDF_X: PdfConformance = PdfConformance::X4_2010_PDF_1_4;
+/// PDF version in the header of PDF/X output, the lowest PDF/X-4 allows
+const PDF_X_VERSION: &str = "1.6";
+/// Entries of the tone curve in the built in sRGB profile
+const CURVE_POINTS: usize = 256;
+
+/// What the PDF says about itself in its document information
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentInfo {
+    pub title: String,
+    pub author: String,
+    pub subject: String,
+    pub keywords: Vec<String>,
+}
+
+impl DocumentInfo {
+    /// Information for a print run of the deck: the card count of each section as the subject,
+    /// and the deck and section names as keywords
+    pub fn new(title: &str, author: Option<&str>, deck: &PickedDeck) -> DocumentInfo {
+        let sections: Vec<(&str, u32)> = deck
+            .cards
+            .iter()
+            .map(|(section, cards)| (section.as_str(), cards.iter().map(|c| c.quantity).sum()))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        let total: u32 = sections.iter().map(|(_, count)| count).sum();
+        let counts: Vec<String> = sections
+            .iter()
+            .map(|(section, count)| format!("{} {}", count, section))
+            .collect();
+        let subject = match counts.is_empty() {
+            true => "Proxies of 0 cards".to_string(),
+            false => format!("Proxies of {} cards: {}", total, counts.join(", ")),
+        };
+        let mut keywords = vec!["proxies".to_string()];
+        for (deck_name, _) in deck.counts_by_deck() {
+            if !deck_name.is_empty() && !keywords.contains(&deck_name) {
+                keywords.push(deck_name);
+            }
+        }
+        keywords.extend(sections.iter().map(|(section, _)| section.to_string()));
+        DocumentInfo {
+            title: title.to_string(),
+            author: author.unwrap_or(env!("CARGO_PKG_NAME")).to_string(),
+            subject,
+            keywords,
+        }
+    }
+
+    /// The information to give a new PDF, claiming PDF/X conformance if asked to. PDF/X has the
+    /// PDF embed the FOGRA39 profile as its output intent, along with XMP metadata.
+    pub fn metadata(&self, pdf_x: bool) -> PdfMetadata {
+        let conformance = match pdf_x {
+            true => PDF_X,
+            false => PdfConformance::default(),
+        };
+        let mut metadata = PdfMetadata::new(self.title.clone(), 1, false, conformance);
+        metadata.author = self.author.clone();
+        metadata.creator = env!("CARGO_PKG_NAME").to_string();
+        metadata.subject = self.subject.clone();
+        metadata.keywords = self.keywords.clone();
+        metadata
+    }
+
+    /// A new PDF with pages `width` by `height` and this information, in a PDF version that
+    /// allows PDF/X if it claims to be PDF/X
+    pub fn writer(&self, pdf_x: bool, width: Mm, height: Mm) -> PdfWriter {
+        let doc = PdfWriter::new(self.metadata(pdf_x), width, height);
+        match pdf_x {
+            true => doc.with_version(PDF_X_VERSION),
+            false => doc,
+        }
+    }
+}
+
+fn s15_fixed16(value: f64) -> [u8; 4] {
+    ((value * 65536.0).round() as i32).to_be_bytes()
+}
+
+fn xyz_tag(x: f64, y: f64, z: f64) -> Vec<u8> {
+    let mut tag = b"XYZ \0\0\0\0".to_vec();
+    for value in [x, y, z] {
+        tag.extend(s15_fixed16(value));
+    }
+    tag
+}
+
+/// A version 2 ICC profile for sRGB: the sRGB primaries adapted to D50, and the sRGB tone curve
+pub fn srgb_profile() -> Vec<u8> {
+    let mut description = b"desc\0\0\0\0".to_vec();
+    let name = b"sRGB\0";
+    description.extend((name.len() as u32).to_be_bytes());
+    description.extend(name);
+    // No Unicode or ScriptCode names
+    description.extend([0; 8 + 3 + 67]);
+    let mut copyright = b"text\0\0\0\0".to_vec();
+    copyright.extend(b"No copyright, use freely\0");
+    let mut curve = b"curv\0\0\0\0".to_vec();
+    curve.extend((CURVE_POINTS as u32).to_be_bytes());
+    for point in 0..CURVE_POINTS {
+        let value = point as f64 / (CURVE_POINTS - 1) as f64;
+        let linear = match value <= 0.04045 {
+            true => value / 12.92,
+            false => ((value + 0.055) / 1.055).powf(2.4),
+        };
+        curve.extend(((linear * 65535.0).round() as u16).to_be_bytes());
+    }
+    let tags: [(&[u8; 4], Vec<u8>); 9] = [
+        (b"desc", description),
+        (b"cprt", copyright),
+        (b"wtpt", xyz_tag(0.9642, 1.0, 0.8249)),
+        (b"rXYZ", xyz_tag(0.4361, 0.2225, 0.0139)),
+        (b"gXYZ", xyz_tag(0.3851, 0.7169, 0.0971)),
+        (b"bXYZ", xyz_tag(0.1431, 0.0606, 0.7141)),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+    ];
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data = vec![];
+    let data_start = 128 + 4 + 12 * tags.len();
+    for (signature, tag) in &tags {
+        table.extend(*signature);
+        table.extend(((data_start + data.len()) as u32).to_be_bytes());
+        table.extend((tag.len() as u32).to_be_bytes());
+        data.extend(tag);
+        // Every tag starts on a four byte boundary
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+    let size = data_start + data.len();
+    let mut header = vec![0; 128];
+    header[0..4].copy_from_slice(&(size as u32).to_be_bytes());
+    header[8..12].copy_from_slice(&0x0210_0000u32.to_be_bytes());
+    header[12..16].copy_from_slice(b"mntr");
+    header[16..20].copy_from_slice(b"RGB ");
+    header[20..24].copy_from_slice(b"XYZ ");
+    header[36..40].copy_from_slice(b"acsp");
+    // The D50 illuminant of the profile connection space
+    header[68..72].copy_from_slice(&s15_fixed16(0.9642));
+    header[72..76].copy_from_slice(&s15_fixed16(1.0));
+    header[76..80].copy_from_slice(&s15_fixed16(0.8249));
+    [header, table, data].concat()
+}
+
+/// The trim box around the cards on a page and the bleed box around that, kept on the page.
+/// Pages without cards, such as the checklist, are trimmed to the whole page.
+pub fn page_boxes(cards: &[Rect], layout: &Layout) -> (Rect, Rect) {
+    let page = Rect::new(Mm(0.0), Mm(0.0), layout.page_width(), layout.page_height());
+    let Some(trim) = cards.iter().copied().reduce(|a, b| a.union(&b)) else {
+        return (page, page);
+    };
+    let bleed = trim.expand(layout.bleed());
+    let (left, bottom) = (bleed.x.0.max(0.0), bleed.y.0.max(0.0));
+    let right = bleed.right().0.min(page.width.0);
+    let top = bleed.top().0.min(page.height.0);
+    let bleed = Rect::new(Mm(left), Mm(bottom), Mm(right - left), Mm(top - bottom));
+    (trim, bleed)
+}
+
+/// Check the print run writes no text. PDF/X needs every font embedded, and text is set in the
+/// standard PDF fonts, which are not.
+pub fn check_options(options: &RenderOptions) -> Result<(), String> {
+    let text: Vec<&str> = [
+        (options.text_proxies, "text proxies"),
+        (options.watermark.is_some(), "watermarks"),
+        (options.checklist, "checklists"),
+        (options.dividers, "dividers"),
+        (options.deck_tags, "deck tags"),
+    ]
+    .iter()
+    .filter(|(wanted, _)| *wanted)
+    .map(|(_, name)| *name)
+    .collect();
+    match text.is_empty() {
+        true => Ok(()),
+        false => Err(format!(
+            "PDF/X cannot have {}, as their fonts are not embedded",
+            text.join(", ")
+        )),
+    }
+}
+
+/// Make a PDF ready for PDF/X: give every page a trim box around the cards placed on it, keyed by
+/// page index, and a bleed box around that, and draw the RGB colours and images of every page
+/// as sRGB
+pub fn make_print_ready(
+    writer: &mut PdfWriter,
+    cards: &BTreeMap<usize, Vec<Rect>>,
+    layout: &Layout,
+) {
+    for index in 0..writer.page_count() {
+        let page_cards = cards.get(&index).map_or(&[][..], |cards| cards.as_slice());
+        let (trim, bleed) = page_boxes(page_cards, layout);
+        writer.set_page_boxes(index, trim, bleed);
+    }
+    writer.set_default_rgb(srgb_profile());
+}
+
+#[cfg(test)]
+mod test {
+    use crate::deck::{PickedCard, PickedDeck, MAINBOARD, SIDEBOARD};
+    use crate::layout::{Layout, Rect};
+    use crate::pdf_calc::PageSpec;
+    use crate::pdf_x::{page_boxes, srgb_profile, DocumentInfo};
+    use printpdf::Mm;
+    use std::collections::BTreeMap;
+
+    #[test]
+    pub fn test_document_info() {
+        let card = |quantity: u32, deck: &str| PickedCard {
+            quantity,
+            deck: deck.to_string(),
+            ..Default::default()
+        };
+        let mut cards = BTreeMap::new();
+        cards.insert(
+            MAINBOARD.to_string(),
+            vec![card(4, "stompy"), card(56, "stompy")],
+        );
+        cards.insert(SIDEBOARD.to_string(), vec![card(15, "stompy")]);
+        let info = DocumentInfo::new("stompy", None, &PickedDeck { cards });
+        assert_eq!(
+            info.subject,
+            "Proxies of 75 cards: 60 Mainboard, 15 Sideboard"
+        );
+        assert_eq!(
+            info.keywords,
+            vec!["proxies", "stompy", MAINBOARD, SIDEBOARD]
+        );
+        assert_eq!(info.author, "mtg-proxy-maker");
+
+        // PDF/X-4 needs a PDF 1.6 header
+        let header = |pdf_x: bool| {
+            let mut doc = info.writer(pdf_x, Mm(210.0), Mm(297.0));
+            doc.layer(0);
+            let pdf = doc.save_to_bytes().unwrap();
+            String::from_utf8_lossy(&pdf[..8]).to_string()
+        };
+        assert_eq!(header(true), "%PDF-1.6");
+        assert_eq!(header(false), "%PDF-1.4");
+    }
+
+    #[test]
+    pub fn test_srgb_profile() {
+        let profile = srgb_profile();
+        let size = u32::from_be_bytes(profile[0..4].try_into().unwrap());
+        assert_eq!(size as usize, profile.len());
+        assert_eq!(&profile[36..40], b"acsp");
+        let tags = u32::from_be_bytes(profile[128..132].try_into().unwrap()) as usize;
+        assert_eq!(tags, 9);
+        for tag in 0..tags {
+            let entry = &profile[132 + tag * 12..144 + tag * 12];
+            let offset = u32::from_be_bytes(entry[4..8].try_into().unwrap()) as usize;
+            let length = u32::from_be_bytes(entry[8..12].try_into().unwrap()) as usize;
+            assert_eq!(offset % 4, 0);
+            assert!(offset + length <= profile.len());
+        }
+    }
+
+    #[test]
+    pub fn test_page_boxes() {
+        let layout = Layout::new(&PageSpec::default()).with_bleed(Mm(3.0));
+        let cards = [
+            Rect::new(Mm(10.0), Mm(20.0), Mm(63.0), Mm(88.0)),
+            Rect::new(Mm(73.0), Mm(108.0), Mm(63.0), Mm(88.0)),
+        ];
+        let (trim, bleed) = page_boxes(&cards, &layout);
+        assert_eq!(trim, Rect::new(Mm(10.0), Mm(20.0), Mm(126.0), Mm(176.0)));
+        assert_eq!(bleed, Rect::new(Mm(7.0), Mm(17.0), Mm(132.0), Mm(182.0)));
+
+        // The bleed box stays on the page
+        let (_, bleed) = page_boxes(&cards, &layout.clone().with_bleed(Mm(12.0)));
+        assert_eq!((bleed.x, bleed.y), (Mm(0.0), Mm(8.0)));
+
+        let (trim, bleed) = page_boxes(&[], &layout);
+        assert_eq!(trim, bleed);
+        assert_eq!(trim.height, layout.page_height());
+    }
+}
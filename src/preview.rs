@@ -0,0 +1,643 @@
+use crate::checklist::{tick_box, Checklist, ChecklistLine, MARGIN};
+use crate::cutter::Cutter;
+use crate::deck::PickedDeck;
+use crate::guides::{clip_lines, page_guide_lines, CutGuides};
+use crate::image_repository::ImageRepository;
+use crate::layout::{Layout, Rect};
+use crate::render::{CardFace, PrintItem, PrintPlan, RenderOptions};
+use printpdf::image_crate::imageops::FilterType;
+use printpdf::image_crate::io::Reader;
+use printpdf::image_crate::{imageops, DynamicImage, ImageFormat, Rgb, RgbImage};
+use printpdf::Mm;
+use std::fmt::Write;
+use std::fs::{write, File};
+use std::io::{BufReader, Cursor};
+use std::path::{Path, PathBuf};
+
+/// Colour of the paper
+const PAPER: [u8; 3] = [255, 255, 255];
+/// Colour of a card with no image, such as a text proxy
+const CARD: [u8; 3] = [215, 215, 215];
+const DIVIDER: [u8; 3] = [160, 160, 160];
+const GUIDE: [u8; 3] = [110, 110, 110];
+const MARK: [u8; 3] = [0, 0, 0];
+
+/// File format of preview pages
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PreviewFormat {
+    /// A picture of each page
+    #[default]
+    Png,
+    /// Each page drawn in mm, with the name of every card
+    Svg,
+}
+
+/// Something a preview page can be drawn onto, in mm from the bottom left like a pdf page
+trait Canvas {
+    fn fill(&mut self, rect: &Rect, colour: [u8; 3]);
+    fn line(&mut self, from: (Mm, Mm), to: (Mm, Mm), colour: [u8; 3]);
+    fn image(&mut self, rect: &Rect, image: &DynamicImage);
+    /// Name a card. Pictures leave the names out, at preview size they could not be read.
+    fn label(&mut self, rect: &Rect, text: &str);
+    /// Write text from a point on its baseline, `size` mm high. Pictures leave it out, as with
+    /// labels.
+    fn text(&mut self, at: (Mm, Mm), size: f32, text: &str);
+}
+
+/// A picture of a page, `scale` pixels to the mm
+struct PngCanvas {
+    image: RgbImage,
+    scale: f32,
+    page_height: Mm,
+}
+
+impl PngCanvas {
+    fn new(layout: &Layout, scale: f32) -> PngCanvas {
+        let width = (layout.page_width().0 * scale).round() as u32;
+        let height = (layout.page_height().0 * scale).round() as u32;
+        PngCanvas {
+            image: RgbImage::from_pixel(width, height, Rgb(PAPER)),
+            scale,
+            page_height: layout.page_height(),
+        }
+    }
+
+    /// Pixel position of a point on the page, which may be off the picture
+    fn pixel(&self, (x, y): (Mm, Mm)) -> (i64, i64) {
+        let x = (x.0 * self.scale).round() as i64;
+        let y = ((self.page_height - y).0 * self.scale).round() as i64;
+        (x, y)
+    }
+
+    /// Top left pixel of a rectangle and its size in pixels
+    fn pixel_rect(&self, rect: &Rect) -> (i64, i64, u32, u32) {
+        let (left, top) = self.pixel((rect.x, rect.top()));
+        let (right, bottom) = self.pixel((rect.right(), rect.y));
+        let width = (right - left).max(1) as u32;
+        let height = (bottom - top).max(1) as u32;
+        (left, top, width, height)
+    }
+
+    fn put(&mut self, x: i64, y: i64, colour: [u8; 3]) {
+        if x >= 0 && y >= 0 && x < self.image.width() as i64 && y < self.image.height() as i64 {
+            self.image.put_pixel(x as u32, y as u32, Rgb(colour));
+        }
+    }
+
+    fn to_png(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = vec![];
+        DynamicImage::ImageRgb8(self.image.clone())
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .map_err(|e| format!("Could not encode preview: {}", e))?;
+        Ok(bytes)
+    }
+}
+
+impl Canvas for PngCanvas {
+    fn fill(&mut self, rect: &Rect, colour: [u8; 3]) {
+        let (left, top, width, height) = self.pixel_rect(rect);
+        for y in top..top + height as i64 {
+            for x in left..left + width as i64 {
+                self.put(x, y, colour);
+            }
+        }
+    }
+
+    fn line(&mut self, from: (Mm, Mm), to: (Mm, Mm), colour: [u8; 3]) {
+        let (x0, y0) = self.pixel(from);
+        let (x1, y1) = self.pixel(to);
+        let steps = (x1 - x0).abs().max((y1 - y0).abs()).max(1);
+        for step in 0..=steps {
+            let x = x0 + (x1 - x0) * step / steps;
+            let y = y0 + (y1 - y0) * step / steps;
+            self.put(x, y, colour);
+        }
+    }
+
+    fn image(&mut self, rect: &Rect, image: &DynamicImage) {
+        let (left, top, width, height) = self.pixel_rect(rect);
+        let image = image
+            .resize_exact(width, height, FilterType::Triangle)
+            .to_rgb8();
+        imageops::overlay(&mut self.image, &image, left, top);
+    }
+
+    fn label(&mut self, _rect: &Rect, _text: &str) {}
+
+    fn text(&mut self, _at: (Mm, Mm), _size: f32, _text: &str) {}
+}
+
+/// A page as SVG, in mm
+struct SvgCanvas {
+    svg: String,
+    page_height: Mm,
+}
+
+impl SvgCanvas {
+    fn new(layout: &Layout) -> SvgCanvas {
+        let (width, height) = (layout.page_width().0, layout.page_height().0);
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}mm\" height=\"{h}mm\" \
+             viewBox=\"0 0 {w} {h}\">\n",
+            w = width,
+            h = height
+        );
+        let _ = writeln!(
+            svg,
+            "  <rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            width,
+            height,
+            hex(PAPER)
+        );
+        SvgCanvas {
+            svg,
+            page_height: layout.page_height(),
+        }
+    }
+
+    /// Distance of a point from the top of the page
+    fn y(&self, y: Mm) -> f32 {
+        (self.page_height - y).0
+    }
+
+    fn finish(mut self) -> String {
+        self.svg.push_str("</svg>\n");
+        self.svg
+    }
+}
+
+impl Canvas for SvgCanvas {
+    fn fill(&mut self, rect: &Rect, colour: [u8; 3]) {
+        let _ = writeln!(
+            self.svg,
+            "  <rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\"/>",
+            rect.x.0,
+            self.y(rect.top()),
+            rect.width.0,
+            rect.height.0,
+            hex(colour)
+        );
+    }
+
+    fn line(&mut self, from: (Mm, Mm), to: (Mm, Mm), colour: [u8; 3]) {
+        let _ = writeln!(
+            self.svg,
+            "  <line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\" \
+             stroke-width=\"0.2\"/>",
+            from.0 .0,
+            self.y(from.1),
+            to.0 .0,
+            self.y(to.1),
+            hex(colour)
+        );
+    }
+
+    fn image(&mut self, rect: &Rect, image: &DynamicImage) {
+        let mut png = vec![];
+        if image
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .is_err()
+        {
+            self.fill(rect, CARD);
+            return;
+        }
+        let _ = writeln!(
+            self.svg,
+            "  <image x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" \
+             preserveAspectRatio=\"none\" href=\"data:image/png;base64,{}\"/>",
+            rect.x.0,
+            self.y(rect.top()),
+            rect.width.0,
+            rect.height.0,
+            base64(&png)
+        );
+    }
+
+    fn label(&mut self, rect: &Rect, text: &str) {
+        let _ = writeln!(
+            self.svg,
+            "  <text x=\"{:.2}\" y=\"{:.2}\" font-size=\"3\" text-anchor=\"middle\">{}</text>",
+            (rect.x + rect.width / 2.0).0,
+            self.y(rect.y + rect.height / 2.0),
+            escape_xml(text)
+        );
+    }
+
+    fn text(&mut self, (x, y): (Mm, Mm), size: f32, text: &str) {
+        let _ = writeln!(
+            self.svg,
+            "  <text x=\"{:.2}\" y=\"{:.2}\" font-size=\"{}\">{}</text>",
+            x.0,
+            self.y(y),
+            size,
+            escape_xml(text)
+        );
+    }
+}
+
+fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Standard base64 with padding, for images embedded in SVG pages
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+/// Turn an image a quarter if it does not have the same orientation as its place on the page
+fn orient(image: DynamicImage, rect: &Rect) -> DynamicImage {
+    let wide_image = image.width() > image.height();
+    let wide_rect = rect.width.0 > rect.height.0;
+    match wide_image == wide_rect {
+        true => image,
+        false => image.rotate90(),
+    }
+}
+
+/// Pages of a planned print run, to be drawn as previews
+struct Preview<'a> {
+    plan: &'a PrintPlan<'a>,
+    layout: &'a Layout,
+    guides: CutGuides,
+    cutter: Option<Cutter>,
+}
+
+impl Preview<'_> {
+    /// Draw one side of a sheet as planned: the cards, the cutting guides and any registration
+    /// marks. `load` gives the image of the front or back of an item, or None to show a blank
+    /// card.
+    fn draw_page<C, F>(
+        &self,
+        canvas: &mut C,
+        sheet: usize,
+        back: bool,
+        load: &mut F,
+    ) -> Result<(), String>
+    where
+        C: Canvas,
+        F: FnMut(&PrintItem, bool) -> Result<Option<DynamicImage>, String>,
+    {
+        let (plan, layout) = (self.plan, self.layout);
+        for (item, slot) in plan.items.iter().zip(&plan.packing.slots) {
+            if slot.page != sheet || (back && item.face != CardFace::Both) {
+                continue;
+            }
+            let rect = match back {
+                true => slot.back,
+                false => slot.front,
+            };
+            let bled = rect.expand(layout.bleed());
+            if item.face == CardFace::Divider {
+                canvas.fill(&bled, DIVIDER);
+                canvas.label(&rect, item.section);
+                continue;
+            }
+            let show_back = back || item.face == CardFace::Back;
+            match load(item, show_back)? {
+                Some(image) => canvas.image(&bled, &orient(image, &bled)),
+                None => canvas.fill(&bled, CARD),
+            }
+            canvas.label(&rect, &item.card.name);
+        }
+        let lines = page_guide_lines(&plan.layouts, &plan.packing, sheet, self.guides, back);
+        let clear_zones = match (self.cutter, back) {
+            (Some(cutter), false) => cutter.clear_zones(layout.page_width(), layout.page_height()),
+            _ => vec![],
+        };
+        for line in clip_lines(lines, &clear_zones) {
+            for pair in line.points.windows(2) {
+                let from = (Mm::from(pair[0].0.x), Mm::from(pair[0].0.y));
+                let to = (Mm::from(pair[1].0.x), Mm::from(pair[1].0.y));
+                canvas.line(from, to, GUIDE);
+            }
+        }
+        if let (Some(cutter), false) = (self.cutter, back) {
+            for mark in cutter.marks(layout.page_width(), layout.page_height()) {
+                canvas.fill(&mark, MARK);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Draw one page of the checklist: its title, and a line of text and a tick box for each row
+fn draw_checklist_page<C: Canvas>(
+    canvas: &mut C,
+    title: &str,
+    lines: &[ChecklistLine],
+    layout: &Layout,
+) {
+    canvas.text((MARGIN, Checklist::title_y(layout)), 5.5, title);
+    for (row, line) in lines.iter().enumerate() {
+        let y = Checklist::row_y(layout, row);
+        let text = match line {
+            ChecklistLine::Heading(heading) => heading.clone(),
+            ChecklistLine::Card {
+                quantity,
+                name,
+                set_code,
+                collector_number,
+                ..
+            } => {
+                let tick = tick_box(y);
+                let corners = [
+                    (tick.x, tick.y),
+                    (tick.right(), tick.y),
+                    (tick.right(), tick.top()),
+                    (tick.x, tick.top()),
+                ];
+                for (index, from) in corners.iter().enumerate() {
+                    canvas.line(*from, corners[(index + 1) % 4], GUIDE);
+                }
+                format!("{} {} {} {}", quantity, name, set_code, collector_number)
+            }
+        };
+        canvas.text((MARGIN + Mm(5.0), y), 3.0, &text);
+    }
+}
+
+/// `deck.pdf` becomes `deck_preview_2_back.png` for a page named `2_back`
+fn preview_path(path: &Path, page: &str, format: PreviewFormat) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = match format {
+        PreviewFormat::Png => "png",
+        PreviewFormat::Svg => "svg",
+    };
+    path.with_file_name(format!("{}_preview_{}.{}", stem, page, extension))
+}
+
+/// Decode the image of one face of a card, or None for text proxies. Single faced cards show the
+/// card back chosen for them, as the PDF does.
+fn load_preview_image(
+    image_repo: &ImageRepository,
+    options: &RenderOptions,
+    item: &PrintItem,
+    back: bool,
+) -> Result<Option<DynamicImage>, String> {
+    let card = item.card;
+    let chosen_back = match back && !card.double_sided {
+        true => options.card_backs.back_for(item.section, card),
+        false => None,
+    };
+    let file = match chosen_back {
+        Some(path) => {
+            File::open(path).map_err(|e| format!("Could not open card back {:?}: {}", path, e))?
+        }
+        None if options.text_proxies => return Ok(None),
+        None => {
+            let (front, back_file) = image_repo.get_image(card)?;
+            match back {
+                true => back_file,
+                false => front,
+            }
+        }
+    };
+    let image = Reader::new(BufReader::new(file))
+        .with_guessed_format()
+        .map_err(|e| format!("Could not read image: {}", e))?
+        .decode()
+        .map_err(|e| format!("Could not decode image: {}", e))?;
+    Ok(Some(image))
+}
+
+/// Draw every page of the print run as it is planned into PNG or SVG files next to `pdf_path`,
+/// fronts and then backs for sheets that have them
+pub fn preview_deck(
+    deck: &PickedDeck,
+    image_repo: &ImageRepository,
+    options: &RenderOptions,
+    format: PreviewFormat,
+    scale: f32,
+    pdf_path: &Path,
+) -> Result<(), String> {
+    let plan = PrintPlan::new(deck, options)?;
+    let layout = &options.layout;
+    let preview = Preview {
+        plan: &plan,
+        layout,
+        guides: options.guides,
+        cutter: options.cutter,
+    };
+    let has_backs = plan.items.iter().any(|item| item.face == CardFace::Both);
+    let write_page = |path: PathBuf, bytes: Vec<u8>| {
+        write(&path, bytes).map_err(|e| format!("Could not write {:?}: {}", path, e))?;
+        println!("Wrote {}", path.display());
+        Ok::<(), String>(())
+    };
+    // The checklist goes in front of the sheets, as in the PDF
+    let checklist = options
+        .checklist
+        .then(|| Checklist::new(&options.title, deck))
+        .filter(|_| options.reprint.is_none());
+    if let Some(checklist) = &checklist {
+        for (page, (title, lines)) in checklist.pages(layout).into_iter().enumerate() {
+            let path = preview_path(pdf_path, &format!("checklist_{}", page + 1), format);
+            let bytes = match format {
+                PreviewFormat::Png => {
+                    let mut canvas = PngCanvas::new(layout, scale);
+                    draw_checklist_page(&mut canvas, &title, lines, layout);
+                    canvas.to_png()?
+                }
+                PreviewFormat::Svg => {
+                    let mut canvas = SvgCanvas::new(layout);
+                    draw_checklist_page(&mut canvas, &title, lines, layout);
+                    canvas.finish().into_bytes()
+                }
+            };
+            write_page(path, bytes)?;
+        }
+    }
+    let mut load =
+        |item: &PrintItem, back: bool| load_preview_image(image_repo, options, item, back);
+    for sheet in 0..plan.packing.page_count() {
+        for back in [false, true] {
+            if back && !has_backs {
+                continue;
+            }
+            let name = match back {
+                true => format!("{}_back", sheet + 1),
+                false => format!("{}", sheet + 1),
+            };
+            let path = preview_path(pdf_path, &name, format);
+            let bytes = match format {
+                PreviewFormat::Png => {
+                    let mut canvas = PngCanvas::new(layout, scale);
+                    preview.draw_page(&mut canvas, sheet, back, &mut load)?;
+                    canvas.to_png()?
+                }
+                PreviewFormat::Svg => {
+                    let mut canvas = SvgCanvas::new(layout);
+                    preview.draw_page(&mut canvas, sheet, back, &mut load)?;
+                    canvas.finish().into_bytes()
+                }
+            };
+            write_page(path, bytes)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::card_size::CardSize;
+    use crate::checklist::Checklist;
+    use crate::cutter::Cutter;
+    use crate::deck::{PickedCard, PickedDeck, MAINBOARD};
+    use crate::guides::CutGuides;
+    use crate::layout::Layout;
+    use crate::packing::Packing;
+    use crate::pdf_calc::PageSpec;
+    use crate::preview::{base64, draw_checklist_page, PngCanvas, Preview, SvgCanvas};
+    use crate::render::{print_items, PrintItem, PrintMode, PrintPlan};
+    use printpdf::image_crate::io::Reader;
+    use printpdf::image_crate::DynamicImage;
+    use printpdf::Mm;
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::fs::{read, read_to_string, write};
+    use std::io::Cursor;
+    use std::path::Path;
+
+    /// Compare against a golden file, or rewrite it when UPDATE_GOLDEN is set
+    fn golden(name: &str, actual: &[u8]) -> Vec<u8> {
+        let path = Path::new("src/golden").join(name);
+        if env::var("UPDATE_GOLDEN").is_ok() {
+            write(&path, actual).unwrap();
+        }
+        read(&path).unwrap()
+    }
+
+    fn plan<'a>(deck: &'a PickedDeck, layout: &Layout) -> PrintPlan<'a> {
+        let items = print_items(deck, PrintMode::Duplex, false);
+        let layouts = vec![layout.clone()];
+        PrintPlan {
+            sizes: vec![CardSize::STANDARD; items.len()],
+            packing: Packing::new(&layouts, &vec![0; items.len()]),
+            layouts,
+            items,
+        }
+    }
+
+    fn no_image(_: &PrintItem, _: bool) -> Result<Option<DynamicImage>, String> {
+        Ok(None)
+    }
+
+    fn deck() -> PickedDeck {
+        let mut cards = BTreeMap::new();
+        let card = PickedCard {
+            quantity: 11,
+            set_code: "m21".to_string(),
+            collector_number: "176".to_string(),
+            name: "Colossal Dreadmaw".to_string(),
+            ..Default::default()
+        };
+        cards.insert(MAINBOARD.to_string(), vec![card]);
+        PickedDeck { cards }
+    }
+
+    #[test]
+    pub fn test_png_preview_matches_golden() {
+        let layout = Layout::new(&PageSpec::default())
+            .with_gutter(Mm(2.0))
+            .best_fit();
+        let deck = deck();
+        let plan = plan(&deck, &layout);
+        let preview = Preview {
+            plan: &plan,
+            layout: &layout,
+            guides: CutGuides::CropMarks,
+            cutter: None,
+        };
+        let mut canvas = PngCanvas::new(&layout, 1.0);
+        preview
+            .draw_page(&mut canvas, 1, false, &mut no_image)
+            .unwrap();
+        let png = canvas.to_png().unwrap();
+        let expected = golden("preview_crop_marks.png", &png);
+        let decode = |bytes: &[u8]| {
+            Reader::new(Cursor::new(bytes))
+                .with_guessed_format()
+                .unwrap()
+                .decode()
+                .unwrap()
+                .to_rgb8()
+        };
+        assert_eq!(decode(&png), decode(&expected));
+    }
+
+    #[test]
+    pub fn test_svg_preview_matches_golden() {
+        let page = PageSpec::default();
+        let cutter = Cutter::Silhouette;
+        let (width, height) = (page.width(), page.height());
+        let layout = Layout::new(&page)
+            .with_margin(cutter.margin(width, height))
+            .with_keep_out(cutter.keep_out(width, height));
+        let deck = deck();
+        let plan = plan(&deck, &layout);
+        let preview = Preview {
+            plan: &plan,
+            layout: &layout,
+            guides: CutGuides::Lines,
+            cutter: Some(cutter),
+        };
+        let mut canvas = SvgCanvas::new(&layout);
+        preview
+            .draw_page(&mut canvas, 0, false, &mut no_image)
+            .unwrap();
+        let svg = canvas.finish();
+        golden("preview_silhouette.svg", svg.as_bytes());
+        assert_eq!(
+            svg,
+            read_to_string("src/golden/preview_silhouette.svg").unwrap()
+        );
+    }
+
+    #[test]
+    pub fn test_checklist_preview() {
+        let layout = Layout::new(&PageSpec::default());
+        let checklist = Checklist::new("stompy", &deck());
+        let pages = checklist.pages(&layout);
+        assert_eq!(pages.len(), 1);
+        let (title, lines) = &pages[0];
+        let mut canvas = SvgCanvas::new(&layout);
+        draw_checklist_page(&mut canvas, title, lines, &layout);
+        let svg = canvas.finish();
+        assert!(svg.contains(">stompy</text>"));
+        assert!(svg.contains(">11 Colossal Dreadmaw M21 176</text>"));
+        assert!(svg.contains(">Total: 11 cards</text>"));
+        // The four sides of the tick box
+        assert_eq!(svg.matches("<line").count(), 4);
+    }
+
+    #[test]
+    pub fn test_base64() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+    }
+}
@@ -0,0 +1,274 @@
+use crate::card_size::CardSize;
+use crate::deck::PickedDeck;
+use crate::export::{
+    back_override, check_options, csv_field, export_entries, export_image, ExportEntry,
+    ExportTarget, ImageExport, ImageFormat, ImagePixels,
+};
+use crate::image_repository::ImageRepository;
+use crate::render::RenderOptions;
+use printpdf::Mm;
+use std::path::Path;
+
+/// Name of the file listing every card of the order with the images to upload for it
+const ORDER: &str = "order.csv";
+
+/// Numbers of cards MakePlayingCards sells a deck in
+const MPC_ORDER_SIZES: [usize; 16] = [
+    18, 36, 55, 72, 90, 108, 126, 144, 162, 180, 198, 216, 234, 396, 504, 612,
+];
+
+/// A print service to write card images and an upload list for
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum PrintShop {
+    /// MakePlayingCards: 822x1122 pixel images, a 750x1050 card with a 36 pixel bleed
+    Mpc,
+    /// Any other service: images of each card at its --card-size with its --bleed, at --dpi
+    Generic,
+}
+
+impl PrintShop {
+    fn name(&self) -> &'static str {
+        match self {
+            PrintShop::Mpc => "MakePlayingCards",
+            PrintShop::Generic => "the print shop",
+        }
+    }
+
+    /// The only card size the service prints, if it has one
+    fn card_size(&self) -> Option<CardSize> {
+        match self {
+            PrintShop::Mpc => Some(CardSize::STANDARD),
+            PrintShop::Generic => None,
+        }
+    }
+
+    /// Size of the images uploaded to the service for a card of the given size
+    fn pixels(&self, size: &CardSize, bleed: Mm, dpi: f32) -> ImagePixels {
+        match self {
+            PrintShop::Mpc => ImagePixels {
+                width: 750,
+                height: 1050,
+                bleed: 36,
+            },
+            PrintShop::Generic => ImagePixels::at_dpi(size, bleed, dpi),
+        }
+    }
+
+    /// Numbers of cards the service sells a deck in, or nothing if it takes any number
+    fn order_sizes(&self) -> &'static [usize] {
+        match self {
+            PrintShop::Mpc => &MPC_ORDER_SIZES,
+            PrintShop::Generic => &[],
+        }
+    }
+
+    /// The smallest number of cards the service sells that covers the order
+    fn order_size(&self, cards: usize) -> Option<usize> {
+        self.order_sizes()
+            .iter()
+            .copied()
+            .find(|size| *size >= cards)
+    }
+}
+
+/// Names of the images of an order: fronts and backs numbered by card, and one back shared by
+/// every single faced card
+struct OrderFiles {
+    format: ImageFormat,
+}
+
+impl OrderFiles {
+    fn front(&self, entry: &ExportEntry) -> String {
+        format!("fronts/{:03}.{}", entry.number, self.format.extension())
+    }
+
+    fn back(&self, entry: &ExportEntry) -> String {
+        format!("backs/{:03}.{}", entry.number, self.format.extension())
+    }
+
+    fn shared_back(&self) -> String {
+        format!("back.{}", self.format.extension())
+    }
+}
+
+/// A CSV with a row for every printed card in order, giving the images to upload for its front
+/// and back. `own_backs` says which cards have a back of their own rather than the shared one.
+fn order_csv(entries: &[ExportEntry], own_backs: &[bool], files: &OrderFiles) -> String {
+    let mut csv = String::from("slot,front,back,name,set,collector_number\n");
+    let mut slot = 0;
+    for (entry, own_back) in entries.iter().zip(own_backs) {
+        let back = match own_back {
+            true => files.back(entry),
+            false => files.shared_back(),
+        };
+        let card = entry.card;
+        for _ in 0..card.quantity {
+            slot += 1;
+            let fields = [
+                slot.to_string(),
+                files.front(entry),
+                back.clone(),
+                card.name.clone(),
+                card.set_code.to_uppercase(),
+                card.collector_number.clone(),
+            ];
+            let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+    }
+    csv
+}
+
+/// Write the images a print shop needs for the deck, at the exact size it asks for, with a list
+/// pairing the front and back of every card to print. Double faced cards, and single faced cards
+/// given a back of their own, get their own back image. The shared back is written at the size of
+/// the first single faced card.
+pub fn export_print_shop(
+    deck: &PickedDeck,
+    image_repo: &ImageRepository,
+    options: &RenderOptions,
+    shop: PrintShop,
+    export: &ImageExport,
+    path: &Path,
+) -> Result<(), String> {
+    check_options(options)?;
+    let entries = export_entries(deck);
+    if let Some(only) = shop.card_size() {
+        for entry in &entries {
+            let size = options.card_sizes.size_for(entry.section, entry.card);
+            if size != only {
+                return Err(format!(
+                    "{} only prints {}x{}mm cards, but {} is {}x{}mm",
+                    shop.name(),
+                    only.width.0,
+                    only.height.0,
+                    entry.card.name,
+                    size.width.0,
+                    size.height.0
+                ));
+            }
+        }
+    }
+    let format = export.format;
+    let files = OrderFiles { format };
+    let mut target = ExportTarget::new(path, export.zip)?;
+    let mut own_backs = vec![];
+    let mut has_shared_back = false;
+    for entry in &entries {
+        let card = entry.card;
+        println!(
+            "[{}/{}] Exporting card {} [{}:{}] for {}",
+            entry.number,
+            entries.len(),
+            card.name,
+            card.set_code,
+            card.collector_number,
+            shop.name()
+        );
+        let size = options.card_sizes.size_for(entry.section, card);
+        let pixels = shop.pixels(&size, options.layout.bleed(), export.dpi);
+        let (mut front, back) = image_repo.get_image(card)?;
+        let front = export_image(&mut front, &size, pixels, true, options, format)?;
+        target.add(&files.front(entry), &front)?;
+        let own_back = back_override(options, entry)?;
+        own_backs.push(card.double_sided || own_back.is_some());
+        match own_back {
+            Some(mut back) => {
+                let back = export_image(&mut back, &size, pixels, false, options, format)?;
+                target.add(&files.back(entry), &back)?;
+            }
+            None if card.double_sided => {
+                let mut back = back;
+                let back = export_image(&mut back, &size, pixels, true, options, format)?;
+                target.add(&files.back(entry), &back)?;
+            }
+            None if !has_shared_back => {
+                let mut back = back;
+                let back = export_image(&mut back, &size, pixels, false, options, format)?;
+                target.add(&files.shared_back(), &back)?;
+                has_shared_back = true;
+            }
+            None => {}
+        }
+    }
+    target.add(ORDER, order_csv(&entries, &own_backs, &files).as_bytes())?;
+    target.finish()?;
+    let cards: usize = entries
+        .iter()
+        .map(|entry| entry.card.quantity as usize)
+        .sum();
+    println!("Wrote {} cards to {}", cards, path.display());
+    match shop.order_size(cards) {
+        Some(size) => println!("Order {} cards from {}", size, shop.name()),
+        None if shop.order_sizes().is_empty() => {}
+        None => println!("{} cards is more than one {} order", cards, shop.name()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::card_size::CardSize;
+    use crate::deck::{PickedCard, PickedDeck, MAINBOARD};
+    use crate::export::ImagePixels;
+    use crate::export::{export_entries, ImageFormat};
+    use crate::print_shop::{order_csv, OrderFiles, PrintShop};
+    use printpdf::Mm;
+    use std::collections::BTreeMap;
+
+    #[test]
+    pub fn test_order_csv() {
+        let card = |name: &str, quantity: u32, double_sided: bool| PickedCard {
+            quantity,
+            set_code: "isd".to_string(),
+            collector_number: "51".to_string(),
+            name: name.to_string(),
+            double_sided,
+            ..Default::default()
+        };
+        let mut cards = BTreeMap::new();
+        cards.insert(
+            MAINBOARD.to_string(),
+            vec![card("Delver of Secrets", 1, true), card("Ponder", 2, false)],
+        );
+        let deck = PickedDeck { cards };
+        let entries = export_entries(&deck);
+        let files = OrderFiles {
+            format: ImageFormat::Png,
+        };
+        assert_eq!(
+            order_csv(&entries, &[true, false], &files),
+            "slot,front,back,name,set,collector_number\n\
+             1,fronts/001.png,backs/001.png,Delver of Secrets,ISD,51\n\
+             2,fronts/002.png,back.png,Ponder,ISD,51\n\
+             3,fronts/002.png,back.png,Ponder,ISD,51\n"
+        );
+    }
+
+    #[test]
+    pub fn test_order_size() {
+        let pixels = PrintShop::Mpc.pixels(&CardSize::STANDARD, Mm(0.0), 600.0);
+        assert_eq!(pixels.width + 2 * pixels.bleed, 822);
+        assert_eq!(pixels.height + 2 * pixels.bleed, 1122);
+        assert_eq!(PrintShop::Mpc.order_size(18), Some(18));
+        assert_eq!(PrintShop::Mpc.order_size(61), Some(72));
+        assert_eq!(PrintShop::Mpc.order_size(613), None);
+        assert_eq!(PrintShop::Generic.order_size(61), None);
+    }
+
+    #[test]
+    pub fn test_generic_pixels() {
+        // 70x120mm with 3mm of bleed at 300 DPI
+        let pixels = PrintShop::Generic.pixels(&CardSize::TAROT, Mm(3.0), 300.0);
+        assert_eq!(
+            pixels,
+            ImagePixels {
+                width: 827,
+                height: 1417,
+                bleed: 35,
+            }
+        );
+        assert_eq!(PrintShop::Generic.card_size(), None);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::bleed::border_colour;
+use crate::card_size::CardSize;
+use crate::pdf_calc::calculate_dpi;
+use printpdf::image_crate::imageops::FilterType;
+use printpdf::image_crate::{DynamicImage, ImageFormat, Rgb, RgbImage};
+use std::fmt::{Display, Formatter};
+use std::fs::create_dir_all;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Radius of the rounded corners of a card, as a fraction of its width
+pub const CORNER_RADIUS: f32 = 3.0 / 63.0;
+/// 64 bit FNV-1a parameters, for cache keys that stay the same between builds
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+pub const FNV_PRIME: u64 = 0x100000001b3;
+
+/// One step of image processing, applied to every card image before it goes into the PDF
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProcessingStep {
+    /// Paint the rounded corners of the scan in the colour of the card's border
+    FillCorners,
+    /// Add this much to every channel, -255 to 255
+    Brightness(i32),
+    /// Raise every channel to 1/gamma, so values above 1 lighten the mid tones
+    Gamma(f32),
+    /// Scale the saturation, 0 for greyscale and above 1 for more colourful
+    Saturation(f32),
+    /// Unsharp mask, blurring with this sigma in pixels. The blur reaches about three times as
+    /// far.
+    Sharpen(f32),
+    /// Resample images below this DPI at card size up to it
+    Upscale(f32),
+}
+
+impl FromStr for ProcessingStep {
+    type Err = String;
+
+    /// Parse `fill-corners`, `brightness=10`, `gamma=1.2`, `saturation=1.1`, `sharpen=1.5` or
+    /// `upscale=600`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (s.trim(), None),
+        };
+        let number = || {
+            value
+                .ok_or_else(|| {
+                    format!(
+                        "Processing step '{}' needs a value, as {}=<value>",
+                        name, name
+                    )
+                })?
+                .parse::<f32>()
+                .map_err(|e| format!("Invalid value for processing step '{}': {}", name, e))
+        };
+        match name.to_lowercase().as_str() {
+            "fill-corners" => Ok(ProcessingStep::FillCorners),
+            "brightness" => Ok(ProcessingStep::Brightness(number()?.round() as i32)),
+            "gamma" => match number()? {
+                gamma if gamma > 0.0 => Ok(ProcessingStep::Gamma(gamma)),
+                _ => Err("Gamma must be above 0".to_string()),
+            },
+            "saturation" => Ok(ProcessingStep::Saturation(number()?.max(0.0))),
+            "sharpen" => Ok(ProcessingStep::Sharpen(number()?)),
+            "upscale" => Ok(ProcessingStep::Upscale(number()?)),
+            _ => Err(format!("Unknown processing step '{}'", name)),
+        }
+    }
+}
+
+/// The step as it is written on the command line, which is also its part of the cache key
+impl Display for ProcessingStep {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProcessingStep::FillCorners => write!(f, "fill-corners"),
+            ProcessingStep::Brightness(value) => write!(f, "brightness={}", value),
+            ProcessingStep::Gamma(gamma) => write!(f, "gamma={}", gamma),
+            ProcessingStep::Saturation(saturation) => write!(f, "saturation={}", saturation),
+            ProcessingStep::Sharpen(sigma) => write!(f, "sharpen={}", sigma),
+            ProcessingStep::Upscale(dpi) => write!(f, "upscale={}", dpi),
+        }
+    }
+}
+
+impl ProcessingStep {
+    pub fn apply(&self, image: DynamicImage) -> DynamicImage {
+        match self {
+            ProcessingStep::FillCorners => fill_corners(image),
+            ProcessingStep::Brightness(value) => image.brighten(*value),
+            ProcessingStep::Gamma(gamma) => {
+                map_channels(image, |c| (c / 255.0).powf(1.0 / gamma) * 255.0)
+            }
+            ProcessingStep::Saturation(saturation) => saturate(image, *saturation),
+            ProcessingStep::Sharpen(sigma) => image.unsharpen(*sigma, 2),
+            ProcessingStep::Upscale(dpi) => upscale(image, *dpi),
+        }
+    }
+}
+
+/// Processing steps applied in order, with the results cached on disk
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    steps: Vec<ProcessingStep>,
+    cache: Option<PathBuf>,
+}
+
+impl Pipeline {
+    pub fn new(steps: Vec<ProcessingStep>) -> Pipeline {
+        Pipeline { steps, cache: None }
+    }
+
+    /// Keep processed images in this directory, so each is only processed once
+    pub fn with_cache(mut self, cache: PathBuf) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Process an image decoded from `source`, using the cached result if there is one
+    pub fn process(&self, source: &[u8], image: DynamicImage) -> Result<DynamicImage, String> {
+        if self.steps.is_empty() {
+            return Ok(image);
+        }
+        let cached = self
+            .cache
+            .as_ref()
+            .map(|cache| cache.join(format!("{:016x}.png", self.key(source))));
+        if let Some(cached) = cached.as_ref().filter(|c| c.exists()) {
+            if let Ok(image) = printpdf::image_crate::open(cached) {
+                return Ok(image);
+            }
+        }
+        let image = self
+            .steps
+            .iter()
+            .fold(image, |image, step| step.apply(image));
+        if let Some(cached) = cached {
+            if let Some(parent) = cached.parent() {
+                create_dir_all(parent).map_err(|e| format!("Could not create directory: {}", e))?;
+            }
+            image
+                .save_with_format(&cached, ImageFormat::Png)
+                .map_err(|e| format!("Could not cache processed image {:?}: {}", cached, e))?;
+        }
+        Ok(image)
+    }
+
+    /// Hash of the source image and the steps, so changing either gives a fresh cache entry
+    pub fn key(&self, source: &[u8]) -> u64 {
+        let steps: Vec<String> = self.steps.iter().map(ProcessingStep::to_string).collect();
+        let steps = steps.join(",");
+        let bytes = source.iter().chain(&[0]).chain(steps.as_bytes());
+        bytes.fold(FNV_OFFSET, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+        })
+    }
+}
+
+fn map_channels<F: Fn(f32) -> f32>(image: DynamicImage, f: F) -> DynamicImage {
+    let lookup: Vec<u8> = (0..=255)
+        .map(|c| f(c as f32).round().clamp(0.0, 255.0) as u8)
+        .collect();
+    let mut image = image.to_rgb8();
+    for pixel in image.pixels_mut() {
+        for channel in pixel.0.iter_mut() {
+            *channel = lookup[*channel as usize];
+        }
+    }
+    DynamicImage::ImageRgb8(image)
+}
+
+fn saturate(image: DynamicImage, saturation: f32) -> DynamicImage {
+    let mut image = image.to_rgb8();
+    for pixel in image.pixels_mut() {
+        let [r, g, b] = pixel.0.map(|c| c as f32);
+        let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+        *pixel = Rgb(
+            [r, g, b].map(|c| (luma + (c - luma) * saturation).round().clamp(0.0, 255.0) as u8)
+        );
+    }
+    DynamicImage::ImageRgb8(image)
+}
+
+fn fill_corners(image: DynamicImage) -> DynamicImage {
+    let colour = border_colour(&image);
+    let mut image: RgbImage = image.to_rgb8();
+    let (width, height) = image.dimensions();
+    let radius = (width as f32 * CORNER_RADIUS).ceil();
+    let size = radius as u32;
+    for y in 0..size.min(height) {
+        for x in 0..size.min(width) {
+            // Distance from the centre of the corner's arc, for the top left corner
+            let dx = radius - x as f32 - 0.5;
+            let dy = radius - y as f32 - 0.5;
+            if dx * dx + dy * dy <= radius * radius {
+                continue;
+            }
+            for (px, py) in [
+                (x, y),
+                (width - 1 - x, y),
+                (x, height - 1 - y),
+                (width - 1 - x, height - 1 - y),
+            ] {
+                image.put_pixel(px, py, colour);
+            }
+        }
+    }
+    DynamicImage::ImageRgb8(image)
+}
+
+fn upscale(image: DynamicImage, dpi: f32) -> DynamicImage {
+    // Taken at standard card size, turned to match the image for cards read sideways
+    let size = match image.width() > image.height() {
+        true => CardSize::STANDARD.landscape(),
+        false => CardSize::STANDARD,
+    };
+    let current = calculate_dpi(image.width() as usize, image.height() as usize, &size);
+    if current >= dpi {
+        return image;
+    }
+    let scale = dpi / current;
+    let width = (image.width() as f32 * scale).round() as u32;
+    let height = (image.height() as f32 * scale).round() as u32;
+    image.resize_exact(width, height, FilterType::Lanczos3)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::processing::{Pipeline, ProcessingStep};
+    use printpdf::image_crate::{DynamicImage, Rgb, RgbImage};
+
+    #[test]
+    pub fn test_parse_steps() {
+        assert_eq!(
+            "fill-corners".parse::<ProcessingStep>(),
+            Ok(ProcessingStep::FillCorners)
+        );
+        assert_eq!(
+            "gamma=1.2".parse::<ProcessingStep>(),
+            Ok(ProcessingStep::Gamma(1.2))
+        );
+        assert_eq!(
+            "brightness=-10".parse::<ProcessingStep>(),
+            Ok(ProcessingStep::Brightness(-10))
+        );
+        assert!("gamma=0".parse::<ProcessingStep>().is_err());
+        assert!("sharpen".parse::<ProcessingStep>().is_err());
+        assert!("blur=2".parse::<ProcessingStep>().is_err());
+        for step in ["fill-corners", "brightness=-10", "gamma=1.2", "sharpen=1.5"] {
+            assert_eq!(step.parse::<ProcessingStep>().unwrap().to_string(), step);
+        }
+    }
+
+    #[test]
+    pub fn test_cache_key() {
+        let pipeline = Pipeline::new(vec![ProcessingStep::Gamma(1.2)]);
+        assert_eq!(pipeline.key(b""), pipeline.key(b""));
+        assert_ne!(pipeline.key(b"a"), pipeline.key(b"b"));
+        let sharper = Pipeline::new(vec![
+            ProcessingStep::Gamma(1.2),
+            ProcessingStep::Sharpen(1.0),
+        ]);
+        assert_ne!(pipeline.key(b"a"), sharper.key(b"a"));
+        // FNV-1a of a single zero byte, so the key is the same from one build to the next
+        assert_eq!(Pipeline::new(vec![]).key(b""), 0xaf63bd4c8601b7df);
+    }
+
+    #[test]
+    pub fn test_pipeline() {
+        // A black bordered card with white corners, as a JPEG scan of a card has
+        let mut card = RgbImage::from_pixel(63, 88, Rgb([0, 0, 0]));
+        card.put_pixel(0, 0, Rgb([255, 255, 255]));
+        card.put_pixel(62, 87, Rgb([255, 255, 255]));
+        card.put_pixel(31, 44, Rgb([128, 64, 32]));
+        let card = DynamicImage::ImageRgb8(card);
+
+        let pipeline = Pipeline::new(vec![
+            ProcessingStep::FillCorners,
+            ProcessingStep::Saturation(0.0),
+            ProcessingStep::Upscale(2.0 * 25.4),
+        ]);
+        let image = pipeline.process(&[], card.clone()).unwrap().to_rgb8();
+        assert_eq!(image.dimensions(), (126, 176));
+        assert_eq!(image.get_pixel(0, 0), &Rgb([0, 0, 0]));
+        assert_eq!(image.get_pixel(125, 175), &Rgb([0, 0, 0]));
+        let centre = image.get_pixel(63, 89);
+        assert_eq!(centre[0], centre[2]);
+
+        let brighter = Pipeline::new(vec![ProcessingStep::Brightness(10)])
+            .process(&[], card)
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(brighter.get_pixel(31, 44), &Rgb([138, 74, 42]));
+    }
+}
@@ -0,0 +1,1029 @@
+use crate::bleed::{add_bleed, BleedFill};
+use crate::card_back::CardBacks;
+use crate::card_size::{CardSize, CardSizes};
+use crate::checklist::Checklist;
+use crate::cutter::{write_cut_files, Cutter};
+use crate::deck::{PickedCard, PickedDeck};
+use crate::guides::{clip_lines, page_guide_lines, CutGuides};
+use crate::image_repository::ImageRepository;
+use crate::layout::{Layout, Rect};
+use crate::packing::Packing;
+use crate::pdf_calc::{calculate_dpi, pixels_at_dpi, CARD_HEIGHT, CARD_WIDTH};
+use crate::pdf_images::ImageCompression;
+use crate::pdf_writer::{Images, Layer, PdfWriter};
+use crate::pdf_x::{check_options, make_print_ready, DocumentInfo};
+use crate::processing::{Pipeline, FNV_PRIME};
+use crate::reprint::{lock_entry, write_lock, Reprint};
+use crate::text_proxy::{draw_divider, draw_text_card, face_text, Fonts};
+use crate::watermark::Watermark;
+use printpdf::image_crate::io::Reader;
+use printpdf::image_crate::{imageops, DynamicImage, Rgba, RgbaImage};
+use printpdf::{CurTransMat, ImageXObject, Mm, Pt};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{write, File};
+use std::io::{Cursor, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Font size of the deck name written in the gutter below each card
+const DECK_TAG_SIZE: f32 = 4.0;
+/// Narrowest gutter a deck tag fits in
+const DECK_TAG_GUTTER: Mm = Mm(1.5);
+
+/// How fronts and backs are arranged across pages and files
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PrintMode {
+    /// Each page of fronts is followed by its page of backs, for duplex printers
+    #[default]
+    Duplex,
+    /// Fronts only. Double sided cards print their back face in a slot of its own.
+    FrontsOnly,
+    /// All pages of fronts, then all pages of backs, for feeding the stack back in by hand
+    FrontsThenBacks,
+    /// Fronts and backs written to two separate files
+    SeparateFiles,
+}
+
+impl PrintMode {
+    /// Pages each sheet takes up in the documents it is printed in
+    fn pages_per_sheet(&self) -> usize {
+        match self {
+            PrintMode::Duplex | PrintMode::FrontsThenBacks => 2,
+            PrintMode::FrontsOnly | PrintMode::SeparateFiles => 1,
+        }
+    }
+
+    /// Pages taken by a cover of `pages` pages. In duplex the cover is padded to whole sheets so
+    /// fronts stay on odd pages.
+    fn cover_pages(&self, pages: usize) -> usize {
+        match self {
+            PrintMode::Duplex => pages.next_multiple_of(2),
+            _ => pages,
+        }
+    }
+}
+
+/// Everything about how a deck is drawn onto pages, other than the deck itself
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub layout: Layout,
+    pub guides: CutGuides,
+    pub bleed_fill: BleedFill,
+    pub mode: PrintMode,
+    pub card_backs: CardBacks,
+    pub card_sizes: CardSizes,
+    pub processing: Pipeline,
+    pub compression: ImageCompression,
+    pub watermark: Option<Watermark>,
+    /// Print card art in greyscale
+    pub grey_art: bool,
+    /// Print the name and rules text of each card instead of its image
+    pub text_proxies: bool,
+    /// Write the deck each card came from in the gutter below it
+    pub deck_tags: bool,
+    /// Title of the PDF and of the checklist
+    pub title: String,
+    /// Author of the PDF, instead of the name of this program
+    pub author: Option<String>,
+    /// Claim PDF/X-4, with an output intent, trim and bleed boxes and sRGB tagged colours
+    pub pdf_x: bool,
+    /// Start with pages listing every card to print
+    pub checklist: bool,
+    /// Print a divider card at the start of each section of each deck
+    pub dividers: bool,
+    /// Cutting machine to print registration marks for and write cut files for
+    pub cutter: Option<Cutter>,
+    /// Write the run as several PDFs of at most this many pages, so only one of them is held in
+    /// memory at a time
+    pub split_pages: Option<usize>,
+    /// Print only some sheets or cards of the run again, each in the slot it had before
+    pub reprint: Option<Reprint>,
+}
+
+/// Which sides of a card go into a slot
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CardFace {
+    /// The front in the slot on the front page, the back in the slot on the back page
+    Both,
+    /// Only the front of the card
+    Front,
+    /// Only the back face of a double sided card, printed where a front would go
+    Back,
+    /// A divider naming the section and deck of the cards after it, with a blank back
+    Divider,
+}
+
+/// One slot worth of printing
+#[derive(Debug)]
+pub struct PrintItem<'a> {
+    pub section: &'a str,
+    pub card: &'a PickedCard,
+    pub face: CardFace,
+}
+
+/// Where every slot of a print run goes, worked out before anything is drawn
+#[derive(Debug)]
+pub struct PrintPlan<'a> {
+    pub items: Vec<PrintItem<'a>>,
+    /// Size of the card in each item
+    pub sizes: Vec<CardSize>,
+    /// The layout for each distinct card size, in order of first use
+    pub layouts: Vec<Layout>,
+    pub packing: Packing,
+}
+
+impl PrintPlan<'_> {
+    pub fn new<'a>(deck: &'a PickedDeck, options: &RenderOptions) -> Result<PrintPlan<'a>, String> {
+        let layout = &options.layout;
+        let items = print_items(deck, options.mode, options.dividers);
+        let sizes: Vec<CardSize> = items
+            .iter()
+            .map(|item| options.card_sizes.size_for(item.section, item.card))
+            .collect();
+        // Each distinct card size gets the layout's grid worked out again for that size
+        let mut layouts: Vec<Layout> = vec![];
+        let mut layout_sizes: Vec<CardSize> = vec![];
+        let mut item_layouts = vec![];
+        for size in &sizes {
+            let index = match layout_sizes.iter().position(|s| s == size) {
+                Some(index) => index,
+                None => {
+                    let sized = layout.clone().with_card_size(*size);
+                    sized.validate()?;
+                    layouts.push(sized);
+                    layout_sizes.push(*size);
+                    layouts.len() - 1
+                }
+            };
+            item_layouts.push(index);
+        }
+        if layouts.is_empty() {
+            layout.validate()?;
+        }
+        let packing = Packing::new(&layouts, &item_layouts);
+        Ok(PrintPlan {
+            items,
+            sizes,
+            layouts,
+            packing,
+        })
+    }
+}
+
+/// Where one face of a card is drawn: its slot on the page, how far it is turned clockwise, and
+/// the size of the card as it is read
+#[derive(Debug, Copy, Clone)]
+struct Placement {
+    rect: Rect,
+    rotation: u16,
+    size: CardSize,
+}
+
+/// Expand a deck into one item per printed slot, in deck order. With `dividers`, each run of
+/// cards from the same section and deck starts with a divider, which refers to its first card.
+pub fn print_items(deck: &PickedDeck, mode: PrintMode, dividers: bool) -> Vec<PrintItem<'_>> {
+    let mut items = vec![];
+    for (section, cards) in &deck.cards {
+        for (index, card) in cards.iter().enumerate() {
+            let new_group = index == 0 || cards[index - 1].deck != card.deck;
+            if dividers && new_group {
+                items.push(PrintItem {
+                    section,
+                    card,
+                    face: CardFace::Divider,
+                });
+            }
+            for _ in 0..card.quantity {
+                match mode {
+                    PrintMode::FrontsOnly => {
+                        items.push(PrintItem {
+                            section,
+                            card,
+                            face: CardFace::Front,
+                        });
+                        if card.double_sided {
+                            items.push(PrintItem {
+                                section,
+                                card,
+                                face: CardFace::Back,
+                            });
+                        }
+                    }
+                    _ => items.push(PrintItem {
+                        section,
+                        card,
+                        face: CardFace::Both,
+                    }),
+                }
+            }
+        }
+    }
+    items
+}
+
+/// A PDF being written. Pages are added as they are first drawn on.
+struct OutputDocument {
+    path: PathBuf,
+    doc: PdfWriter,
+    /// Pages the document has once it is finished
+    page_count: usize,
+    /// Fonts for text proxies and text stamped on the cards
+    fonts: Fonts,
+    /// Where the cards are on each page, for the trim boxes of PDF/X
+    cards: BTreeMap<usize, Vec<Rect>>,
+}
+
+impl OutputDocument {
+    fn new(
+        path: PathBuf,
+        info: &DocumentInfo,
+        options: &RenderOptions,
+        page_count: usize,
+    ) -> OutputDocument {
+        let layout = &options.layout;
+        let mut doc = info.writer(options.pdf_x, layout.page_width(), layout.page_height());
+        let fonts = Fonts::add(&mut doc);
+        OutputDocument {
+            path,
+            doc,
+            page_count,
+            fonts,
+            cards: BTreeMap::new(),
+        }
+    }
+
+    /// The layer of a page, adding the pages before it that are not there yet
+    fn layer(&mut self, page: usize) -> Layer {
+        self.doc.layer(page)
+    }
+
+    fn save(mut self, options: &RenderOptions) -> Result<(), String> {
+        // Pages nothing was drawn on, such as blank backs, are still printed
+        self.layer(self.page_count - 1);
+        if options.pdf_x {
+            make_print_ready(&mut self.doc, &self.cards, &options.layout);
+        }
+        let pdf = self.doc.save_to_bytes()?;
+        write(&self.path, pdf).map_err(|e| format!("Could not write {:?}: {}", self.path, e))?;
+        println!("Wrote {}", self.path.display());
+        Ok(())
+    }
+}
+
+/// One side of a sheet, and the fonts and images of the document it is in
+struct SheetSide {
+    layer: Layer,
+    fonts: Fonts,
+    images: Images,
+}
+
+/// A page in one of the output documents, as (document, page)
+type PageRef = (usize, usize);
+
+/// The front page and, depending on the print mode, back page of a run of sheets
+struct Sheets {
+    documents: Vec<OutputDocument>,
+    /// For every sheet in these documents, (document, page) of the front and of the back
+    pages: BTreeMap<usize, (PageRef, Option<PageRef>)>,
+}
+
+impl Sheets {
+    /// Pages for the given sheets in order, after `cover` pages at the start of the first
+    /// document
+    fn new(
+        pdf_file_path: &Path,
+        info: &DocumentInfo,
+        options: &RenderOptions,
+        numbers: &[usize],
+        cover: usize,
+    ) -> Result<Sheets, String> {
+        let sheets = numbers.len();
+        let cover = options.mode.cover_pages(cover);
+        let pdf_file_path = pdf_file_path.to_path_buf();
+        let new = |path: PathBuf, pages: usize| OutputDocument::new(path, info, options, pages);
+        let (documents, pages): (_, Vec<(PageRef, Option<PageRef>)>) = match options.mode {
+            PrintMode::Duplex => (
+                vec![new(pdf_file_path, cover + sheets * 2)],
+                (0..sheets)
+                    .map(|sheet| {
+                        let front = cover + sheet * 2;
+                        ((0, front), Some((0, front + 1)))
+                    })
+                    .collect(),
+            ),
+            PrintMode::FrontsOnly => (
+                vec![new(pdf_file_path, cover + sheets)],
+                (0..sheets)
+                    .map(|sheet| ((0, cover + sheet), None))
+                    .collect(),
+            ),
+            PrintMode::FrontsThenBacks => (
+                vec![new(pdf_file_path, cover + sheets * 2)],
+                (0..sheets)
+                    .map(|sheet| ((0, cover + sheet), Some((0, cover + sheets + sheet))))
+                    .collect(),
+            ),
+            PrintMode::SeparateFiles => {
+                let fronts = suffixed_path(&pdf_file_path, "fronts");
+                let backs = suffixed_path(&pdf_file_path, "backs");
+                (
+                    vec![new(fronts, cover + sheets), new(backs, sheets)],
+                    (0..sheets)
+                        .map(|sheet| ((0, cover + sheet), Some((1, sheet))))
+                        .collect(),
+                )
+            }
+        };
+        Ok(Sheets {
+            documents,
+            pages: numbers.iter().copied().zip(pages).collect(),
+        })
+    }
+
+    fn side(&mut self, (document, page): PageRef) -> SheetSide {
+        let document = &mut self.documents[document];
+        SheetSide {
+            layer: document.layer(page),
+            fonts: document.fonts.clone(),
+            images: document.doc.images(),
+        }
+    }
+
+    /// Note where a card is on a page of one of the documents
+    fn place_card(&mut self, (document, page): PageRef, card: Rect) {
+        let cards = &mut self.documents[document].cards;
+        cards.entry(page).or_default().push(card);
+    }
+
+    /// Add a bookmark to the outline of the document a page is in, nested under the entries
+    /// titled by the rest of `path`
+    fn bookmark(&mut self, (document, page): PageRef, path: &[String]) {
+        self.documents[document].doc.add_bookmark(path, page);
+    }
+
+    fn front_page(&self, sheet: usize) -> PageRef {
+        self.pages[&sheet].0
+    }
+
+    fn front(&mut self, sheet: usize) -> SheetSide {
+        self.side(self.front_page(sheet))
+    }
+
+    fn back_page(&self, sheet: usize) -> Option<PageRef> {
+        self.pages[&sheet].1
+    }
+
+    fn back(&mut self, sheet: usize) -> Option<SheetSide> {
+        self.back_page(sheet).map(|page| self.side(page))
+    }
+}
+
+/// `deck.pdf` becomes `deck_fronts.pdf` for a suffix of `fronts`
+pub fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}_{}.pdf", stem, suffix))
+}
+
+fn read_image(file: &mut File) -> Result<Vec<u8>, String> {
+    let mut source = vec![];
+    file.read_to_end(&mut source)
+        .map_err(|e| format!("Could not read image: {}", e))?;
+    Ok(source)
+}
+
+/// Decode a JPEG or PNG card image and crop it to the shape of a card of `size`, applying the
+/// image processing steps. `art` is false for the shared back of single faced cards, which is
+/// never greyed out.
+pub fn prepare_card_image(
+    file: &mut File,
+    size: &CardSize,
+    art: bool,
+    options: &RenderOptions,
+) -> Result<DynamicImage, String> {
+    decode_card_image(&read_image(file)?, size, art, options)
+}
+
+fn decode_card_image(
+    source: &[u8],
+    size: &CardSize,
+    art: bool,
+    options: &RenderOptions,
+) -> Result<DynamicImage, String> {
+    let image = Reader::new(Cursor::new(source))
+        .with_guessed_format()
+        .map_err(|e| format!("Could not read image: {}", e))?
+        .decode()
+        .map_err(|e| format!("Could not decode image: {}", e))?;
+    let image = flatten(image);
+    // Images of cards read sideways, and the backs printed behind them, may be stored upright
+    let image = match size.is_landscape() && image.height() > image.width() {
+        true => image.rotate90(),
+        false => image,
+    };
+    let image = crop_to_card(image, size);
+    let image = options.processing.process(source, image)?;
+    Ok(match art && options.grey_art {
+        true => DynamicImage::ImageRgb8(image.grayscale().to_rgb8()),
+        false => image,
+    })
+}
+
+/// Prepare a card image for its place on the page, adding the bleed and turning it clockwise by
+/// the placement's rotation
+fn load_card_image(
+    source: &[u8],
+    place: &Placement,
+    art: bool,
+    options: &RenderOptions,
+) -> Result<ImageXObject, String> {
+    let image = decode_card_image(source, &place.size, art, options)?;
+    let dpi = calculate_dpi(image.width() as usize, image.height() as usize, &place.size);
+    let image = options.compression.downsample(image, dpi);
+    let dpi = calculate_dpi(image.width() as usize, image.height() as usize, &place.size);
+    let bleed_px = pixels_at_dpi(options.layout.bleed(), dpi);
+    let image = add_bleed(&image, bleed_px, options.bleed_fill);
+    let image = match place.rotation {
+        90 => image.rotate90(),
+        180 => image.rotate180(),
+        270 => image.rotate270(),
+        _ => image,
+    };
+    options.compression.embed(&image)
+}
+
+/// Put images with transparency, such as PNGs with rounded corners, onto a white background
+fn flatten(image: DynamicImage) -> DynamicImage {
+    if !image.color().has_alpha() {
+        return image;
+    }
+    let (width, height) = (image.width(), image.height());
+    let mut background = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+    imageops::overlay(&mut background, &image.to_rgba8(), 0, 0);
+    DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(background).to_rgb8())
+}
+
+/// Trim the long side of an image that is not the shape of the card, such as custom artwork, so
+/// it is not stretched to fit
+fn crop_to_card(image: DynamicImage, size: &CardSize) -> DynamicImage {
+    let (width, height) = (image.width(), image.height());
+    let card_aspect = size.width.0 / size.height.0;
+    let aspect = width as f32 / height as f32;
+    if (aspect / card_aspect - 1.0).abs() < 0.01 {
+        return image;
+    }
+    match aspect > card_aspect {
+        true => {
+            let cropped = (height as f32 * card_aspect).round() as u32;
+            image.crop_imm((width - cropped) / 2, 0, cropped, height)
+        }
+        false => {
+            let cropped = (width as f32 / card_aspect).round() as u32;
+            image.crop_imm(0, (height - cropped) / 2, width, cropped)
+        }
+    }
+}
+
+/// Run `draw` with a standard card in place of the card placed on the page, scaled to fill it.
+/// Text and watermarks are laid out on a standard card, which cards read sideways take turned a
+/// quarter.
+fn draw_scaled<F: FnOnce(&Rect, u16)>(layer: &Layer, place: &Placement, draw: F) {
+    let rotation = match place.size.is_landscape() {
+        true => (place.rotation + 90) % 360,
+        false => place.rotation,
+    };
+    let (width, height) = match rotation % 180 {
+        90 => (CARD_HEIGHT, CARD_WIDTH),
+        _ => (CARD_WIDTH, CARD_HEIGHT),
+    };
+    let rect = &place.rect;
+    let (scale_x, scale_y) = (rect.width.0 / width.0, rect.height.0 / height.0);
+    if (scale_x - 1.0).abs() < 0.001 && (scale_y - 1.0).abs() < 0.001 {
+        draw(rect, rotation);
+        return;
+    }
+    layer.save_graphics_state();
+    layer.set_ctm(CurTransMat::Scale(scale_x, scale_y));
+    let standard = Rect::new(rect.x / scale_x, rect.y / scale_y, width, height);
+    draw(&standard, rotation);
+    layer.restore_graphics_state();
+}
+
+/// Key of a card image as it is embedded: the same source turned and sized the same way is
+/// embedded once, however many copies of the card are printed
+fn image_key(source: &[u8], place: &Placement, grey: bool, options: &RenderOptions) -> u64 {
+    let mut key = options.processing.key(source);
+    let size = &place.size;
+    let parts = [
+        place.rotation as u64,
+        size.width.0.to_bits() as u64,
+        size.height.0.to_bits() as u64,
+        grey as u64,
+    ];
+    for part in parts {
+        key = (key ^ part).wrapping_mul(FNV_PRIME);
+    }
+    key
+}
+
+/// Draw one face of a card from `deck` into its place on one side of a sheet, with the watermark
+/// over any card art. `back` is true for the back face of a card, which is only watermarked if
+/// the watermark asks for it.
+fn draw_face(
+    side: &SheetSide,
+    file: &mut File,
+    place: &Placement,
+    art: bool,
+    back: bool,
+    deck: &str,
+    options: &RenderOptions,
+) -> Result<(), String> {
+    let source = read_image(file)?;
+    let key = image_key(&source, place, art && options.grey_art, options);
+    let image = side
+        .images
+        .get_or_add(key, || load_card_image(&source, place, art, options))?;
+    side.layer
+        .draw_image(&image, &place.rect.expand(options.layout.bleed()));
+    let stamped = |watermark: &&Watermark| art && (!back || watermark.backs);
+    if let Some(watermark) = options.watermark.as_ref().filter(stamped) {
+        let watermark = watermark.for_deck(deck);
+        draw_scaled(&side.layer, place, |rect, rotation| {
+            watermark.draw(&side.layer, &side.fonts.bold, rect, rotation);
+        });
+    }
+    Ok(())
+}
+
+/// Draw the text of one face of a card into its place on one side of a sheet, with the
+/// watermark over it unless it is a back face the watermark leaves out
+fn draw_text_face(
+    side: &SheetSide,
+    card: &PickedCard,
+    back: bool,
+    place: &Placement,
+    options: &RenderOptions,
+) {
+    let text = face_text(card, back);
+    let watermark = options
+        .watermark
+        .as_ref()
+        .filter(|watermark| !back || watermark.backs)
+        .map(|watermark| watermark.for_deck(&card.deck));
+    draw_scaled(&side.layer, place, |rect, rotation| {
+        draw_text_card(&side.layer, &side.fonts, card, &text, rect, rotation);
+        if let Some(watermark) = &watermark {
+            watermark.draw(&side.layer, &side.fonts.bold, rect, rotation);
+        }
+    });
+}
+
+/// Write the name of the deck a card came from in the gutter below it
+fn draw_deck_tag(side: &SheetSide, slot: &Rect, deck: &str, layout: &Layout) {
+    let x = slot.x - layout.bleed();
+    // Centre the capitals of the tag in the gutter
+    let y = slot.y - layout.bleed() - layout.gutter() / 2.0 - Mm::from(Pt(DECK_TAG_SIZE * 0.36));
+    side.layer
+        .use_text(deck, DECK_TAG_SIZE, x, y, &side.fonts.regular);
+}
+
+/// Draw the divider card starting a run of cards from one section of one deck, naming both and
+/// counting the cards
+fn draw_section_divider(side: &SheetSide, deck: &PickedDeck, item: &PrintItem, place: &Placement) {
+    let count: u32 = deck.cards[item.section]
+        .iter()
+        .filter(|card| card.deck == item.card.deck)
+        .map(|card| card.quantity)
+        .sum();
+    let lines = [
+        item.section.to_string(),
+        item.card.deck.clone(),
+        format!("{} cards", count),
+    ];
+    draw_scaled(&side.layer, place, |rect, rotation| {
+        draw_divider(&side.layer, &side.fonts, &lines, rect, rotation);
+    });
+}
+
+/// The sheets that go into each PDF when none may have more than `max_pages` pages, or into one
+/// PDF if there is no limit, with the `cover` pages at the start of the first one
+fn split_sheets(
+    sheets: usize,
+    cover: usize,
+    mode: PrintMode,
+    max_pages: Option<usize>,
+) -> Result<Vec<Range<usize>>, String> {
+    let max_pages = max_pages.unwrap_or(usize::MAX);
+    let (per_sheet, cover) = (mode.pages_per_sheet(), mode.cover_pages(cover));
+    if max_pages < cover + per_sheet {
+        return Err(format!(
+            "Each PDF needs room for at least {} pages, got {}",
+            cover + per_sheet,
+            max_pages
+        ));
+    }
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut room = max_pages - cover;
+    while start < sheets {
+        let end = sheets.min(start + room / per_sheet);
+        parts.push(start..end);
+        start = end;
+        room = max_pages;
+    }
+    Ok(parts)
+}
+
+/// Render a deck to one or more PDFs according to the print mode
+pub fn render_deck(
+    deck: &PickedDeck,
+    image_repo: &ImageRepository,
+    options: &RenderOptions,
+    pdf_file_path: &Path,
+) -> Result<(), String> {
+    let layout = &options.layout;
+    if options.pdf_x {
+        check_options(options)?;
+    }
+    if options.deck_tags && layout.gutter() < DECK_TAG_GUTTER {
+        return Err(format!(
+            "Deck tags need a gutter of at least {}mm",
+            DECK_TAG_GUTTER.0
+        ));
+    }
+    let PrintPlan {
+        items,
+        sizes,
+        layouts,
+        packing,
+    } = PrintPlan::new(deck, options)?;
+    let total = items.len();
+    let sheet_count = packing.page_count();
+    match layouts.as_slice() {
+        [single] => println!(
+            "Printing {} cards {}-up on {} sheets",
+            total,
+            single.open_slots_per_page(),
+            single.page_count(total)
+        ),
+        _ => println!("Printing {} cards on {} sheets", total, sheet_count),
+    }
+    // Number each slot on its sheet for the layout lock
+    let mut numbers = vec![];
+    let mut on_sheet = (0, 0);
+    for slot in &packing.slots {
+        on_sheet = match on_sheet.0 == slot.page {
+            true => (slot.page, on_sheet.1 + 1),
+            false => (slot.page, 1),
+        };
+        numbers.push(on_sheet.1);
+    }
+    let entries: Vec<String> = (0..items.len())
+        .map(|index| {
+            let slot = &packing.slots[index];
+            lock_entry(&items[index], slot, slot.page + 1, numbers[index])
+        })
+        .collect();
+    // A reprint lays out the whole run as before, then only draws what is asked for
+    let kept: Vec<bool> = match &options.reprint {
+        Some(reprint) => {
+            reprint.validate(&items, sheet_count)?;
+            reprint.check_layout(&entries)?;
+            let kept: Vec<bool> = items
+                .iter()
+                .zip(&packing.slots)
+                .map(|(item, slot)| reprint.keeps(item, slot))
+                .collect();
+            match kept.iter().filter(|kept| **kept).count() {
+                0 => return Err("None of the cards to reprint are on those pages".to_string()),
+                count => println!("Reprinting {} of the cards", count),
+            }
+            kept
+        }
+        None => vec![true; items.len()],
+    };
+    let printed: Vec<usize> = match options.reprint {
+        Some(_) => packing
+            .slots
+            .iter()
+            .zip(&kept)
+            .filter(|(_, kept)| **kept)
+            .map(|(slot, _)| slot.page)
+            .collect::<BTreeSet<usize>>()
+            .into_iter()
+            .collect(),
+        // An empty deck still gets a blank sheet
+        None => (0..sheet_count.max(1)).collect(),
+    };
+    // The items drawn on each sheet, so each part only goes through its own
+    let mut on_sheets: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (index, slot) in packing.slots.iter().enumerate() {
+        if kept[index] {
+            on_sheets.entry(slot.page).or_default().push(index);
+        }
+    }
+    let checklist = options
+        .checklist
+        .then(|| Checklist::new(&options.title, deck))
+        .filter(|_| options.reprint.is_none());
+    let info = DocumentInfo::new(&options.title, options.author.as_deref(), deck);
+    let cover = checklist.as_ref().map_or(0, |c| c.page_count(layout));
+    let parts = split_sheets(printed.len(), cover, options.mode, options.split_pages)?;
+    if let (true, Some(pages)) = (parts.len() > 1, options.split_pages) {
+        println!(
+            "Splitting into {} PDFs of at most {} pages",
+            parts.len(),
+            pages
+        );
+    }
+    let several_decks = deck.counts_by_deck().len() > 1;
+    // Guides stop short of the registration marks, so the cutter does not mistake them for marks
+    let clear_zones = options.cutter.map_or(vec![], |cutter| {
+        cutter.clear_zones(layout.page_width(), layout.page_height())
+    });
+    // Each part is drawn and saved before the next is started, so only one is held in memory
+    for (part, range) in parts.iter().enumerate() {
+        let part_sheets = &printed[range.clone()];
+        let on_sheet = |sheet: &usize| on_sheets.get(sheet).map_or(&[][..], |items| items);
+        let path = match parts.len() {
+            1 => pdf_file_path.to_path_buf(),
+            _ => suffixed_path(pdf_file_path, &format!("part{}", part + 1)),
+        };
+        let cover = match part {
+            0 => cover,
+            _ => 0,
+        };
+        let mut sheets = Sheets::new(&path, &info, options, part_sheets, cover)?;
+        if let (Some(checklist), 0) = (&checklist, part) {
+            let document = &mut sheets.documents[0];
+            let pages: Vec<Layer> = (0..cover).map(|page| document.layer(page)).collect();
+            checklist.draw(&pages, &document.fonts, layout);
+            sheets.bookmark((0, 0), &["Checklist".to_string()]);
+        }
+        // The cards on each page of fronts, for the cut files
+        let mut cut_pages = BTreeMap::new();
+        for &sheet in part_sheets {
+            let lines = page_guide_lines(&layouts, &packing, sheet, options.guides, false);
+            for line in clip_lines(lines, &clear_zones) {
+                sheets.front(sheet).layer.add_line(line);
+            }
+            if let Some(back) = sheets.back(sheet) {
+                for line in page_guide_lines(&layouts, &packing, sheet, options.guides, true) {
+                    back.layer.add_line(line);
+                }
+            }
+            if let Some(cutter) = options.cutter {
+                cutter.draw_marks(&sheets.front(sheet).layer, layout);
+            }
+            let mut cards = vec![];
+            for slot in on_sheet(&sheet).iter().map(|index| &packing.slots[*index]) {
+                sheets.place_card(sheets.front_page(sheet), slot.front);
+                if let Some(back) = sheets.back_page(sheet) {
+                    sheets.place_card(back, slot.back);
+                }
+                cards.push(slot.front);
+            }
+            cut_pages.insert(sheets.front_page(sheet).1, cards);
+        }
+
+        let mut group = None;
+        for &index in part_sheets.iter().flat_map(on_sheet) {
+            let item = &items[index];
+            let card = item.card;
+            let slot = packing.slots[index];
+            let front_place = Placement {
+                rect: slot.front,
+                rotation: match slot.rotated {
+                    true => 90,
+                    false => 0,
+                },
+                size: sizes[index],
+            };
+            let back_place = Placement {
+                rect: slot.back,
+                rotation: slot.back_rotation,
+                size: sizes[index],
+            };
+            // Bookmark the page each section of each deck starts on, under the deck if there are
+            // several
+            if group != Some((item.section, card.deck.as_str())) {
+                group = Some((item.section, card.deck.as_str()));
+                let path = match several_decks {
+                    true => vec![card.deck.clone(), item.section.to_string()],
+                    false => vec![item.section.to_string()],
+                };
+                sheets.bookmark(sheets.front_page(slot.page), &path);
+            }
+            if item.face == CardFace::Divider {
+                println!(
+                    "[{}/{}] Rendering divider for {} {}",
+                    index, total, card.deck, item.section
+                );
+                draw_section_divider(&sheets.front(slot.page), deck, item, &front_place);
+                continue;
+            }
+            println!(
+                "[{}/{}] Rendering card {} [{}:{}] to pdf",
+                index, total, card.name, card.set_code, card.collector_number
+            );
+            if options.text_proxies {
+                let front_side = sheets.front(slot.page);
+                if options.deck_tags {
+                    draw_deck_tag(&front_side, &slot.front, &card.deck, layout);
+                }
+                let back_face = item.face == CardFace::Back;
+                draw_text_face(&front_side, card, back_face, &front_place, options);
+                if let (CardFace::Both, Some(back_side)) = (item.face, sheets.back(slot.page)) {
+                    if card.double_sided {
+                        draw_text_face(&back_side, card, true, &back_place, options);
+                    } else if let Some(path) = options.card_backs.back_for(item.section, card) {
+                        // Single faced cards keep a blank back unless a back image was asked for
+                        let mut back = File::open(path)
+                            .map_err(|e| format!("Could not open card back {:?}: {}", path, e))?;
+                        draw_face(
+                            &back_side,
+                            &mut back,
+                            &back_place,
+                            false,
+                            true,
+                            &card.deck,
+                            options,
+                        )?;
+                    }
+                }
+                continue;
+            }
+            let (mut front, mut back) = image_repo.get_image(card)?;
+            if !card.double_sided {
+                if let Some(path) = options.card_backs.back_for(item.section, card) {
+                    back = File::open(path)
+                        .map_err(|e| format!("Could not open card back {:?}: {}", path, e))?;
+                }
+            }
+            let front_side = sheets.front(slot.page);
+            if options.deck_tags {
+                draw_deck_tag(&front_side, &slot.front, &card.deck, layout);
+            }
+            match item.face {
+                CardFace::Both | CardFace::Front => {
+                    draw_face(
+                        &front_side,
+                        &mut front,
+                        &front_place,
+                        true,
+                        false,
+                        &card.deck,
+                        options,
+                    )?;
+                }
+                CardFace::Back => {
+                    draw_face(
+                        &front_side,
+                        &mut back,
+                        &front_place,
+                        true,
+                        true,
+                        &card.deck,
+                        options,
+                    )?;
+                }
+                // Dividers have been drawn already
+                CardFace::Divider => {}
+            }
+            if let (CardFace::Both, Some(back_side)) = (item.face, sheets.back(slot.page)) {
+                let art = card.double_sided;
+                draw_face(
+                    &back_side,
+                    &mut back,
+                    &back_place,
+                    art,
+                    true,
+                    &card.deck,
+                    options,
+                )?;
+            }
+        }
+
+        // Fronts are always in the first document
+        let fronts_path = sheets.documents[0].path.clone();
+        for document in sheets.documents {
+            document.save(options)?;
+        }
+        if options.cutter.is_some() {
+            write_cut_files(&fronts_path, layout, &cut_pages)?;
+        }
+        // Sheets are numbered within the part, as the pages of its PDF are
+        let mut part_entries = vec![];
+        for (number, sheet) in part_sheets.iter().enumerate() {
+            for &index in on_sheet(sheet) {
+                let (item, slot) = (&items[index], &packing.slots[index]);
+                part_entries.push(lock_entry(item, slot, number + 1, numbers[index]));
+            }
+        }
+        write_lock(&path, &part_entries)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::deck::{PickedCard, PickedDeck, MAINBOARD, SIDEBOARD};
+    use crate::render::{print_items, split_sheets, CardFace, PrintMode};
+    use std::collections::BTreeMap;
+
+    #[test]
+    pub fn test_print_items() {
+        let mut cards = BTreeMap::new();
+        cards.insert(
+            MAINBOARD.to_string(),
+            vec![
+                PickedCard {
+                    double_sided: true,
+                    ..PickedCard::new_for_test("Delver of Secrets", 2)
+                },
+                PickedCard::new_for_test("Ponder", 1),
+            ],
+        );
+        let deck = PickedDeck { cards };
+
+        let duplex = print_items(&deck, PrintMode::Duplex, false);
+        assert_eq!(duplex.len(), 3);
+        assert!(duplex.iter().all(|item| item.face == CardFace::Both));
+
+        let fronts: Vec<(&str, CardFace)> = print_items(&deck, PrintMode::FrontsOnly, false)
+            .iter()
+            .map(|item| (item.card.name.as_str(), item.face))
+            .collect();
+        assert_eq!(
+            fronts,
+            vec![
+                ("Delver of Secrets", CardFace::Front),
+                ("Delver of Secrets", CardFace::Back),
+                ("Delver of Secrets", CardFace::Front),
+                ("Delver of Secrets", CardFace::Back),
+                ("Ponder", CardFace::Front),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_dividers() {
+        let mut cards = BTreeMap::new();
+        let mut ponder = PickedCard::new_for_test("Ponder", 1);
+        ponder.deck = "tempo".to_string();
+        cards.insert(
+            MAINBOARD.to_string(),
+            vec![
+                PickedCard {
+                    double_sided: true,
+                    ..PickedCard::new_for_test("Delver of Secrets", 2)
+                },
+                ponder,
+            ],
+        );
+        cards.insert(
+            SIDEBOARD.to_string(),
+            vec![PickedCard::new_for_test("Negate", 1)],
+        );
+        let deck = PickedDeck { cards };
+
+        let items: Vec<(&str, &str, CardFace)> = print_items(&deck, PrintMode::Duplex, true)
+            .iter()
+            .map(|item| (item.section, item.card.name.as_str(), item.face))
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                (MAINBOARD, "Delver of Secrets", CardFace::Divider),
+                (MAINBOARD, "Delver of Secrets", CardFace::Both),
+                (MAINBOARD, "Delver of Secrets", CardFace::Both),
+                (MAINBOARD, "Ponder", CardFace::Divider),
+                (MAINBOARD, "Ponder", CardFace::Both),
+                (SIDEBOARD, "Negate", CardFace::Divider),
+                (SIDEBOARD, "Negate", CardFace::Both),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_split_sheets() {
+        let whole = split_sheets(5, 0, PrintMode::Duplex, None).unwrap();
+        assert_eq!((whole.len(), whole[0].clone()), (1, 0..5));
+        assert_eq!(
+            split_sheets(5, 0, PrintMode::Duplex, Some(4)),
+            Ok(vec![0..2, 2..4, 4..5])
+        );
+        // A three page checklist is padded to four pages in duplex
+        assert_eq!(
+            split_sheets(5, 3, PrintMode::Duplex, Some(6)),
+            Ok(vec![0..1, 1..4, 4..5])
+        );
+        assert_eq!(
+            split_sheets(5, 3, PrintMode::FrontsOnly, Some(5)),
+            Ok(vec![0..2, 2..5])
+        );
+        assert!(split_sheets(5, 3, PrintMode::Duplex, Some(5)).is_err());
+    }
+}
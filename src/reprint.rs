@@ -0,0 +1,395 @@
+use crate::deck::PickedCard;
+use crate::layout::Slot;
+use crate::mapping::CardMap;
+use crate::render::{suffixed_path, CardFace, PrintItem};
+use std::collections::BTreeSet;
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Start of every slot in a layout lock
+const SLOT_HEADER: &str = "[[slot]]\n";
+
+/// Sheets of a print run, counted from 1 and given as a list of numbers and ranges such as
+/// `3,7-9`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SheetList {
+    /// Sheets counted from 0
+    sheets: BTreeSet<usize>,
+}
+
+impl FromStr for SheetList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |reason: &str| format!("Invalid page list '{}': {}", s, reason);
+        let number = |value: &str| match value.trim().parse::<usize>() {
+            Ok(0) => Err(error("pages are counted from 1")),
+            Ok(number) => Ok(number - 1),
+            Err(e) => Err(error(&e.to_string())),
+        };
+        let mut sheets = BTreeSet::new();
+        for part in s.split(',') {
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (number(first)?, number(last)?);
+                    if last < first {
+                        return Err(error("a range ends before it starts"));
+                    }
+                    sheets.extend(first..=last);
+                }
+                None => {
+                    sheets.insert(number(part)?);
+                }
+            }
+        }
+        Ok(SheetList { sheets })
+    }
+}
+
+/// Part of a print run to print again, such as a sheet that jammed or a card that was miscut.
+/// The whole run is laid out as before and only the chosen sheets and cards are drawn, so each
+/// card lands in the same slot it had in the original run.
+#[derive(Debug, Clone)]
+pub struct Reprint {
+    sheets: Option<SheetList>,
+    /// Each card asked for, and the list that matches it
+    cards: Vec<(String, CardMap<()>)>,
+    /// The PDF of the original run
+    original: PathBuf,
+    /// Whether a run laid out differently than its layout lock is reprinted anyway
+    ignore_lock: bool,
+}
+
+impl Reprint {
+    /// A reprint of the given sheets and cards of the run written to `original`, or None if
+    /// neither was given
+    pub fn new(sheets: Option<SheetList>, cards: &[String], original: &Path) -> Option<Reprint> {
+        if sheets.is_none() && cards.is_empty() {
+            return None;
+        }
+        let cards = cards
+            .iter()
+            .map(|card| (card.clone(), CardMap::parse_list(card)))
+            .collect();
+        Some(Reprint {
+            sheets,
+            cards,
+            original: original.to_path_buf(),
+            ignore_lock: false,
+        })
+    }
+
+    pub fn with_ignore_lock(self, ignore_lock: bool) -> Reprint {
+        Reprint {
+            ignore_lock,
+            ..self
+        }
+    }
+
+    /// Where the reprint is written, next to the original run
+    pub fn output_path(&self) -> PathBuf {
+        suffixed_path(&self.original, "reprint")
+    }
+
+    fn has_card(&self, card: &PickedCard) -> bool {
+        self.cards.is_empty() || self.cards.iter().any(|(_, list)| list.contains(card))
+    }
+
+    /// Whether the item printed in a slot is reprinted. Dividers are only reprinted with whole
+    /// sheets.
+    pub fn keeps(&self, item: &PrintItem, slot: &Slot) -> bool {
+        let on_sheet = self
+            .sheets
+            .as_ref()
+            .is_none_or(|list| list.sheets.contains(&slot.page));
+        let card = match item.face {
+            CardFace::Divider => self.cards.is_empty(),
+            _ => self.has_card(item.card),
+        };
+        on_sheet && card
+    }
+
+    /// Check every sheet and card asked for is in a run of `sheet_count` sheets
+    pub fn validate(&self, items: &[PrintItem], sheet_count: usize) -> Result<(), String> {
+        if let Some(last) = self.sheets.as_ref().and_then(|list| list.sheets.last()) {
+            if *last >= sheet_count {
+                return Err(format!(
+                    "Cannot reprint page {}, the run has {} pages",
+                    last + 1,
+                    sheet_count
+                ));
+            }
+        }
+        for (name, list) in &self.cards {
+            if !items.iter().any(|item| list.contains(item.card)) {
+                return Err(format!("Cannot reprint {}, it is not in the run", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuse to reprint a run that is not laid out the same as when the original run was
+    /// written, going by the layout locks written next to it, unless the lock is ignored. A run
+    /// without a lock can only be warned about. `entries` is the lock entry of every slot of the
+    /// run.
+    pub fn check_layout(&self, entries: &[String]) -> Result<(), String> {
+        let mut locks = vec![lock_path(&self.original)];
+        if !locks[0].exists() {
+            // A split run has a lock for each part
+            locks = (1..)
+                .map(|part| lock_path(&suffixed_path(&self.original, &format!("part{}", part))))
+                .take_while(|path| path.exists())
+                .collect();
+        }
+        if locks.is_empty() {
+            println!(
+                "Warning: no layout lock for {}, so cannot check the cards land in the same slots",
+                self.original.display()
+            );
+            return Ok(());
+        }
+        let mut locked = vec![];
+        // Each part numbers its sheets from 1, so later parts carry on from the earlier ones
+        let mut offset = 0;
+        for lock in &locks {
+            match read_to_string(lock) {
+                Ok(text) => {
+                    let entries = lock_entries(&text);
+                    locked.extend(entries.iter().map(|entry| renumber(entry, offset)));
+                    offset += entries
+                        .iter()
+                        .filter_map(|e| lock_sheet(e))
+                        .max()
+                        .unwrap_or(0);
+                }
+                Err(e) => return Err(format!("Could not read layout lock {:?}: {}", lock, e)),
+            }
+        }
+        if locked == entries {
+            return Ok(());
+        }
+        let message = format!(
+            "the run is laid out differently than when {} was written, so reprinted cards may not \
+             land in the same slots",
+            locks[0].display()
+        );
+        match self.ignore_lock {
+            true => {
+                println!("Warning: {}", message);
+                Ok(())
+            }
+            false => Err(format!(
+                "Cannot reprint, {}. Use --ignore-layout-lock to reprint anyway",
+                message
+            )),
+        }
+    }
+}
+
+/// `deck.pdf` has its layout lock in `deck.layout.lock`
+pub fn lock_path(pdf_path: &Path) -> PathBuf {
+    pdf_path.with_extension("layout.lock")
+}
+
+fn quoted(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The lock entry for the item printed in a slot, numbered from 1 on its sheet, which is
+/// numbered from 1 in its PDF. Positions are the bottom left corner of the front of the card, in
+/// mm from the bottom left of the page.
+pub fn lock_entry(item: &PrintItem, slot: &Slot, sheet: usize, number: usize) -> String {
+    let card = item.card;
+    let face = match item.face {
+        CardFace::Both => "both",
+        CardFace::Front => "front",
+        CardFace::Back => "back",
+        CardFace::Divider => "divider",
+    };
+    format!(
+        "{}sheet = {}\nslot = {}\ncard = {}\nprinting = \"{}:{}\"\nface = \"{}\"\nsection = {}\n\
+         deck = {}\nposition = [{:.2}, {:.2}]\nrotated = {}\n",
+        SLOT_HEADER,
+        sheet,
+        number,
+        quoted(&card.name),
+        card.set_code.to_uppercase(),
+        card.collector_number,
+        face,
+        quoted(item.section),
+        quoted(&card.deck),
+        slot.front.x.0,
+        slot.front.y.0,
+        slot.rotated
+    )
+}
+
+/// A layout lock recording which card went in which slot of every sheet of a PDF, in the style
+/// of a lockfile, for checking a later reprint puts cards back in the same slots
+pub fn lock_text(pdf_path: &Path, entries: &[String]) -> String {
+    let name = pdf_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut text = format!(
+        "# Where every card of {} is printed. Written by {}, do not edit.\nversion = 1\n",
+        name,
+        env!("CARGO_PKG_NAME")
+    );
+    for entry in entries {
+        text.push('\n');
+        text.push_str(entry);
+    }
+    text
+}
+
+pub fn write_lock(pdf_path: &Path, entries: &[String]) -> Result<(), String> {
+    let path = lock_path(pdf_path);
+    write(&path, lock_text(pdf_path, entries))
+        .map_err(|e| format!("Could not write layout lock {:?}: {}", path, e))
+}
+
+/// The slot entries of a layout lock
+fn lock_entries(text: &str) -> Vec<String> {
+    text.split(&format!("\n{}", SLOT_HEADER))
+        .skip(1)
+        .map(|entry| format!("{}{}\n", SLOT_HEADER, entry.trim_end_matches('\n')))
+        .collect()
+}
+
+/// The sheet a lock entry is on
+fn lock_sheet(entry: &str) -> Option<usize> {
+    entry
+        .lines()
+        .find_map(|line| line.strip_prefix("sheet = "))
+        .and_then(|sheet| sheet.parse().ok())
+}
+
+/// A lock entry moved `offset` sheets further into the run
+fn renumber(entry: &str, offset: usize) -> String {
+    match lock_sheet(entry) {
+        Some(sheet) if offset > 0 => entry.replacen(
+            &format!("\nsheet = {}\n", sheet),
+            &format!("\nsheet = {}\n", sheet + offset),
+            1,
+        ),
+        _ => entry.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::deck::{PickedCard, MAINBOARD};
+    use crate::layout::{Rect, Slot};
+    use crate::render::{CardFace, PrintItem};
+    use crate::reprint::{
+        lock_entries, lock_entry, lock_sheet, lock_text, renumber, write_lock, Reprint, SheetList,
+    };
+    use printpdf::Mm;
+    use std::fs::{create_dir_all, remove_dir_all};
+    use std::path::Path;
+
+    fn slot(page: usize, x: f32) -> Slot {
+        let rect = Rect::new(Mm(x), Mm(10.0), Mm(63.0), Mm(88.0));
+        Slot {
+            page,
+            front: rect,
+            back: rect,
+            rotated: false,
+            back_rotation: 0,
+        }
+    }
+
+    #[test]
+    pub fn test_sheet_list() {
+        let sheets = "3,7-9".parse::<SheetList>().unwrap().sheets;
+        assert_eq!(sheets.into_iter().collect::<Vec<_>>(), vec![2, 6, 7, 8]);
+        assert!("0".parse::<SheetList>().is_err());
+        assert!("9-7".parse::<SheetList>().is_err());
+        assert!("3,".parse::<SheetList>().is_err());
+    }
+
+    #[test]
+    pub fn test_reprint_keeps() {
+        let (dreadmaw, elves) = (
+            PickedCard::new_for_test("Colossal Dreadmaw", 1),
+            PickedCard::new_for_test("Llanowar Elves", 1),
+        );
+        let item = |card, face| PrintItem {
+            section: MAINBOARD,
+            card,
+            face,
+        };
+        let items = [
+            item(&dreadmaw, CardFace::Divider),
+            item(&dreadmaw, CardFace::Both),
+            item(&elves, CardFace::Both),
+        ];
+        let slots = [slot(0, 10.0), slot(0, 80.0), slot(1, 10.0)];
+        let original = Path::new("deck.pdf");
+        let kept = |reprint: &Reprint| -> Vec<bool> {
+            items
+                .iter()
+                .zip(&slots)
+                .map(|(item, slot)| reprint.keeps(item, slot))
+                .collect()
+        };
+
+        let by_name = Reprint::new(None, &["llanowar elves".to_string()], original).unwrap();
+        by_name.validate(&items, 2).unwrap();
+        assert_eq!(kept(&by_name), vec![false, false, true]);
+        assert_eq!(by_name.output_path(), Path::new("deck_reprint.pdf"));
+
+        let by_page = Reprint::new(Some("1".parse().unwrap()), &[], original).unwrap();
+        assert_eq!(kept(&by_page), vec![true, true, false]);
+
+        let too_far = Reprint::new(Some("3".parse().unwrap()), &[], original).unwrap();
+        assert!(too_far.validate(&items, 2).is_err());
+        let missing = Reprint::new(None, &["Ponder".to_string()], original).unwrap();
+        assert!(missing.validate(&items, 2).is_err());
+        assert!(Reprint::new(None, &[], original).is_none());
+    }
+
+    #[test]
+    pub fn test_layout_lock() {
+        let mut ghalta = PickedCard::new_for_test("Ghalta, \"Primal\" Hunger", 1);
+        ghalta.deck = String::new();
+        let item = PrintItem {
+            section: MAINBOARD,
+            card: &ghalta,
+            face: CardFace::Both,
+        };
+        let entries = [
+            lock_entry(&item, &slot(0, 10.0), 1, 1),
+            lock_entry(&item, &slot(0, 80.0), 1, 2),
+        ];
+        assert_eq!(
+            entries[0],
+            "[[slot]]\nsheet = 1\nslot = 1\ncard = \"Ghalta, \\\"Primal\\\" Hunger\"\n\
+             printing = \"M21:176\"\nface = \"both\"\nsection = \"Mainboard\"\ndeck = \"\"\n\
+             position = [10.00, 10.00]\nrotated = false\n"
+        );
+        let text = lock_text(Path::new("out/deck.pdf"), &entries);
+        assert!(text.starts_with("# Where every card of deck.pdf is printed."));
+        assert_eq!(lock_entries(&text), entries);
+
+        // The first sheet of a second part is the fourth sheet of the run
+        assert_eq!(lock_sheet(&renumber(&entries[1], 3)), Some(4));
+        assert!(renumber(&entries[1], 3).contains("\nslot = 2\n"));
+
+        // A run laid out differently is only reprinted when the lock is ignored
+        let directory = Path::new("Test_Temp_Lock");
+        create_dir_all(directory).unwrap();
+        let original = directory.join("deck.pdf");
+        write_lock(&original, &entries).unwrap();
+        let reprint = Reprint::new(Some("1".parse().unwrap()), &[], &original).unwrap();
+        let same = reprint.check_layout(&entries);
+        let moved = reprint.check_layout(&entries[..1]);
+        let ignored = reprint.with_ignore_lock(true).check_layout(&entries[..1]);
+        remove_dir_all(directory).unwrap();
+        assert!(same.is_ok());
+        assert!(moved.unwrap_err().contains("--ignore-layout-lock"));
+        assert!(ignored.is_ok());
+    }
+}
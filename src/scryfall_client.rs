@@ -7,6 +7,39 @@ use std::io::Read;
 const PROXY_MAKER_AGENT: &str = "MyMTGApp/1.0";
 const BASE_URL: &str = "https://api.scryfall.com";
 
+/// Which of Scryfall's image versions to download
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ImageQuality {
+    /// 146x204 JPEG, for previews
+    Small,
+    /// 488x680 JPEG
+    Normal,
+    /// 672x936 JPEG, about 270 DPI at card size
+    #[default]
+    Large,
+    /// 745x1040 PNG with transparent rounded corners, about 300 DPI at card size
+    Png,
+}
+
+impl ImageQuality {
+    /// The `version` Scryfall's image endpoint takes
+    pub fn version(&self) -> &'static str {
+        match self {
+            ImageQuality::Small => "small",
+            ImageQuality::Normal => "normal",
+            ImageQuality::Large => "large",
+            ImageQuality::Png => "png",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageQuality::Png => "png",
+            _ => "jpg",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ScryfallClient {
     client: Client,
@@ -19,39 +52,81 @@ impl ScryfallClient {
         }
     }
 
-    pub fn get_image(&self, set: &str, collector_number: &str, back: bool) -> Response {
+    pub fn get_image(
+        &self,
+        set: &str,
+        collector_number: &str,
+        back: bool,
+        quality: ImageQuality,
+    ) -> Response {
         let back_str = match back {
             true => "&face=back",
             false => "",
         };
         let url = format!(
-            "{BASE_URL}/cards/{}/{}?format=image{}",
-            set, collector_number, back_str
+            "{BASE_URL}/cards/{}/{}?format=image&version={}{}",
+            set,
+            collector_number,
+            quality.version(),
+            back_str
         );
         println!("url: {}", url);
         // Scryfall requests that we are polite
         // sleep(Duration::from_millis(100));
-        let resp = self
+        self
             .client
             .get(&url)
             .header(USER_AGENT, PROXY_MAKER_AGENT) // Customize as needed
             .header(ACCEPT, "*/*")
             .send()
-            .expect("Expected get image scryfall response to work");
-        resp
+            .expect("Expected get image scryfall response to work")
     }
 
     pub fn get_card_variants(
         &self,
         name: &str,
+    ) -> Result<Vec<ScryfallSearchResultEntry>, SearchCardError> {
+        // Planes, phenomena and schemes only turn up when extras are included
+        self.search(name, false).or_else(|_| self.search(name, true))
+    }
+
+    /// The one printing with this Scryfall id, such as a token named in another card's parts
+    pub fn get_card_by_id(&self, id: &str) -> Result<ScryfallSearchResultEntry, SearchCardError> {
+        let url = format!("{BASE_URL}/cards/{}", id);
+        let mut resp = self
+            .client
+            .get(&url)
+            .header(USER_AGENT, PROXY_MAKER_AGENT)
+            .header(ACCEPT, "application/json")
+            .send()
+            .map_err(|e| SearchCardError {
+                url: url.clone(),
+                error: e.to_string(),
+                cause: "Expected card request to be sent".to_string(),
+                response: String::new(),
+            })?;
+        let mut data = String::new();
+        resp.read_to_string(&mut data).unwrap();
+        serde_json::from_str(&data).map_err(|e| SearchCardError {
+            url,
+            error: format!("{:?}", e),
+            cause: "Expected json to deserialise".to_string(),
+            response: data,
+        })
+    }
+
+    fn search(
+        &self,
+        name: &str,
+        include_extras: bool,
     ) -> Result<Vec<ScryfallSearchResultEntry>, SearchCardError> {
         let mut results = vec![];
         let mut has_more = true;
         let mut page = 1;
         while has_more {
             let url = format!(
-                "{BASE_URL}/cards/search?q=\"{}\"&page={}&unique=prints",
-                name, page
+                "{BASE_URL}/cards/search?q=\"{}\"&page={}&unique=prints&include_extras={}",
+                name, page, include_extras
             );
             let mut resp = self
                 .client
@@ -59,7 +134,12 @@ impl ScryfallClient {
                 .header(USER_AGENT, PROXY_MAKER_AGENT)
                 .header(ACCEPT, "application/json")
                 .send()
-                .expect("Expected search result to work");
+                .map_err(|e| SearchCardError {
+                    url: url.clone(),
+                    error: e.to_string(),
+                    cause: "Expected search request to be sent".to_string(),
+                    response: String::new(),
+                })?;
             let mut data = String::new();
             resp.read_to_string(&mut data).unwrap();
             let search_result: ScryfallSearchResult = serde_json::from_str(&data).map_err(|e| {
@@ -106,8 +186,6 @@ pub struct SearchCardError {
 
 #[derive(Deserialize)]
 pub struct ScryfallSearchResult {
-    pub object: String,
-    pub total_cards: u32,
     pub has_more: bool,
     pub data: Vec<ScryfallSearchResultEntry>,
 }
@@ -118,8 +196,19 @@ pub struct ScryfallSearchResultEntry {
     pub lang: String,
     pub set: String,
     pub collector_number: String,
+    /// How the card is laid out, such as `normal`, `transform` or `planar`
+    pub layout: String,
+    /// True for printings larger than a standard card, such as planes and schemes
+    pub oversized: bool,
     pub image_uris: Option<ScryfallSearchResultEntryImageUris>,
     pub card_faces: Option<Vec<ScryfallSearchResultEntryCardFace>>,
+    pub mana_cost: Option<String>,
+    pub type_line: Option<String>,
+    pub oracle_text: Option<String>,
+    pub power: Option<String>,
+    pub toughness: Option<String>,
+    pub loyalty: Option<String>,
+    pub all_parts: Option<Vec<ScryfallRelatedCard>>,
 }
 
 impl ScryfallSearchResultEntry {
@@ -146,10 +235,38 @@ pub struct ScryfallSearchResultEntryImageUris {
     pub png: String,
 }
 
+/// A card Scryfall lists as related to another, such as a token it makes
+#[derive(Deserialize, Eq, PartialEq, Clone, Debug)]
+pub struct ScryfallRelatedCard {
+    /// Scryfall id of the exact printing that is related
+    pub id: String,
+    pub component: String,
+    pub name: String,
+    pub type_line: Option<String>,
+}
+
+impl ScryfallRelatedCard {
+    /// True for tokens, emblems and meld results, which are printed alongside the cards that
+    /// make them. Emblems are listed as combo pieces, so are told apart by their type line.
+    pub fn is_extra(&self) -> bool {
+        let emblem = self
+            .type_line
+            .as_deref()
+            .is_some_and(|type_line| type_line.starts_with("Emblem"));
+        self.component == "token" || self.component == "meld_result" || emblem
+    }
+}
+
 #[derive(Deserialize, Eq, PartialEq, Clone, Debug)]
 pub struct ScryfallSearchResultEntryCardFace {
     pub name: String,
     pub image_uris: Option<ScryfallSearchResultEntryImageUris>,
+    pub mana_cost: Option<String>,
+    pub type_line: Option<String>,
+    pub oracle_text: Option<String>,
+    pub power: Option<String>,
+    pub toughness: Option<String>,
+    pub loyalty: Option<String>,
 }
 
 #[cfg(test)]
@@ -0,0 +1,285 @@
+use crate::deck::PickedDeck;
+use crate::mapping::CardMap;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Positions of the cards to print, counted from 1 in print order. Both ends are included, as
+/// with the sheets of a reprint: `10..19` is the 10th to the 19th card, and `10..=19` the same.
+/// Either end can be left open, as in `10..` or `..19`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CardRange {
+    /// Copies to keep, counted from 0
+    copies: Range<usize>,
+}
+
+impl FromStr for CardRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |reason: &str| format!("Invalid card range '{}': {}", s, reason);
+        let (start, end) = s
+            .trim()
+            .split_once("..")
+            .ok_or_else(|| error("expected <first>..<last>, such as 10..19"))?;
+        let (end, inclusive) = match end.strip_prefix('=') {
+            Some(end) => (end, true),
+            None => (end, false),
+        };
+        let position = |value: &str| -> Result<Option<usize>, String> {
+            match value.trim() {
+                "" => Ok(None),
+                value => match value.parse::<usize>() {
+                    Ok(0) => Err(error("cards are counted from 1")),
+                    Ok(position) => Ok(Some(position)),
+                    Err(e) => Err(error(&e.to_string())),
+                },
+            }
+        };
+        let start = position(start)?.unwrap_or(1) - 1;
+        let end = match position(end)? {
+            Some(end) => end,
+            None if inclusive => return Err(error("..= needs an end")),
+            None => usize::MAX,
+        };
+        if end <= start {
+            return Err(error("the range has no cards in it"));
+        }
+        Ok(CardRange { copies: start..end })
+    }
+}
+
+fn card_count(deck: &PickedDeck) -> usize {
+    deck.cards
+        .values()
+        .flatten()
+        .map(|card| card.quantity as usize)
+        .sum()
+}
+
+/// Which cards of the deck go into the print run. Each filter is applied to what the ones before
+/// it left: sections, then names, then the range, then the limit.
+#[derive(Debug, Default)]
+pub struct Selection {
+    sections: Vec<String>,
+    names: Option<CardMap<()>>,
+    range: Option<CardRange>,
+    limit: Option<usize>,
+}
+
+impl Selection {
+    pub fn new() -> Selection {
+        Selection::default()
+    }
+
+    /// Only cards in these sections, such as Sideboard
+    pub fn with_sections(mut self, sections: Vec<String>) -> Selection {
+        self.sections = sections;
+        self
+    }
+
+    /// Only cards on this list
+    pub fn with_names(mut self, names: Option<CardMap<()>>) -> Selection {
+        self.names = names;
+        self
+    }
+
+    pub fn with_range(mut self, range: Option<CardRange>) -> Selection {
+        self.range = range;
+        self
+    }
+
+    /// At most this many cards
+    pub fn with_limit(mut self, limit: Option<usize>) -> Selection {
+        self.limit = limit;
+        self
+    }
+
+    pub fn is_everything(&self) -> bool {
+        self.sections.is_empty()
+            && self.names.is_none()
+            && self.range.is_none()
+            && self.limit.is_none()
+    }
+
+    /// Drop the cards that are not selected from the deck. A range or limit that ends part way
+    /// through the copies of a card keeps only the copies inside it.
+    pub fn apply(&self, deck: &mut PickedDeck) -> Result<(), String> {
+        if self.is_everything() {
+            return Ok(());
+        }
+        if let Some(missing) = self.sections.iter().find(|section| {
+            !deck
+                .cards
+                .keys()
+                .any(|name| name.eq_ignore_ascii_case(section))
+        }) {
+            let sections: Vec<&str> = deck.cards.keys().map(String::as_str).collect();
+            return Err(format!(
+                "The deck has no {} section, only {}",
+                missing,
+                sections.join(", ")
+            ));
+        }
+        let before = card_count(deck);
+        let mut copies = self
+            .range
+            .as_ref()
+            .map_or(0..usize::MAX, |r| r.copies.clone());
+        if let Some(limit) = self.limit {
+            copies.end = copies.end.min(copies.start.saturating_add(limit));
+        }
+        // Position of the first copy of the next card that passes the section and name filters
+        let mut position = 0;
+        deck.cards.retain(|section, cards| {
+            if !self.sections.is_empty()
+                && !self
+                    .sections
+                    .iter()
+                    .any(|s| s.eq_ignore_ascii_case(section))
+            {
+                return false;
+            }
+            cards.retain_mut(|card| {
+                if self
+                    .names
+                    .as_ref()
+                    .is_some_and(|names| !names.contains(card))
+                {
+                    return false;
+                }
+                let first = position;
+                position += card.quantity as usize;
+                let kept = position
+                    .min(copies.end)
+                    .saturating_sub(first.max(copies.start));
+                card.quantity = kept as u32;
+                kept > 0
+            });
+            !cards.is_empty()
+        });
+        let after = card_count(deck);
+        if after == 0 {
+            return Err(format!(
+                "None of the {} cards of the deck are selected",
+                before
+            ));
+        }
+        println!("Selected {} of {} cards", after, before);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::deck::{PickedCard, PickedDeck, MAINBOARD, SIDEBOARD};
+    use crate::mapping::CardMap;
+    use crate::selection::{CardRange, Selection};
+    use std::collections::BTreeMap;
+
+    fn deck() -> PickedDeck {
+        let mut cards = BTreeMap::new();
+        cards.insert(
+            MAINBOARD.to_string(),
+            vec![
+                PickedCard::new_for_test("Colossal Dreadmaw", 4),
+                PickedCard::new_for_test("Llanowar Elves", 3),
+            ],
+        );
+        cards.insert(
+            SIDEBOARD.to_string(),
+            vec![PickedCard::new_for_test("Colossal Dreadmaw", 2)],
+        );
+        PickedDeck { cards }
+    }
+
+    fn quantities(deck: &PickedDeck) -> Vec<(&str, &str, u32)> {
+        deck.cards
+            .iter()
+            .flat_map(|(section, cards)| {
+                cards
+                    .iter()
+                    .map(move |card| (section.as_str(), card.name.as_str(), card.quantity))
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn test_card_range() {
+        let copies = |s: &str| s.parse::<CardRange>().unwrap().copies;
+        assert_eq!(copies("10..19"), 9..19);
+        assert_eq!(copies("10..=19"), 9..19);
+        assert_eq!(copies("..3"), 0..3);
+        assert_eq!(copies("4..4"), 3..4);
+        assert_eq!(copies("5.."), 4..usize::MAX);
+        assert!("0..3".parse::<CardRange>().is_err());
+        assert!("4..3".parse::<CardRange>().is_err());
+        assert!("4..=".parse::<CardRange>().is_err());
+        assert!("4-9".parse::<CardRange>().is_err());
+    }
+
+    #[test]
+    pub fn test_select_range_and_limit() {
+        let mut selected = deck();
+        Selection::new()
+            .with_range(Some("3..=6".parse().unwrap()))
+            .apply(&mut selected)
+            .unwrap();
+        assert_eq!(
+            quantities(&selected),
+            vec![
+                (MAINBOARD, "Colossal Dreadmaw", 2),
+                (MAINBOARD, "Llanowar Elves", 2)
+            ]
+        );
+
+        let mut selected = deck();
+        Selection::new()
+            .with_range(Some("6..".parse().unwrap()))
+            .with_limit(Some(3))
+            .apply(&mut selected)
+            .unwrap();
+        assert_eq!(
+            quantities(&selected),
+            vec![
+                (MAINBOARD, "Llanowar Elves", 2),
+                (SIDEBOARD, "Colossal Dreadmaw", 1)
+            ]
+        );
+
+        let mut selected = deck();
+        assert!(Selection::new()
+            .with_range(Some("20..".parse().unwrap()))
+            .apply(&mut selected)
+            .is_err());
+    }
+
+    #[test]
+    pub fn test_select_sections_and_names() {
+        let mut selected = deck();
+        Selection::new()
+            .with_sections(vec!["sideboard".to_string()])
+            .apply(&mut selected)
+            .unwrap();
+        assert_eq!(
+            quantities(&selected),
+            vec![(SIDEBOARD, "Colossal Dreadmaw", 2)]
+        );
+
+        let mut selected = deck();
+        Selection::new()
+            .with_names(Some(CardMap::parse_list("# Ramp\nllanowar elves\n")))
+            .with_limit(Some(1))
+            .apply(&mut selected)
+            .unwrap();
+        assert_eq!(
+            quantities(&selected),
+            vec![(MAINBOARD, "Llanowar Elves", 1)]
+        );
+
+        let mut selected = deck();
+        assert!(Selection::new()
+            .with_sections(vec!["Commander".to_string()])
+            .apply(&mut selected)
+            .is_err());
+    }
+}
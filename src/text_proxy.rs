@@ -0,0 +1,338 @@
+use crate::deck::{CardText, PickedCard};
+use crate::font_metrics::text_width;
+use crate::layout::{card_to_page, Rect};
+use crate::pdf_calc::{CARD_HEIGHT, CARD_WIDTH};
+use crate::pdf_writer::{Font, Layer, PdfWriter};
+use printpdf::{BuiltinFont, Line, Mm, Point, Pt, TextMatrix};
+
+/// Font for the rules text and everything else that is not a heading
+pub const TEXT_FONT: BuiltinFont = BuiltinFont::Helvetica;
+/// Font for the card name and power/toughness
+pub const BOLD_FONT: BuiltinFont = BuiltinFont::HelveticaBold;
+
+/// Distance from the edge of the card to the frame
+const FRAME_INSET: Mm = Mm(2.5);
+/// Distance from the edge of the card to the text
+const TEXT_INSET: Mm = Mm(4.5);
+/// Largest and smallest size of the rules text, in points. The text is shrunk until it fits.
+const ORACLE_SIZES: (f32, f32) = (8.0, 4.0);
+/// Top and bottom of the rules text box, from the bottom of the card
+const ORACLE_BOX: (Mm, Mm) = (Mm(68.5), Mm(12.0));
+
+/// The built in fonts of one PDF
+#[derive(Debug, Clone)]
+pub struct Fonts {
+    pub regular: Font,
+    pub bold: Font,
+}
+
+impl Fonts {
+    pub fn add(doc: &mut PdfWriter) -> Fonts {
+        Fonts {
+            regular: doc.add_builtin_font(TEXT_FONT),
+            bold: doc.add_builtin_font(BOLD_FONT),
+        }
+    }
+}
+
+/// The text of the front face, or of the back face of a double sided card. Cards with no rules
+/// text, such as custom cards, get one with just their name.
+pub fn face_text(card: &PickedCard, back: bool) -> CardText {
+    let index = back as usize;
+    if let Some(face) = card.faces.get(index) {
+        return face.clone();
+    }
+    let mut names = card.name.split(" // ");
+    let name = match back {
+        true => names.nth(1),
+        false => names.next(),
+    };
+    CardText {
+        name: name.unwrap_or(&card.name).to_string(),
+        ..Default::default()
+    }
+}
+
+/// Draw a card as plain text in `rect`, turned clockwise by `rotation` degrees: a frame, the
+/// name and mana cost, the type line, the rules text, power/toughness or loyalty and the set
+pub fn draw_text_card(
+    layer: &Layer,
+    fonts: &Fonts,
+    card: &PickedCard,
+    text: &CardText,
+    rect: &Rect,
+    rotation: u16,
+) {
+    let page = |point: (Mm, Mm)| {
+        let (x, y) = card_to_page(rect, rotation, point);
+        Point::new(x, y)
+    };
+    let angle = (360 - rotation % 360) as f32 % 360.0;
+    let write = |value: &str, font: BuiltinFont, size: f32, (u, v): (Mm, Mm)| {
+        if value.is_empty() {
+            return;
+        }
+        let (x, y) = card_to_page(rect, rotation, (u, v));
+        let font_ref = match font {
+            BOLD_FONT => &fonts.bold,
+            _ => &fonts.regular,
+        };
+        layer.begin_text_section();
+        layer.set_font(font_ref, size);
+        layer.set_text_matrix(TextMatrix::TranslateRotate(x.into(), y.into(), angle));
+        layer.write_text(winansi(value));
+        layer.end_text_section();
+    };
+    let rule = |v: Mm| {
+        layer.add_line(Line {
+            points: vec![
+                (page((FRAME_INSET, v)), false),
+                (page((CARD_WIDTH - FRAME_INSET, v)), false),
+            ],
+            is_closed: false,
+        });
+    };
+
+    let (left, right) = (FRAME_INSET, CARD_WIDTH - FRAME_INSET);
+    let (bottom, top) = (FRAME_INSET, CARD_HEIGHT - FRAME_INSET);
+    layer.set_outline_thickness(0.5);
+    layer.add_line(Line {
+        points: vec![
+            (page((left, bottom)), false),
+            (page((right, bottom)), false),
+            (page((right, top)), false),
+            (page((left, top)), false),
+        ],
+        is_closed: true,
+    });
+
+    // Name on the left, mana cost on the right, with the name shrunk if the two would overlap
+    let width = CARD_WIDTH - TEXT_INSET * 2.0;
+    let cost_size = 8.0;
+    let cost_width = text_width(&text.mana_cost, TEXT_FONT, cost_size);
+    let name_room = width.0 - cost_width.0 - 2.0;
+    let name_size = fit_size(&text.name, BOLD_FONT, 9.0, Mm(name_room));
+    let name_line = Mm(79.5);
+    write(&text.name, BOLD_FONT, name_size, (TEXT_INSET, name_line));
+    write(
+        &text.mana_cost,
+        TEXT_FONT,
+        cost_size,
+        (CARD_WIDTH - TEXT_INSET - cost_width, name_line),
+    );
+    rule(Mm(77.0));
+
+    let type_size = fit_size(&text.type_line, TEXT_FONT, 7.5, width);
+    write(
+        &text.type_line,
+        TEXT_FONT,
+        type_size,
+        (TEXT_INSET, Mm(72.5)),
+    );
+    rule(Mm(70.5));
+
+    let (size, lines) = fit_oracle_text(&text.oracle_text, width);
+    let leading = Mm::from(Pt(size * 1.2));
+    let mut baseline = ORACLE_BOX.0 - Mm::from(Pt(size));
+    for line in lines {
+        match line {
+            Some(line) => {
+                write(&line, TEXT_FONT, size, (TEXT_INSET, baseline));
+                baseline -= leading;
+            }
+            // Half a line between paragraphs
+            None => baseline -= leading * 0.5,
+        }
+    }
+    rule(Mm(10.0));
+
+    let set = format!("{} {}", card.set_code.to_uppercase(), card.collector_number);
+    write(&set, TEXT_FONT, 6.0, (TEXT_INSET, Mm(5.5)));
+    let corner = match (&text.power, &text.toughness, &text.loyalty) {
+        (Some(power), Some(toughness), _) => Some(format!("{}/{}", power, toughness)),
+        (_, _, Some(loyalty)) => Some(loyalty.clone()),
+        _ => None,
+    };
+    if let Some(corner) = corner {
+        let corner_width = text_width(&corner, BOLD_FONT, 9.0);
+        write(
+            &corner,
+            BOLD_FONT,
+            9.0,
+            (CARD_WIDTH - TEXT_INSET - corner_width, Mm(5.5)),
+        );
+    }
+}
+
+/// Draw a divider card in `rect`, turned clockwise by `rotation` degrees: a frame with the
+/// first line large and bold across the middle, and any other lines smaller below it
+pub fn draw_divider(layer: &Layer, fonts: &Fonts, lines: &[String], rect: &Rect, rotation: u16) {
+    let page = |point: (Mm, Mm)| {
+        let (x, y) = card_to_page(rect, rotation, point);
+        Point::new(x, y)
+    };
+    let angle = (360 - rotation % 360) as f32 % 360.0;
+    let (left, right) = (FRAME_INSET, CARD_WIDTH - FRAME_INSET);
+    let (bottom, top) = (FRAME_INSET, CARD_HEIGHT - FRAME_INSET);
+    layer.set_outline_thickness(1.5);
+    layer.add_line(Line {
+        points: vec![
+            (page((left, bottom)), false),
+            (page((right, bottom)), false),
+            (page((right, top)), false),
+            (page((left, top)), false),
+        ],
+        is_closed: true,
+    });
+
+    let width = CARD_WIDTH - TEXT_INSET * 2.0;
+    let mut baseline = CARD_HEIGHT / 2.0;
+    for (index, line) in lines.iter().enumerate() {
+        let (font, font_ref, size) = match index {
+            0 => (
+                BOLD_FONT,
+                &fonts.bold,
+                fit_size(line, BOLD_FONT, 18.0, width),
+            ),
+            _ => (
+                TEXT_FONT,
+                &fonts.regular,
+                fit_size(line, TEXT_FONT, 9.0, width),
+            ),
+        };
+        // Centred across the card
+        let u = (CARD_WIDTH - text_width(line, font, size)) / 2.0;
+        let (x, y) = card_to_page(rect, rotation, (u, baseline));
+        layer.begin_text_section();
+        layer.set_font(font_ref, size);
+        layer.set_text_matrix(TextMatrix::TranslateRotate(x.into(), y.into(), angle));
+        layer.write_text(winansi(line));
+        layer.end_text_section();
+        baseline -= Mm::from(Pt(size * 1.4));
+    }
+}
+
+/// The largest size up to `size` at which a single line of text fits in `width`
+fn fit_size(text: &str, font: BuiltinFont, size: f32, width: Mm) -> f32 {
+    let natural = text_width(text, font, size);
+    match natural.0 > width.0 {
+        true => (size * width.0 / natural.0).max(ORACLE_SIZES.1),
+        false => size,
+    }
+}
+
+/// Wrap the rules text at the largest size that fits the text box. Paragraph breaks are None.
+fn fit_oracle_text(text: &str, width: Mm) -> (f32, Vec<Option<String>>) {
+    let height = ORACLE_BOX.0 - ORACLE_BOX.1;
+    let mut size = ORACLE_SIZES.0;
+    loop {
+        let lines: Vec<Option<String>> = text
+            .split('\n')
+            .enumerate()
+            .flat_map(|(i, paragraph)| {
+                let gap = (i > 0).then_some(None);
+                gap.into_iter().chain(
+                    wrap(paragraph, TEXT_FONT, size, width)
+                        .into_iter()
+                        .map(Some),
+                )
+            })
+            .collect();
+        let leading = Mm::from(Pt(size * 1.2)).0;
+        let used: f32 = lines
+            .iter()
+            .map(|line| match line {
+                Some(_) => leading,
+                None => leading * 0.5,
+            })
+            .sum();
+        if used <= height.0 || size <= ORACLE_SIZES.1 {
+            return (size, lines);
+        }
+        size -= 0.5;
+    }
+}
+
+/// Break text into lines no wider than `width`. A word too long for a line gets a line of its
+/// own.
+pub fn wrap(text: &str, font: BuiltinFont, size: f32, width: Mm) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = match line.is_empty() {
+            true => word.to_string(),
+            false => format!("{} {}", line, word),
+        };
+        if !line.is_empty() && text_width(&candidate, font, size).0 > width.0 {
+            lines.push(line);
+            line = word.to_string();
+        } else {
+            line = candidate;
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// The built in fonts only cover Windows-1252, so swap the few characters Scryfall uses that
+/// are outside it
+pub fn winansi(text: &str) -> String {
+    text.replace('\u{2212}', "-").replace('\u{221e}', "inf")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::deck::{CardText, PickedCard};
+    use crate::text_proxy::{face_text, fit_oracle_text, wrap, TEXT_FONT};
+    use printpdf::Mm;
+
+    #[test]
+    pub fn test_wrap() {
+        let lines = wrap(
+            "Flying, vigilance, deathtouch, lifelink",
+            TEXT_FONT,
+            8.0,
+            Mm(30.0),
+        );
+        assert_eq!(lines, vec!["Flying, vigilance,", "deathtouch, lifelink"]);
+        assert_eq!(wrap("", TEXT_FONT, 8.0, Mm(30.0)), Vec::<String>::new());
+        // A word wider than the line is not split
+        assert_eq!(wrap("Abcdefghijklmnop", TEXT_FONT, 8.0, Mm(5.0)).len(), 1);
+    }
+
+    #[test]
+    pub fn test_long_text_shrinks() {
+        let (size, lines) = fit_oracle_text("Trample\nHaste", Mm(54.0));
+        assert_eq!(size, 8.0);
+        assert_eq!(
+            lines,
+            vec![Some("Trample".to_string()), None, Some("Haste".to_string())]
+        );
+        let long = "Draw a card, then discard a card. ".repeat(30);
+        let (size, _) = fit_oracle_text(&long, Mm(54.0));
+        assert!(size < 8.0);
+    }
+
+    #[test]
+    pub fn test_face_text() {
+        let card = PickedCard {
+            quantity: 1,
+            name: "Delver of Secrets // Insectile Aberration".to_string(),
+            double_sided: true,
+            ..Default::default()
+        };
+        assert_eq!(face_text(&card, true).name, "Insectile Aberration");
+        let card = PickedCard {
+            faces: vec![CardText {
+                name: "Colossal Dreadmaw".to_string(),
+                power: Some("6".to_string()),
+                ..Default::default()
+            }],
+            ..card
+        };
+        assert_eq!(face_text(&card, false).power, Some("6".to_string()));
+        assert_eq!(face_text(&card, true).name, "Insectile Aberration");
+    }
+}
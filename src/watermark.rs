@@ -0,0 +1,131 @@
+use crate::font_metrics::text_width;
+use crate::layout::{card_to_page, Rect};
+use crate::pdf_calc::{CARD_HEIGHT, CARD_WIDTH};
+use crate::pdf_writer::{Font, Layer};
+use crate::text_proxy::BOLD_FONT;
+use printpdf::{BuiltinFont, Color, Mm, Pt, Rgb, TextMatrix};
+use std::str::FromStr;
+
+/// Font watermarks are set in
+const WATERMARK_FONT: BuiltinFont = BOLD_FONT;
+/// Placeholder in the watermark text that is replaced by the deck name
+pub const DECK_PLACEHOLDER: &str = "{deck}";
+
+/// Where on the card the watermark goes
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum WatermarkPosition {
+    /// Across the middle of the card
+    #[default]
+    Centre,
+    /// Just below the name line
+    Top,
+    /// Across the bottom of the text box
+    Bottom,
+    /// Corner to corner, from bottom left to top right
+    Diagonal,
+}
+
+/// An sRGB colour written as a hex triplet, with or without a leading #
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Colour(pub u8, pub u8, pub u8);
+
+impl FromStr for Colour {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().trim_start_matches('#');
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(format!("Expected a colour as rrggbb, got '{}'", s));
+        }
+        let channel = |i: usize| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| format!("Expected a colour as rrggbb, got '{}'", s))
+        };
+        Ok(Colour(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl From<Colour> for Color {
+    fn from(colour: Colour) -> Self {
+        Color::Rgb(Rgb::new(
+            colour.0 as f32 / 255.0,
+            colour.1 as f32 / 255.0,
+            colour.2 as f32 / 255.0,
+            None,
+        ))
+    }
+}
+
+/// Text stamped over the front of every card, to mark the cards as proxies
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watermark {
+    pub text: String,
+    pub position: WatermarkPosition,
+    /// Font size in points
+    pub size: f32,
+    /// 0 for invisible to 1 for solid
+    pub opacity: f32,
+    pub colour: Colour,
+    /// Whether the back faces of double sided cards are stamped too
+    pub backs: bool,
+}
+
+impl Watermark {
+    /// The watermark with the deck name filled in
+    pub fn for_deck(&self, deck_name: &str) -> Watermark {
+        Watermark {
+            text: self.text.replace(DECK_PLACEHOLDER, deck_name),
+            ..self.clone()
+        }
+    }
+
+    /// Draw the watermark over the card in `rect`, which is turned clockwise by `rotation`
+    /// degrees on the page
+    pub fn draw(&self, layer: &Layer, font: &Font, rect: &Rect, rotation: u16) {
+        // Lay the text out on the upright card, then turn it with the card
+        let (centre, angle) = match self.position {
+            WatermarkPosition::Centre => ((CARD_WIDTH / 2.0, CARD_HEIGHT / 2.0), 0.0),
+            WatermarkPosition::Top => ((CARD_WIDTH / 2.0, CARD_HEIGHT * 0.84), 0.0),
+            WatermarkPosition::Bottom => ((CARD_WIDTH / 2.0, CARD_HEIGHT * 0.1), 0.0),
+            WatermarkPosition::Diagonal => (
+                (CARD_WIDTH / 2.0, CARD_HEIGHT / 2.0),
+                CARD_HEIGHT.0.atan2(CARD_WIDTH.0).to_degrees(),
+            ),
+        };
+        let half_width = text_width(&self.text, WATERMARK_FONT, self.size).0 / 2.0;
+        // Cap height of Helvetica is about 0.72em, so drop the baseline by half of that
+        let drop = Mm::from(Pt(self.size * 0.36)).0;
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let start = (
+            Mm(centre.0 .0 - half_width * cos + drop * sin),
+            Mm(centre.1 .0 - half_width * sin - drop * cos),
+        );
+        let (x, y) = card_to_page(rect, rotation, start);
+        let page_angle = (angle - rotation as f32).rem_euclid(360.0);
+
+        layer.save_graphics_state();
+        if self.opacity < 1.0 {
+            layer.set_opacity(self.opacity);
+        }
+        layer.set_fill_color(self.colour.into());
+        layer.begin_text_section();
+        layer.set_font(font, self.size);
+        layer.set_text_matrix(TextMatrix::TranslateRotate(x.into(), y.into(), page_angle));
+        layer.write_text(self.text.clone());
+        layer.end_text_section();
+        layer.restore_graphics_state();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::watermark::Colour;
+
+    #[test]
+    pub fn test_parse_colour() {
+        assert_eq!("#ff8000".parse::<Colour>(), Ok(Colour(255, 128, 0)));
+        assert_eq!("00FF00".parse::<Colour>(), Ok(Colour(0, 255, 0)));
+        assert!("red".parse::<Colour>().is_err());
+        assert!("#ff80".parse::<Colour>().is_err());
+    }
+}